@@ -0,0 +1,318 @@
+use crate::{
+    auth::Identity,
+    changes::CommitChange,
+    error::GitDataStoreError,
+    metadata::{is_reserved_path, METADATA_DIR},
+    path_glob::PathGlobs,
+    validation::{Document, Validator},
+    GitData, GitDataStore, GitEntry,
+};
+use actix_web::{dev::Payload, web, FromRequest, HttpRequest};
+use futures::future::{ready, Ready};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::{fmt, fs, path::Path, sync::Arc};
+
+/// Rule `identities` entry matching every request, authenticated or not.
+pub const ANY_IDENTITY: &str = "*";
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Permission {
+    Read,
+    Write,
+    Delete,
+}
+
+impl fmt::Display for Permission {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Permission::Read => "read",
+            Permission::Write => "write",
+            Permission::Delete => "delete",
+        })
+    }
+}
+
+/// Grants `permissions` on the documents matching `paths` to `identities`, by login, and to
+/// the members of `groups`.
+#[derive(Clone, Debug, Deserialize)]
+pub struct AclRule {
+    #[serde(default)]
+    pub identities: Vec<String>,
+    #[serde(default)]
+    pub groups: Vec<String>,
+    pub paths: Vec<String>,
+    pub permissions: Vec<Permission>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AclConfig {
+    rules: Vec<AclRule>,
+}
+
+/// Access control list of the documents, loaded from JSON:
+///
+/// ```json
+/// {
+///   "rules": [
+///     { "groups": ["team-a"], "paths": ["team-a/**"], "permissions": ["read", "write", "delete"] },
+///     { "identities": ["*"], "paths": ["public/**"], "permissions": ["read"] }
+///   ]
+/// }
+/// ```
+///
+/// Everything not granted by a rule is denied. A glob ending with `/**` also matches the
+/// directory itself, so that it can be listed. Metadata is governed by the rules of its
+/// document.
+#[derive(Debug)]
+pub struct Acl {
+    rules: Vec<(AclRule, PathGlobs)>,
+}
+
+impl Acl {
+    pub fn new(rules: Vec<AclRule>) -> Result<Self, GitDataStoreError> {
+        let rules = rules
+            .into_iter()
+            .map(|rule| {
+                if rule.paths.is_empty() {
+                    return Err(GitDataStoreError::InvalidConfig {
+                        path: "acl".to_string(),
+                        error: "rules must have paths, use `**` for every path".to_string(),
+                    });
+                }
+                let mut globs = rule.paths.clone();
+                globs.extend(
+                    rule.paths
+                        .iter()
+                        .filter_map(|glob| glob.strip_suffix("/**"))
+                        .map(str::to_string),
+                );
+                let globs = PathGlobs::new(&globs)?;
+                Ok((rule, globs))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Acl { rules })
+    }
+
+    pub fn parse(content: &[u8]) -> Result<Self, String> {
+        let config: AclConfig = serde_json::from_slice(content).map_err(|e| e.to_string())?;
+        Acl::new(config.rules).map_err(|e| e.to_string())
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, GitDataStoreError> {
+        let invalid_config = |error: String| GitDataStoreError::InvalidConfig {
+            path: path.as_ref().to_string_lossy().to_string(),
+            error,
+        };
+        let content = fs::read(path.as_ref()).map_err(|e| invalid_config(e.to_string()))?;
+        Acl::parse(&content).map_err(invalid_config)
+    }
+
+    pub fn allows(&self, identity: Option<&Identity>, path: &str, permission: Permission) -> bool {
+        let path = document_path(path);
+        self.rules.iter().any(|(rule, globs)| {
+            rule.permissions.contains(&permission)
+                && applies_to(rule, identity)
+                && globs.is_match(path)
+        })
+    }
+}
+
+fn applies_to(rule: &AclRule, identity: Option<&Identity>) -> bool {
+    if rule.identities.iter().any(|login| login == ANY_IDENTITY) {
+        return true;
+    }
+    match identity {
+        Some(identity) => {
+            rule.identities.contains(&identity.login)
+                || rule
+                    .groups
+                    .iter()
+                    .any(|group| identity.groups.contains(group))
+        }
+        None => false,
+    }
+}
+
+/// Path of the document whose rules apply to `path`.
+fn document_path(path: &str) -> &str {
+    let path = path.trim_matches('/');
+    if is_reserved_path(path) {
+        path[METADATA_DIR.len()..].trim_start_matches('/')
+    } else {
+        path
+    }
+}
+
+/// Where the access control list is read from.
+#[derive(Debug)]
+pub enum AccessControl {
+    /// Loaded once, from a file outside of the repository.
+    Static(Arc<Acl>),
+    /// Read from a document of the repository at the head of the primary branch, so that
+    /// changes to the rules are versioned like any other document. Writing the document is
+    /// itself subject to the rules. Until the document exists, everything is denied.
+    InRepo {
+        path: String,
+        /// Rules parsed at the commit they were last read at.
+        cache: Mutex<Option<(String, Arc<Acl>)>>,
+    },
+}
+
+impl AccessControl {
+    pub fn in_repo(path: &str) -> Self {
+        AccessControl::InRepo {
+            path: path.trim_matches('/').to_string(),
+            cache: Mutex::new(None),
+        }
+    }
+
+    /// Current rules.
+    pub fn acl(&self, store: &GitDataStore) -> Result<Arc<Acl>, GitDataStoreError> {
+        match self {
+            AccessControl::Static(acl) => Ok(acl.clone()),
+            AccessControl::InRepo { path, cache } => {
+                let head = store.head()?.commit_id;
+                let mut cache = cache.lock();
+                if let Some((commit_id, acl)) = cache.as_ref() {
+                    if *commit_id == head {
+                        return Ok(acl.clone());
+                    }
+                }
+                let acl = match store.read(&head, path)? {
+                    Some(GitEntry {
+                        data: GitData::File { data },
+                        ..
+                    }) => Acl::parse(data.as_bytes()).map_err(|error| {
+                        GitDataStoreError::InvalidConfig {
+                            path: path.clone(),
+                            error,
+                        }
+                    })?,
+                    _ => Acl::new(Vec::new())?,
+                };
+                let acl = Arc::new(acl);
+                *cache = Some((head, acl.clone()));
+                Ok(acl)
+            }
+        }
+    }
+}
+
+/// Rejects commits leaving an invalid access control list at `path`, which would deny
+/// every request.
+pub struct AclValidator {
+    path: String,
+}
+
+impl AclValidator {
+    pub fn new(path: &str) -> Self {
+        AclValidator {
+            path: path.trim_matches('/').to_string(),
+        }
+    }
+}
+
+impl Validator for AclValidator {
+    fn name(&self) -> &str {
+        "acl"
+    }
+
+    fn validate(&self, document: &Document<'_>) -> Result<(), String> {
+        match document.content {
+            Some(content) if document.path == self.path => Acl::parse(content).map(|_| ()),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Access of the identity of a request to the documents. Allows everything when the server
+/// has no access control.
+#[derive(Clone)]
+pub struct Access {
+    acl: Option<Arc<Acl>>,
+    identity: Option<Identity>,
+}
+
+impl Access {
+    pub fn new(acl: Option<Arc<Acl>>, identity: Option<Identity>) -> Self {
+        Access { acl, identity }
+    }
+
+    pub fn allows(&self, path: &str, permission: Permission) -> bool {
+        match &self.acl {
+            Some(acl) => acl.allows(self.identity.as_ref(), path, permission),
+            None => true,
+        }
+    }
+
+    pub fn check(&self, path: &str, permission: Permission) -> Result<(), GitDataStoreError> {
+        if self.allows(path, permission) {
+            Ok(())
+        } else {
+            Err(GitDataStoreError::Forbidden {
+                path: path.to_string(),
+                permission,
+            })
+        }
+    }
+
+    /// Removes the entries of a directory which cannot be read.
+    pub fn filter_entry(&self, path: &str, mut entry: GitEntry) -> GitEntry {
+        if let GitData::Dir { entries } = &mut entry.data {
+            let dir = path.trim_matches('/');
+            entries.retain(|dir_entry| {
+                let name = dir_entry
+                    .name
+                    .clone()
+                    .unwrap_or_else(|| String::from_utf8_lossy(&dir_entry.name_bytes).to_string());
+                let entry_path = if dir.is_empty() {
+                    name
+                } else {
+                    format!("{}/{}", dir, name)
+                };
+                self.allows(&entry_path, Permission::Read)
+            });
+        }
+        entry
+    }
+
+    /// Restricts `commit` to the paths which can be read, `None` if none can.
+    pub fn filter_commit(&self, mut commit: CommitChange) -> Option<CommitChange> {
+        commit
+            .paths
+            .retain(|change| self.allows(&change.path, Permission::Read));
+        if commit.paths.is_empty() && self.acl.is_some() {
+            None
+        } else {
+            Some(commit)
+        }
+    }
+
+    pub fn is_restricted(&self) -> bool {
+        self.acl.is_some()
+    }
+
+    fn from_http_request(req: &HttpRequest) -> Result<Self, GitDataStoreError> {
+        let identity = req.extensions().get::<Identity>().cloned();
+        let access_control = match req.app_data::<web::Data<Arc<AccessControl>>>() {
+            Some(access_control) => access_control,
+            None => return Ok(Access::new(None, identity)),
+        };
+        let store = req
+            .app_data::<web::Data<Arc<GitDataStore>>>()
+            .expect("access control requires the data store");
+        Ok(Access::new(Some(access_control.acl(store)?), identity))
+    }
+}
+
+impl FromRequest for Access {
+    type Error = GitDataStoreError;
+    type Future = Ready<Result<Self, Self::Error>>;
+    type Config = ();
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(Access::from_http_request(req))
+    }
+}
@@ -0,0 +1,307 @@
+use crate::{error::GitDataStoreError, Signature};
+use actix_web::http::{header::AUTHORIZATION, HeaderMap};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use parking_lot::Mutex;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::{collections::HashMap, fmt, fs, net::IpAddr, path::Path};
+
+/// bcrypt is slow on purpose and runs on the workers serving the requests, so verified
+/// basic credentials are remembered. The cache is cleared when it holds this many entries.
+const MAX_VERIFIED_CREDENTIALS: usize = 1024;
+
+const REDACTED: &str = "<redacted>";
+
+/// Authenticated user of the HTTP API, whose commits are authored with [`Identity::signature`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Identity {
+    pub login: String,
+    pub name: String,
+    pub email: String,
+    pub groups: Vec<String>,
+}
+
+impl Identity {
+    pub fn signature(&self) -> Signature {
+        Signature {
+            name: self.name.clone(),
+            email: self.email.clone(),
+        }
+    }
+}
+
+/// How HTTP requests are authenticated, loaded from a JSON file:
+///
+/// ```json
+/// {
+///   "tokens": { "s3cr3t-token": "ci" },
+///   "htpasswd": "users.htpasswd",
+///   "proxy": { "trusted": ["127.0.0.1"], "user_header": "X-Forwarded-User" },
+///   "users": { "ci": { "name": "CI", "email": "ci@example.com", "groups": ["ops"] } }
+/// }
+/// ```
+///
+/// Paths are relative to the directory of the file.
+#[derive(Clone, Default, Deserialize)]
+pub struct AuthConfig {
+    /// Bearer tokens and the login they authenticate.
+    #[serde(default)]
+    pub tokens: HashMap<String, String>,
+    /// File of `login:hash` lines checked for HTTP basic authentication. Only bcrypt hashes
+    /// are supported, as created by `htpasswd -B`.
+    #[serde(default)]
+    pub htpasswd: Option<String>,
+    #[serde(default)]
+    pub proxy: Option<ProxyConfig>,
+    /// Names, emails and groups of the logins. Logins without an entry are used as name
+    /// and email.
+    #[serde(default)]
+    pub users: HashMap<String, User>,
+    /// Lets unauthenticated requests read, writes always require an identity.
+    #[serde(default)]
+    pub anonymous_reads: bool,
+}
+
+/// Tokens are redacted, only the logins they authenticate are shown.
+impl fmt::Debug for AuthConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AuthConfig")
+            .field(
+                "tokens",
+                &self
+                    .tokens
+                    .values()
+                    .map(|login| (REDACTED, login))
+                    .collect::<Vec<_>>(),
+            )
+            .field("htpasswd", &self.htpasswd)
+            .field("proxy", &self.proxy)
+            .field("users", &self.users)
+            .field("anonymous_reads", &self.anonymous_reads)
+            .finish()
+    }
+}
+
+/// Authentication done by a reverse proxy passing the identity in headers. The headers are
+/// only trusted for requests coming from the `trusted` addresses.
+#[derive(Clone, Debug, Deserialize)]
+pub struct ProxyConfig {
+    pub trusted: Vec<IpAddr>,
+    #[serde(default = "default_user_header")]
+    pub user_header: String,
+    #[serde(default = "default_email_header")]
+    pub email_header: String,
+    /// Comma separated groups, added to the groups of the user.
+    #[serde(default = "default_groups_header")]
+    pub groups_header: String,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct User {
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub email: Option<String>,
+    #[serde(default)]
+    pub groups: Vec<String>,
+}
+
+fn default_user_header() -> String {
+    "X-Forwarded-User".to_string()
+}
+
+fn default_email_header() -> String {
+    "X-Forwarded-Email".to_string()
+}
+
+fn default_groups_header() -> String {
+    "X-Forwarded-Groups".to_string()
+}
+
+/// Authenticates HTTP requests with bearer tokens, HTTP basic authentication or trusted
+/// proxy headers.
+#[derive(Default)]
+pub struct Authenticator {
+    config: AuthConfig,
+    /// Password hashes by login, read from `config.htpasswd`.
+    passwords: HashMap<String, String>,
+    /// Logins by the SHA-256 of the basic credentials already verified with bcrypt.
+    verified: Mutex<HashMap<[u8; 32], String>>,
+}
+
+/// Password hashes are redacted, only the logins are shown.
+impl fmt::Debug for Authenticator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut logins = self.passwords.keys().collect::<Vec<_>>();
+        logins.sort();
+        f.debug_struct("Authenticator")
+            .field("config", &self.config)
+            .field("passwords", &logins)
+            .finish()
+    }
+}
+
+impl Authenticator {
+    pub fn new(config: AuthConfig, htpasswd: &str) -> Result<Self, String> {
+        let mut passwords = HashMap::new();
+        for line in htpasswd.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (login, hash) = line
+                .split_once(':')
+                .ok_or_else(|| format!("invalid htpasswd line for {}", line))?;
+            if !hash.starts_with("$2") {
+                return Err(format!(
+                    "unsupported htpasswd hash for {}, only bcrypt is supported",
+                    login
+                ));
+            }
+            passwords.insert(login.to_string(), hash.to_string());
+        }
+        Ok(Authenticator {
+            config,
+            passwords,
+            verified: Mutex::new(HashMap::new()),
+        })
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, GitDataStoreError> {
+        let path = path.as_ref();
+        let invalid_config = |error: String| GitDataStoreError::InvalidConfig {
+            path: path.to_string_lossy().to_string(),
+            error,
+        };
+        let content = fs::read(path).map_err(|e| invalid_config(e.to_string()))?;
+        let config: AuthConfig =
+            serde_json::from_slice(&content).map_err(|e| invalid_config(e.to_string()))?;
+
+        let htpasswd = match &config.htpasswd {
+            Some(htpasswd) => {
+                let htpasswd_path = path
+                    .parent()
+                    .unwrap_or_else(|| Path::new(""))
+                    .join(htpasswd);
+                fs::read_to_string(&htpasswd_path).map_err(|e| {
+                    GitDataStoreError::InvalidConfig {
+                        path: htpasswd_path.to_string_lossy().to_string(),
+                        error: e.to_string(),
+                    }
+                })?
+            }
+            None => String::new(),
+        };
+        Authenticator::new(config, &htpasswd).map_err(invalid_config)
+    }
+
+    pub fn anonymous_reads(&self) -> bool {
+        self.config.anonymous_reads
+    }
+
+    /// Identity of the request with these headers coming from `peer`, `None` if the request
+    /// carries no credentials. Invalid credentials are an error rather than an anonymous
+    /// request.
+    pub fn authenticate(
+        &self,
+        headers: &HeaderMap,
+        peer: Option<IpAddr>,
+    ) -> Result<Option<Identity>, GitDataStoreError> {
+        if let Some(proxy) = &self.config.proxy {
+            if peer.is_some_and(|peer| proxy.trusted.contains(&peer)) {
+                if let Some(login) = header(headers, &proxy.user_header) {
+                    let email = header(headers, &proxy.email_header);
+                    let groups = header(headers, &proxy.groups_header)
+                        .map(|groups| {
+                            groups
+                                .split(',')
+                                .map(str::trim)
+                                .filter(|group| !group.is_empty())
+                                .map(str::to_string)
+                                .collect()
+                        })
+                        .unwrap_or_default();
+                    return Ok(Some(self.identity(login, email, groups)));
+                }
+            }
+        }
+
+        let authorization = match header(headers, AUTHORIZATION.as_str()) {
+            Some(authorization) => authorization,
+            None => return Ok(None),
+        };
+        let (scheme, credentials) = authorization.split_once(' ').unwrap_or((authorization, ""));
+        let login = if scheme.eq_ignore_ascii_case("bearer") {
+            self.bearer_login(credentials.trim())
+        } else if scheme.eq_ignore_ascii_case("basic") {
+            self.basic_login(credentials.trim())
+        } else {
+            None
+        };
+        match login {
+            Some(login) => Ok(Some(self.identity(&login, None, Vec::new()))),
+            None => Err(GitDataStoreError::Unauthenticated(format!(
+                "invalid {} credentials",
+                scheme.to_lowercase()
+            ))),
+        }
+    }
+
+    fn bearer_login(&self, token: &str) -> Option<String> {
+        // every token is compared so that the time taken does not tell which one matched
+        let mut login = None;
+        for (known, known_login) in &self.config.tokens {
+            if constant_time_eq(known.as_bytes(), token.as_bytes()) {
+                login = Some(known_login.clone());
+            }
+        }
+        login
+    }
+
+    fn basic_login(&self, credentials: &str) -> Option<String> {
+        let decoded = String::from_utf8(STANDARD.decode(credentials).ok()?).ok()?;
+        let (login, password) = decoded.split_once(':')?;
+        let hash = self.passwords.get(login)?;
+        let key: [u8; 32] = Sha256::digest(decoded.as_bytes()).into();
+        if let Some(login) = self.verified.lock().get(&key) {
+            return Some(login.clone());
+        }
+        match bcrypt::verify(password, hash) {
+            Ok(true) => {
+                let mut verified = self.verified.lock();
+                if verified.len() >= MAX_VERIFIED_CREDENTIALS {
+                    verified.clear();
+                }
+                verified.insert(key, login.to_string());
+                Some(login.to_string())
+            }
+            _ => None,
+        }
+    }
+
+    fn identity(&self, login: &str, email: Option<&str>, mut groups: Vec<String>) -> Identity {
+        let user = self.config.users.get(login).cloned().unwrap_or_default();
+        groups.extend(user.groups);
+        groups.sort();
+        groups.dedup();
+        Identity {
+            login: login.to_string(),
+            name: user.name.unwrap_or_else(|| login.to_string()),
+            email: email
+                .map(str::to_string)
+                .or(user.email)
+                .unwrap_or_else(|| login.to_string()),
+            groups,
+        }
+    }
+}
+
+fn header<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .filter(|value| !value.is_empty())
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
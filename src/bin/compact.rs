@@ -0,0 +1,73 @@
+use chrono::{DateTime, Duration, Utc};
+use clap::Clap;
+use nosql_git::{
+    compaction::CompactionMode,
+    signing::{CommitSigner, SignatureFormat},
+    GitDataStore,
+};
+
+/// Squashes the history of the primary branch older than a cutoff.
+#[derive(Clap, Debug)]
+pub struct Config {
+    /// Sets the git repository path to use
+    #[clap(short, long)]
+    path: String,
+
+    /// Sets the primary branch
+    #[clap(short, long, default_value = "master")]
+    branch: String,
+
+    /// Commits older than this RFC 3339 date are squashed
+    #[clap(
+        long,
+        required_unless_present = "older-than-days",
+        conflicts_with = "older-than-days"
+    )]
+    before: Option<DateTime<Utc>>,
+
+    /// Commits older than this number of days are squashed
+    #[clap(long)]
+    older_than_days: Option<i64>,
+
+    /// Squashes into a single commit, or into a commit per day. One of single or daily.
+    #[clap(long, default_value = "single")]
+    mode: CompactionMode,
+
+    /// Keeps the history before the compaction under refs/archive/<archive>
+    #[clap(long)]
+    archive: Option<String>,
+
+    /// Signs the rewritten commits with `signing-key`. One of ssh or openpgp.
+    #[clap(long, requires = "signing-key")]
+    signing_format: Option<SignatureFormat>,
+
+    /// Key the rewritten commits are signed with
+    #[clap(long, requires = "signing-format")]
+    signing_key: Option<String>,
+}
+
+pub fn main() {
+    let config = Config::parse();
+
+    let cutoff = match (config.before, config.older_than_days) {
+        (Some(before), _) => before,
+        (None, Some(days)) => Utc::now() - Duration::days(days),
+        (None, None) => unreachable!("clap requires a cutoff"),
+    };
+    let mut store = GitDataStore::new(&config.path, &config.branch);
+    if let (Some(signing_format), Some(signing_key)) = (config.signing_format, &config.signing_key)
+    {
+        store = store.with_signer(CommitSigner::new(signing_format, signing_key));
+    }
+    let report = store
+        .compact(cutoff, config.mode, config.archive.as_deref())
+        .expect("compact");
+
+    println!(
+        "{} commits before, {} after, head is now {}",
+        report.commits_before, report.commits_after, report.head
+    );
+    if let Some(archive_ref) = &report.archive_ref {
+        println!("previous history archived as {}", archive_ref);
+    }
+}
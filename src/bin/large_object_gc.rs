@@ -0,0 +1,43 @@
+use clap::Clap;
+use nosql_git::{large_object::LargeObjectStore, GitDataStore};
+use std::time::Duration;
+
+/// Removes the large objects which are no longer referenced by the repository.
+#[derive(Clap, Debug)]
+pub struct Config {
+    /// Sets the git repository path to use
+    #[clap(short, long)]
+    path: String,
+
+    /// Sets the primary branch
+    #[clap(short, long, default_value = "master")]
+    branch: String,
+
+    /// Directory in which the large objects are stored
+    #[clap(short, long)]
+    large_objects_dir: String,
+
+    /// Large objects modified less than this number of seconds ago are kept,
+    /// since they may belong to writes in progress.
+    #[clap(long, default_value = "3600")]
+    grace_secs: u64,
+}
+
+pub fn main() {
+    let config = Config::parse();
+
+    let store = GitDataStore::new(&config.path, &config.branch)
+        .with_large_objects(LargeObjectStore::new(&config.large_objects_dir, usize::MAX));
+    let report = store
+        .gc_large_objects(Duration::from_secs(config.grace_secs))
+        .expect("gc_large_objects");
+
+    for removed in &report.removed {
+        println!("removed {}", removed);
+    }
+    println!(
+        "{} large objects referenced, {} removed",
+        report.referenced,
+        report.removed.len()
+    );
+}
@@ -0,0 +1,48 @@
+use clap::Clap;
+use nosql_git::{large_object::LargeObjectStore, maintenance::MaintenanceOptions, GitDataStore};
+use std::time::Duration;
+
+/// Repacks the repository, prunes unreachable objects and verifies the repository.
+#[derive(Clap, Debug)]
+pub struct Config {
+    /// Sets the git repository path to use
+    #[clap(short, long)]
+    path: String,
+
+    /// Sets the primary branch
+    #[clap(short, long, default_value = "master")]
+    branch: String,
+
+    /// Also removes the large objects no longer referenced from this directory
+    #[clap(short, long)]
+    large_objects_dir: Option<String>,
+
+    /// Unreachable objects modified less than this number of seconds ago are kept,
+    /// since they may belong to writes in progress.
+    #[clap(long, default_value = "3600")]
+    grace_secs: u64,
+
+    /// Skips the verification of the objects and references
+    #[clap(long)]
+    skip_fsck: bool,
+}
+
+pub fn main() {
+    let config = Config::parse();
+
+    let mut store = GitDataStore::new(&config.path, &config.branch);
+    if let Some(large_objects_dir) = &config.large_objects_dir {
+        store = store.with_large_objects(LargeObjectStore::new(large_objects_dir, usize::MAX));
+    }
+    let report = store
+        .maintain(&MaintenanceOptions {
+            grace_period: Duration::from_secs(config.grace_secs),
+            fsck: !config.skip_fsck,
+        })
+        .expect("maintain");
+
+    println!("{}", serde_json::to_string_pretty(&report).expect("report"));
+    if !report.is_healthy() {
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,80 @@
+use clap::Clap;
+use git2::Oid;
+use nosql_git::{
+    large_object::LargeObjectStore,
+    purge::PurgeTarget,
+    signing::{CommitSigner, SignatureFormat},
+    GitDataStore,
+};
+use std::fs;
+
+/// Permanently removes a document, or a blob, from every commit of the repository. The
+/// reflogs of the rewritten branches and tags, and of HEAD when it points to one of them,
+/// are emptied since they reference the old history.
+#[derive(Clap, Debug)]
+pub struct Config {
+    /// Sets the git repository path to use
+    #[clap(short, long)]
+    path: String,
+
+    /// Sets the primary branch
+    #[clap(short, long, default_value = "master")]
+    branch: String,
+
+    /// Path of the document, or directory, to remove
+    #[clap(long, required_unless_present = "blob", conflicts_with = "blob")]
+    document: Option<String>,
+
+    /// Id of the blob to remove, at every path it appears
+    #[clap(long)]
+    blob: Option<String>,
+
+    /// File the JSON mapping of old to new commit ids is written to, standard output if unset
+    #[clap(long)]
+    report: Option<String>,
+
+    /// Directory in which the large objects are stored
+    #[clap(short, long)]
+    large_objects_dir: Option<String>,
+
+    /// Signs the rewritten commits with `signing-key`. One of ssh or openpgp.
+    #[clap(long, requires = "signing-key")]
+    signing_format: Option<SignatureFormat>,
+
+    /// Key the rewritten commits are signed with
+    #[clap(long, requires = "signing-format")]
+    signing_key: Option<String>,
+}
+
+pub fn main() {
+    let config = Config::parse();
+
+    let target = match (&config.document, &config.blob) {
+        (Some(document), _) => PurgeTarget::Path(document.clone()),
+        (None, Some(blob)) => PurgeTarget::Blob(Oid::from_str(blob).expect("blob id")),
+        (None, None) => unreachable!("clap requires a document or a blob"),
+    };
+    let mut store = GitDataStore::new(&config.path, &config.branch);
+    if let Some(large_objects_dir) = &config.large_objects_dir {
+        store = store.with_large_objects(LargeObjectStore::new(large_objects_dir, usize::MAX));
+    }
+    if let (Some(signing_format), Some(signing_key)) = (config.signing_format, &config.signing_key)
+    {
+        store = store.with_signer(CommitSigner::new(signing_format, signing_key));
+    }
+
+    let report = store.purge(target).expect("purge");
+    let json = serde_json::to_string_pretty(&report).expect("report");
+    match &config.report {
+        Some(report_file) => {
+            fs::write(report_file, json).expect("write report");
+            eprintln!(
+                "{} commits rewritten, {} dropped, report written to {}",
+                report.commits.len(),
+                report.dropped.len(),
+                report_file
+            );
+        }
+        None => println!("{}", json),
+    }
+}
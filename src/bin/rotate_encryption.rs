@@ -0,0 +1,53 @@
+use clap::Clap;
+use nosql_git::{encryption::Encryption, large_object::LargeObjectStore, GitDataStore};
+
+/// Re-encrypts the documents of the primary branch with the last key of the keyfile.
+#[derive(Clap, Debug)]
+pub struct Config {
+    /// Sets the git repository path to use
+    #[clap(short, long)]
+    path: String,
+
+    /// Sets the primary branch
+    #[clap(short, long, default_value = "master")]
+    branch: String,
+
+    /// Glob of the encrypted documents, can be repeated
+    #[clap(long, required = true)]
+    encrypt: Vec<String>,
+
+    /// File of `<key id> <base64 of 32 bytes>` lines, the last key being the new one
+    #[clap(long)]
+    encryption_keyfile: String,
+
+    /// Directory in which the large objects are stored
+    #[clap(short, long)]
+    large_objects_dir: Option<String>,
+
+    /// Size in bytes above which re-encrypted documents are stored in `large-objects-dir`
+    #[clap(long, default_value = "10485760")]
+    large_object_threshold: usize,
+}
+
+pub fn main() {
+    let config = Config::parse();
+
+    let encryption =
+        Encryption::load(&config.encrypt, &config.encryption_keyfile).expect("encryption keys");
+    let active_key = encryption.active_key().to_string();
+    let mut store = GitDataStore::new(&config.path, &config.branch).with_encryption(encryption);
+    if let Some(large_objects_dir) = &config.large_objects_dir {
+        store = store.with_large_objects(LargeObjectStore::new(
+            large_objects_dir,
+            config.large_object_threshold,
+        ));
+    }
+
+    match store
+        .rotate_encryption(None, None)
+        .expect("rotate_encryption")
+    {
+        Some(commit_id) => println!("encrypted with key {} in {}", active_key, commit_id),
+        None => println!("every document is encrypted with key {}", active_key),
+    }
+}
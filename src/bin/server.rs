@@ -4,11 +4,35 @@ extern crate slog_async;
 extern crate slog_term;
 
 use actix_slog::StructuredLogger;
-use actix_web::{App, HttpServer};
+use actix_web::{
+    dev::{Service, ServiceRequest},
+    http::{
+        header::{ALLOW, LOCATION},
+        Method,
+    },
+    web, App, HttpMessage, HttpResponse, HttpServer,
+};
 use clap::Clap;
-use nosql_git::{clone, route, GitDataStore};
+use futures::future::{ok, Either};
+use nosql_git::{
+    acl::{AccessControl, Acl, AclValidator},
+    auth::Authenticator,
+    clone,
+    credentials::Credentials,
+    encryption::Encryption,
+    error::GitDataStoreError,
+    large_object::LargeObjectStore,
+    maintenance::{Maintenance, MaintenanceOptions},
+    merge::MergeStrategy,
+    replica::Replica,
+    route,
+    signing::{CommitSigner, CommitVerifier, SignatureFormat},
+    validation::ValidationPipeline,
+    webhook::{self, WebhookConfig},
+    GitDataStore,
+};
 use slog::Drain;
-use std::{path::Path, sync::Arc};
+use std::{path::Path, sync::Arc, time::Duration};
 
 #[derive(Clap, Debug)]
 pub struct Config {
@@ -22,10 +46,30 @@ pub struct Config {
 
     /// Clone from url if `path` does not already exists.
     /// Errors if path already exists.
-    /// Currently only supports ssh
+    /// Authenticates with the credentials options below.
     #[clap(short, long)]
     clone: Option<String>,
 
+    /// Authenticates against ssh remotes with the keys of the running ssh-agent.
+    #[clap(long)]
+    ssh_agent: bool,
+
+    /// Authenticates against ssh remotes with this private key.
+    #[clap(long)]
+    ssh_key: Option<String>,
+
+    /// Passphrase of `ssh-key`.
+    #[clap(long, env = "NOSQL_GIT_SSH_PASSPHRASE", hide_env_values = true)]
+    ssh_passphrase: Option<String>,
+
+    /// Authenticates against https remotes with this user name and `password`.
+    #[clap(long)]
+    username: Option<String>,
+
+    /// Password or token of `username`.
+    #[clap(long, env = "NOSQL_GIT_PASSWORD", hide_env_values = true)]
+    password: Option<String>,
+
     /// Initializes a repository at path if path does not already exist.
     /// Clone will take precedence over init and the init will fail.
     #[clap(short, long)]
@@ -34,8 +78,118 @@ pub struct Config {
     /// Repository is created as bare when cloned or initialized.
     #[clap(long)]
     bare: bool,
+
+    /// Stores files larger than `large-object-threshold` in this directory instead of the
+    /// repository, leaving a pointer document in their place.
+    #[clap(long)]
+    large_objects_dir: Option<String>,
+
+    /// Size in bytes above which files are stored in `large-objects-dir`.
+    #[clap(long, default_value = "10485760")]
+    large_object_threshold: usize,
+
+    /// Name of a remote the primary branch is pulled from and pushed to every `sync-interval` seconds.
+    #[clap(long)]
+    sync_remote: Option<String>,
+
+    /// Url of `sync-remote`. The remote is added to the repository, or its url updated, on startup.
+    #[clap(long)]
+    sync_url: Option<String>,
+
+    /// Branch of `sync-remote` to sync with. Defaults to the primary branch.
+    #[clap(long)]
+    sync_branch: Option<String>,
+
+    /// Number of seconds between syncs with `sync-remote`.
+    #[clap(long, default_value = "60")]
+    sync_interval: u64,
+
+    /// How diverged histories are reconciled when pulling from `sync-remote`.
+    /// One of ff-only, merge, ours or theirs.
+    #[clap(long, default_value = "merge")]
+    sync_strategy: MergeStrategy,
+
+    /// Runs as a read-only replica of the repository at this url: its primary branch is
+    /// fetched every `replica-interval` seconds and write requests are rejected.
+    /// Cannot be combined with `sync-remote`.
+    #[clap(long)]
+    replica_of: Option<String>,
+
+    /// Number of seconds between fetches from `replica-of`.
+    #[clap(long, default_value = "10")]
+    replica_interval: u64,
+
+    /// Base url of the writable server. Replicas redirect write requests to it instead of
+    /// rejecting them with 405.
+    #[clap(long)]
+    primary_url: Option<String>,
+
+    /// Number of seconds between maintenance runs, which repack the repository, prune the
+    /// unreachable objects and verify the repository. Maintenance is not scheduled if unset.
+    #[clap(long)]
+    maintenance_interval: Option<u64>,
+
+    /// Number of seconds unreachable objects and unreferenced large objects are kept before
+    /// maintenance prunes them.
+    #[clap(long, default_value = "3600")]
+    maintenance_grace_period: u64,
+
+    /// JSON file listing the webhooks called for every new commit on the primary branch.
+    #[clap(long)]
+    webhooks: Option<String>,
+
+    /// JSON file configuring the validators every commit is checked with.
+    #[clap(long)]
+    validation: Option<String>,
+
+    /// JSON file configuring how requests are authenticated: bearer tokens, an htpasswd
+    /// file or trusted proxy headers. Commits are authored by the authenticated identity
+    /// and committed by the identity of the repository.
+    #[clap(long)]
+    auth: Option<String>,
+
+    /// JSON file of access control rules granting read, write and delete permissions on
+    /// paths to identities and groups. Cannot be combined with `acl-path`.
+    #[clap(long)]
+    acl: Option<String>,
+
+    /// Path of a document of the repository holding the access control rules, read at the
+    /// head of the primary branch. Everything is denied until it exists.
+    #[clap(long)]
+    acl_path: Option<String>,
+
+    /// Signs every commit with `signing-key`. One of ssh or openpgp.
+    #[clap(long)]
+    signing_format: Option<SignatureFormat>,
+
+    /// Key commits are signed with: a private key file, or the public key file of a key held
+    /// by the ssh-agent, for ssh; a key id of the local gpg keyring for openpgp.
+    #[clap(long)]
+    signing_key: Option<String>,
+
+    /// File of the SSH keys trusted when verifying commit signatures, in the format of
+    /// `ssh-keygen -Y verify`. OpenPGP signatures are verified with the local gpg keyring.
+    #[clap(long)]
+    allowed_signers: Option<String>,
+
+    /// Encrypts the documents matching this glob at rest, can be repeated. Requires
+    /// `encryption-keyfile`.
+    #[clap(long)]
+    encrypt: Vec<String>,
+
+    /// File of `<key id> <base64 of 32 bytes>` lines. The last key encrypts new documents,
+    /// the others decrypt documents encrypted before it was added. Requires at least one
+    /// `encrypt` glob.
+    #[clap(long, requires = "encrypt")]
+    encryption_keyfile: Option<String>,
 }
 
+/// Interval at which new commits are looked for to call webhooks.
+const WEBHOOKS_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Name of the remote replicas fetch from.
+const REPLICA_REMOTE: &str = "upstream";
+
 #[actix_web::main]
 pub async fn main() -> std::io::Result<()> {
     let decorator = slog_term::TermDecorator::new().build();
@@ -47,13 +201,24 @@ pub async fn main() -> std::io::Result<()> {
 
     let config = Config::parse();
 
-    if let Some(clone_url) = config.clone {
+    let credentials = match credentials(&config) {
+        Ok(credentials) => credentials,
+        Err(err) => {
+            error!(root_log, "Invalid credentials options"; "error" => err);
+            std::process::exit(1);
+        }
+    };
+
+    if let Some(clone_url) = &config.clone {
         if Path::new(&config.path).exists() {
-            error!(root_log, "Path already exists. Cannot clone"; "url" => &clone_url, "path" => &config.path);
+            error!(root_log, "Path already exists. Cannot clone"; "url" => clone_url, "path" => &config.path);
+            std::process::exit(1);
+        }
+        info!(root_log, "cloning from url"; "url" => clone_url, "path" => &config.path);
+        if let Err(err) = clone::clone(clone_url, &config.path, config.bare, &credentials) {
+            error!(root_log, "Cannot clone"; "url" => clone_url, "error" => format!("{:#}", err));
             std::process::exit(1);
         }
-        info!(root_log, "cloning from url"; "url" => &clone_url, "path" => &config.path);
-        clone::clone_ssh(&clone_url, &config.path, config.bare).expect("clone");
     }
 
     if config.init {
@@ -61,22 +226,376 @@ pub async fn main() -> std::io::Result<()> {
         clone::init(&config.path, config.bare).expect("init");
     }
 
-    let data_store = Arc::new(GitDataStore::new(&config.path, &config.branch));
+    let mut data_store =
+        GitDataStore::new(&config.path, &config.branch).with_credentials(credentials);
+    if let Some(large_objects_dir) = &config.large_objects_dir {
+        info!(root_log, "storing large objects outside of the repository"; "dir" => large_objects_dir, "threshold" => config.large_object_threshold);
+        data_store = data_store.with_large_objects(LargeObjectStore::new(
+            large_objects_dir,
+            config.large_object_threshold,
+        ));
+    }
+    match &config.encryption_keyfile {
+        Some(keyfile) => match Encryption::load(&config.encrypt, keyfile) {
+            Ok(encryption) => {
+                info!(root_log, "encrypting documents at rest"; "paths" => format!("{:?}", config.encrypt), "key" => encryption.active_key());
+                data_store = data_store.with_encryption(encryption);
+            }
+            Err(err) => {
+                error!(root_log, "Cannot load encryption keys"; "error" => err.to_string());
+                std::process::exit(1);
+            }
+        },
+        None if !config.encrypt.is_empty() => {
+            error!(root_log, "`encrypt` requires `encryption-keyfile`");
+            std::process::exit(1);
+        }
+        None => {}
+    }
+    let mut validation = match &config.validation {
+        Some(validation) => match ValidationPipeline::load(validation) {
+            Ok(validation) => {
+                info!(root_log, "validating commits"; "validators" => format!("{:?}", validation));
+                validation
+            }
+            Err(err) => {
+                error!(root_log, "Cannot load validation"; "error" => err.to_string());
+                std::process::exit(1);
+            }
+        },
+        None => ValidationPipeline::new(),
+    };
+    let access_control = match (&config.acl, &config.acl_path) {
+        (Some(_), Some(_)) => {
+            error!(root_log, "Only one of `acl` or `acl-path` can be set");
+            std::process::exit(1);
+        }
+        (Some(acl_file), None) => match Acl::load(acl_file) {
+            Ok(acl) => {
+                info!(root_log, "enforcing access control"; "rules" => acl_file);
+                Some(Arc::new(AccessControl::Static(Arc::new(acl))))
+            }
+            Err(err) => {
+                error!(root_log, "Cannot load access control"; "error" => err.to_string());
+                std::process::exit(1);
+            }
+        },
+        (None, Some(acl_path)) => {
+            info!(root_log, "enforcing access control from the repository"; "path" => acl_path);
+            validation = validation.with(AclValidator::new(acl_path));
+            Some(Arc::new(AccessControl::in_repo(acl_path)))
+        }
+        (None, None) => None,
+    };
+    data_store = data_store.with_validation(validation);
+    match (config.signing_format, &config.signing_key) {
+        (Some(signing_format), Some(signing_key)) => {
+            info!(root_log, "signing commits"; "format" => format!("{:?}", signing_format), "key" => signing_key);
+            data_store = data_store.with_signer(CommitSigner::new(signing_format, signing_key));
+        }
+        (None, None) => {}
+        _ => {
+            error!(
+                root_log,
+                "`signing-format` and `signing-key` must be set together"
+            );
+            std::process::exit(1);
+        }
+    }
+    data_store = data_store.with_verifier(CommitVerifier::new(
+        config.allowed_signers.as_ref().map(|path| path.into()),
+    ));
+    let data_store = Arc::new(data_store);
+
+    if config.replica_of.is_some() && config.sync_remote.is_some() {
+        error!(root_log, "A replica cannot sync with a remote");
+        std::process::exit(1);
+    }
+
+    let replica = config.replica_of.as_ref().map(|upstream_url| {
+        data_store
+            .set_remote(REPLICA_REMOTE, upstream_url)
+            .expect("set_remote");
+        info!(root_log, "replicating upstream"; "url" => upstream_url, "branch" => &config.branch, "interval" => config.replica_interval);
+        let replica = Arc::new(Replica::new(REPLICA_REMOTE, &config.branch));
+        spawn_replication(
+            data_store.clone(),
+            replica.clone(),
+            Duration::from_secs(config.replica_interval),
+            root_log.new(o!("log_type" => "replication")),
+        );
+        replica
+    });
+    let maintenance = config.maintenance_interval.map(|interval| {
+        info!(root_log, "scheduling maintenance"; "interval" => interval, "grace_period" => config.maintenance_grace_period);
+        let maintenance = Arc::new(Maintenance::new(MaintenanceOptions {
+            grace_period: Duration::from_secs(config.maintenance_grace_period),
+            ..MaintenanceOptions::default()
+        }));
+        spawn_maintenance(
+            data_store.clone(),
+            maintenance.clone(),
+            Duration::from_secs(interval),
+            root_log.new(o!("log_type" => "maintenance")),
+        );
+        maintenance
+    });
+    if let Some(webhooks) = &config.webhooks {
+        let webhook_config = match WebhookConfig::load(webhooks) {
+            Ok(webhook_config) => webhook_config,
+            Err(err) => {
+                error!(root_log, "Cannot load webhooks"; "error" => err.to_string());
+                std::process::exit(1);
+            }
+        };
+        info!(root_log, "calling webhooks"; "count" => webhook_config.webhooks.len());
+        webhook::spawn(
+            data_store.clone(),
+            webhook_config,
+            WEBHOOKS_POLL_INTERVAL,
+            root_log.new(o!("log_type" => "webhooks")),
+        )
+        .expect("webhook::spawn");
+    }
+
+    let authenticator = config
+        .auth
+        .as_ref()
+        .map(|auth| match Authenticator::load(auth) {
+            Ok(authenticator) => {
+                info!(root_log, "authenticating requests"; "config" => auth);
+                Arc::new(authenticator)
+            }
+            Err(err) => {
+                error!(root_log, "Cannot load authentication"; "error" => err.to_string());
+                std::process::exit(1);
+            }
+        });
+
+    let read_only = replica.is_some();
+    let primary_url = config.primary_url.clone();
+
+    if let Some(sync_remote) = &config.sync_remote {
+        if let Some(sync_url) = &config.sync_url {
+            data_store
+                .set_remote(sync_remote, sync_url)
+                .expect("set_remote");
+        }
+        let sync_branch = config.sync_branch.as_ref().unwrap_or(&config.branch);
+        info!(root_log, "syncing with remote"; "remote" => sync_remote, "branch" => sync_branch, "interval" => config.sync_interval);
+        spawn_sync(
+            data_store.clone(),
+            sync_remote.clone(),
+            sync_branch.clone(),
+            Duration::from_secs(config.sync_interval),
+            config.sync_strategy,
+            root_log.new(o!("log_type" => "sync")),
+        );
+    }
 
     info!(root_log, "listening to :8081");
     HttpServer::new(move || {
-        App::new()
+        let primary_url = primary_url.clone();
+        let authenticator = authenticator.clone();
+        let mut app = App::new()
+            .wrap_fn(move |req, srv| {
+                let authenticator = match &authenticator {
+                    Some(authenticator) => authenticator,
+                    None => return Either::Right(srv.call(req)),
+                };
+                match authenticate(authenticator, &req) {
+                    Ok(()) => Either::Right(srv.call(req)),
+                    Err(err) => Either::Left(ok(req.error_response(err))),
+                }
+            })
+            .wrap_fn(move |req, srv| {
+                if read_only && is_write(req.method()) {
+                    let response = reject_write(primary_url.as_deref(), &req);
+                    Either::Left(ok(req.into_response(response)))
+                } else {
+                    Either::Right(srv.call(req))
+                }
+            })
             .wrap(StructuredLogger::new(
                 root_log.new(o!("log_type" => "access")),
             ))
-            .data(data_store.clone())
-            .service(route::get_data)
+            .data(data_store.clone());
+        if let Some(replica) = &replica {
+            app = app.data(replica.clone());
+        }
+        if let Some(maintenance) = &maintenance {
+            app = app.data(maintenance.clone());
+        }
+        if let Some(access_control) = &access_control {
+            app = app.data(access_control.clone());
+        }
+        app.service(route::get_data)
             .service(route::put_data)
             .service(route::history)
             .service(route::get_latest_data)
             .service(route::put_latest_data)
+            .service(route::patch_data)
+            .service(route::patch_latest_data)
+            .service(route::get_raw_data)
+            .service(route::get_latest_raw_data)
+            .service(route::put_raw_data)
+            .service(route::put_latest_raw_data)
+            .service(route::find_by_labels)
+            .service(route::find_by_labels_latest)
+            .service(route::schemas_for)
+            .service(route::schemas_for_latest)
+            .service(route::changes)
+            .service(route::watch)
+            .service(route::revert)
+            .service(route::restore)
+            .service(route::cherry_pick)
+            .service(route::tags)
+            .service(route::get_tag)
+            .service(route::create_tag)
+            .service(route::delete_tag)
+            .service(route::status)
     })
     .bind("127.0.0.1:8081")?
     .run()
     .await
 }
+
+/// At most one kind of credentials can be configured.
+fn credentials(config: &Config) -> Result<Credentials, String> {
+    match (config.ssh_agent, &config.ssh_key, &config.username) {
+        (false, None, None) => Ok(Credentials::None),
+        (true, None, None) => Ok(Credentials::SshAgent),
+        (false, Some(ssh_key), None) => Ok(Credentials::SshKey {
+            private_key: ssh_key.into(),
+            public_key: None,
+            passphrase: config.ssh_passphrase.clone(),
+        }),
+        (false, None, Some(username)) => match &config.password {
+            Some(password) => Ok(Credentials::UserPass {
+                username: username.clone(),
+                password: password.clone(),
+            }),
+            None => Err("`password` is required with `username`".to_string()),
+        },
+        _ => Err("only one of `ssh-agent`, `ssh-key` or `username` can be set".to_string()),
+    }
+}
+
+/// Periodically fetches the primary branch of the upstream repository in the background.
+fn spawn_replication(
+    data_store: Arc<GitDataStore>,
+    replica: Arc<Replica>,
+    interval: Duration,
+    log: slog::Logger,
+) {
+    actix_rt::spawn(async move {
+        let mut interval = actix_rt::time::interval(interval);
+        loop {
+            interval.tick().await;
+            let (data_store, replica) = (data_store.clone(), replica.clone());
+            match web::block(move || replica.fetch(&data_store)).await {
+                Ok(head) => debug!(log, "fetched from upstream"; "head" => head),
+                Err(err) => {
+                    error!(log, "failed to fetch from upstream"; "error" => err.to_string())
+                }
+            }
+        }
+    });
+}
+
+/// Periodically runs the maintenance of the repository in the background. The first run
+/// happens after `interval` rather than on startup.
+fn spawn_maintenance(
+    data_store: Arc<GitDataStore>,
+    maintenance: Arc<Maintenance>,
+    interval: Duration,
+    log: slog::Logger,
+) {
+    actix_rt::spawn(async move {
+        let start = actix_rt::time::Instant::now() + interval;
+        let mut interval = actix_rt::time::interval_at(start, interval);
+        loop {
+            interval.tick().await;
+            let (data_store, maintenance) = (data_store.clone(), maintenance.clone());
+            match web::block(move || maintenance.run(&data_store)).await {
+                Ok(report) if report.is_healthy() => {
+                    info!(log, "maintained repository"; "duration_ms" => report.duration_ms as u64, "loose_objects" => report.after.loose_objects, "packs" => report.after.packs)
+                }
+                Ok(report) => {
+                    error!(log, "repository is corrupted"; "problems" => report.fsck_problems.join("; "))
+                }
+                Err(err) => {
+                    error!(log, "failed to maintain repository"; "error" => err.to_string())
+                }
+            }
+        }
+    });
+}
+
+/// Makes the identity of the request available to the routes. Requests without credentials
+/// are only let through when they are reads and anonymous reads are allowed.
+fn authenticate(
+    authenticator: &Authenticator,
+    req: &ServiceRequest,
+) -> Result<(), GitDataStoreError> {
+    let peer = req.peer_addr().map(|addr| addr.ip());
+    match authenticator.authenticate(req.headers(), peer)? {
+        Some(identity) => {
+            req.extensions_mut().insert(identity);
+            Ok(())
+        }
+        None if authenticator.anonymous_reads() && !is_write(req.method()) => Ok(()),
+        None => Err(GitDataStoreError::Unauthenticated(
+            "missing credentials".to_string(),
+        )),
+    }
+}
+
+fn is_write(method: &Method) -> bool {
+    !matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS)
+}
+
+/// Replicas redirect writes to the primary when its url is known. 307 keeps the method
+/// and the body of the request.
+fn reject_write(primary_url: Option<&str>, req: &ServiceRequest) -> HttpResponse {
+    match primary_url {
+        Some(primary_url) => {
+            let path = req
+                .uri()
+                .path_and_query()
+                .map(|path| path.as_str())
+                .unwrap_or("/");
+            HttpResponse::TemporaryRedirect()
+                .header(
+                    LOCATION,
+                    format!("{}{}", primary_url.trim_end_matches('/'), path),
+                )
+                .finish()
+        }
+        None => HttpResponse::MethodNotAllowed()
+            .header(ALLOW, "GET, HEAD, OPTIONS")
+            .json(serde_json::json!({ "error": "read-only replica" })),
+    }
+}
+
+/// Periodically pulls from and pushes to the remote in the background.
+fn spawn_sync(
+    data_store: Arc<GitDataStore>,
+    remote: String,
+    branch: String,
+    interval: Duration,
+    strategy: MergeStrategy,
+    log: slog::Logger,
+) {
+    actix_rt::spawn(async move {
+        let mut interval = actix_rt::time::interval(interval);
+        loop {
+            interval.tick().await;
+            let (data_store, remote, branch) = (data_store.clone(), remote.clone(), branch.clone());
+            match web::block(move || data_store.sync(&remote, &branch, strategy)).await {
+                Ok(head) => debug!(log, "synced with remote"; "head" => head),
+                Err(err) => error!(log, "failed to sync with remote"; "error" => err.to_string()),
+            }
+        }
+    });
+}
@@ -0,0 +1,220 @@
+use crate::{
+    encryption::{self, Encryption},
+    error::GitDataStoreError,
+    large_object::{LargeObjectPointer, LargeObjectStore},
+    metadata::Metadata,
+};
+use git2::{Blob, BlobWriter, Oid, Repository};
+use std::{
+    borrow::Cow,
+    fs::File,
+    io::{self, Read, Seek, SeekFrom},
+    ops::Range,
+};
+
+/// How the content of documents is stored in blobs: in the large object store when they
+/// are large, encrypted when their path is encrypted.
+#[derive(Clone, Copy, Default)]
+pub(crate) struct BlobCodec<'a> {
+    pub large_objects: Option<&'a LargeObjectStore>,
+    pub encryption: Option<&'a Encryption>,
+}
+
+impl<'a> BlobCodec<'a> {
+    /// Content of the document at `path` whose blob holds `content`.
+    pub fn decode<'b>(
+        &self,
+        path: &str,
+        content: &'b [u8],
+    ) -> Result<Cow<'b, [u8]>, GitDataStoreError> {
+        let content = match self.large_objects.zip(LargeObjectPointer::parse(content)) {
+            Some((large_objects, pointer)) => Cow::Owned(large_objects.read(&pointer)?),
+            None => Cow::Borrowed(content),
+        };
+        Ok(
+            match encryption::decrypt(self.encryption, path, &content)? {
+                Some(decrypted) => Cow::Owned(decrypted),
+                None => content,
+            },
+        )
+    }
+}
+
+/// Size of the chunks yielded by [`BlobChunks`].
+pub const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Handle on the content of a file at a given commit which can be read in chunks
+/// without copying the whole content into a `String` like `GitData::File` does.
+pub struct BlobReader {
+    source: BlobSource,
+    commit_id: String,
+    metadata: Option<Metadata>,
+}
+
+pub(crate) enum BlobSource {
+    Git {
+        repo: Repository,
+        blob_id: Oid,
+        size: usize,
+    },
+    LargeObject {
+        large_objects: LargeObjectStore,
+        pointer: LargeObjectPointer,
+        size: usize,
+    },
+    /// Content decrypted from the blob `blob_id`.
+    Memory { blob_id: String, data: Vec<u8> },
+}
+
+impl BlobReader {
+    pub(crate) fn new(source: BlobSource, commit_id: String, metadata: Option<Metadata>) -> Self {
+        BlobReader {
+            source,
+            commit_id,
+            metadata,
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        match &self.source {
+            BlobSource::Git { size, .. } => *size,
+            BlobSource::LargeObject { size, .. } => *size,
+            BlobSource::Memory { data, .. } => data.len(),
+        }
+    }
+
+    /// Id of the blob holding the content. For large objects this is the id the content
+    /// would have had if it had been stored in the repository.
+    pub fn blob_id(&self) -> String {
+        match &self.source {
+            BlobSource::Git { blob_id, .. } => blob_id.to_string(),
+            BlobSource::LargeObject { pointer, .. } => pointer.oid.to_string(),
+            BlobSource::Memory { blob_id, .. } => blob_id.clone(),
+        }
+    }
+
+    pub fn commit_id(&self) -> &str {
+        &self.commit_id
+    }
+
+    pub fn metadata(&self) -> Option<&Metadata> {
+        self.metadata.as_ref()
+    }
+
+    /// Content type recorded in the metadata of the file when it was written.
+    pub fn content_type(&self) -> Option<&str> {
+        self.metadata
+            .as_ref()
+            .and_then(|metadata| metadata.content_type.as_deref())
+    }
+
+    /// Calls `f` with the bytes of `range` in chunks of at most [`CHUNK_SIZE`], until it
+    /// returns `false`. The range is clamped to the size of the blob. Unlike `chunks`, blobs
+    /// stored as loose objects are streamed from the object database rather than read at
+    /// once; libgit2 cannot stream packed objects, which are read at once.
+    pub fn for_each_chunk<F>(&self, range: Range<usize>, mut f: F) -> Result<(), GitDataStoreError>
+    where
+        F: FnMut(Vec<u8>) -> bool,
+    {
+        let end = range.end.min(self.size());
+        let pos = range.start.min(end);
+        if let BlobSource::Git { repo, blob_id, .. } = &self.source {
+            let odb = repo.odb()?;
+            let stream = odb.reader(*blob_id);
+            if let Ok((mut stream, _size, _kind)) = stream {
+                io::copy(&mut (&mut stream).take(pos as u64), &mut io::sink())?;
+                let mut remaining = end - pos;
+                while remaining > 0 {
+                    let mut chunk = vec![0; remaining.min(CHUNK_SIZE)];
+                    stream.read_exact(&mut chunk)?;
+                    remaining -= chunk.len();
+                    if !f(chunk) {
+                        break;
+                    }
+                }
+                return Ok(());
+            }
+        }
+        for chunk in self.chunks(pos..end)? {
+            if !f(chunk?) {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Iterates over the bytes of `range` in chunks of at most [`CHUNK_SIZE`].
+    /// The range is clamped to the size of the blob.
+    pub fn chunks(&self, range: Range<usize>) -> Result<BlobChunks<'_>, GitDataStoreError> {
+        let end = range.end.min(self.size());
+        let pos = range.start.min(end);
+        let content = match &self.source {
+            BlobSource::Git { repo, blob_id, .. } => ChunkSource::Blob(repo.find_blob(*blob_id)?),
+            BlobSource::LargeObject {
+                large_objects,
+                pointer,
+                ..
+            } => {
+                let mut file = large_objects.open(pointer)?;
+                file.seek(SeekFrom::Start(pos as u64))?;
+                ChunkSource::File(file)
+            }
+            BlobSource::Memory { data, .. } => ChunkSource::Memory(data),
+        };
+        Ok(BlobChunks { content, pos, end })
+    }
+}
+
+pub struct BlobChunks<'repo> {
+    content: ChunkSource<'repo>,
+    pos: usize,
+    end: usize,
+}
+
+enum ChunkSource<'repo> {
+    Blob(Blob<'repo>),
+    File(File),
+    Memory(&'repo [u8]),
+}
+
+impl<'repo> Iterator for BlobChunks<'repo> {
+    type Item = Result<Vec<u8>, GitDataStoreError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.pos >= self.end {
+            return None;
+        }
+        let chunk_end = self.end.min(self.pos + CHUNK_SIZE);
+        let chunk = match &mut self.content {
+            ChunkSource::Blob(blob) => Ok(blob.content()[self.pos..chunk_end].to_vec()),
+            ChunkSource::Memory(data) => Ok(data[self.pos..chunk_end].to_vec()),
+            ChunkSource::File(file) => {
+                let mut chunk = vec![0; chunk_end - self.pos];
+                file.read_exact(&mut chunk)
+                    .map(|_| chunk)
+                    .map_err(|e| e.into())
+            }
+        };
+        // stop after the first error
+        self.pos = if chunk.is_ok() { chunk_end } else { self.end };
+        Some(chunk)
+    }
+}
+
+/// Owns the repository a [`BlobWriter`] writes into, so that content can be
+/// streamed into the object database before it is committed with
+/// `GitDataStore::put_blob` or `GitDataStore::put_blob_latest`.
+pub struct BlobUpload {
+    repo: Repository,
+}
+
+impl BlobUpload {
+    pub(crate) fn new(repo: Repository) -> Self {
+        BlobUpload { repo }
+    }
+
+    /// The blob id is returned by `BlobWriter::commit` once all the content has been written.
+    pub fn writer(&self) -> Result<BlobWriter<'_>, GitDataStoreError> {
+        Ok(self.repo.blob_writer(None)?)
+    }
+}
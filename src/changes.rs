@@ -0,0 +1,156 @@
+use crate::{
+    error::GitDataStoreError,
+    metadata::{is_reserved_path, METADATA_DIR},
+    GitEntry,
+};
+use chrono::{DateTime, FixedOffset, TimeZone};
+use git2::{Commit, Delta, Oid, Repository, Sort, Tree};
+use serde::Serialize;
+use std::path::Path;
+
+/// Commits of the primary branch made after a given commit.
+#[derive(Debug, Serialize)]
+pub struct Changes {
+    /// Head of the primary branch the changes were computed up to. Pass it as `since` to get
+    /// the next changes.
+    pub head: String,
+    /// Oldest first.
+    pub commits: Vec<CommitChange>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct CommitChange {
+    pub commit_id: String,
+    pub datetime: DateTime<FixedOffset>,
+    pub author: String,
+    pub message: Option<String>,
+    pub paths: Vec<PathChange>,
+}
+
+/// Latest version of a document which changed since a given commit.
+#[derive(Debug, Serialize)]
+pub struct DocumentChange {
+    /// Head of the primary branch the document was read at.
+    pub commit_id: String,
+    /// `None` when the document was deleted.
+    pub entry: Option<GitEntry>,
+}
+
+#[derive(Clone, Debug, Serialize, PartialEq, Eq)]
+pub struct PathChange {
+    pub path: String,
+    pub status: ChangeStatus,
+}
+
+#[derive(Debug, Serialize, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeStatus {
+    Added,
+    Modified,
+    Deleted,
+}
+
+/// Lists the commits reachable from `head` but not from `since`, with the documents each of
+/// them changed compared to its first parent. Only documents under `prefix` are listed and
+/// commits which changed none of them are skipped.
+pub(crate) fn changes_since(
+    repo: &Repository,
+    since: Oid,
+    head: Oid,
+    prefix: Option<&str>,
+) -> Result<Changes, GitDataStoreError> {
+    let mut rev_walk = repo.revwalk()?;
+    rev_walk.set_sorting(Sort::TOPOLOGICAL | Sort::REVERSE)?;
+    rev_walk.push(head)?;
+    rev_walk.hide(since)?;
+
+    let mut commits = Vec::new();
+    for rev in rev_walk {
+        let commit = repo.find_commit(rev?)?;
+        let paths = commit_paths(repo, &commit, prefix)?;
+        if paths.is_empty() && prefix.is_some() {
+            continue;
+        }
+
+        let commit_time = commit.time();
+        commits.push(CommitChange {
+            commit_id: commit.id().to_string(),
+            datetime: FixedOffset::east(commit_time.offset_minutes() * 60)
+                .timestamp(commit_time.seconds(), 0),
+            author: commit.author().to_string(),
+            message: commit.message().map(|m| m.to_string()),
+            paths,
+        });
+    }
+
+    Ok(Changes {
+        head: head.to_string(),
+        commits,
+    })
+}
+
+/// Documents changed by `commit` compared to its first parent, under `prefix` when given.
+pub(crate) fn commit_paths(
+    repo: &Repository,
+    commit: &Commit,
+    prefix: Option<&str>,
+) -> Result<Vec<PathChange>, GitDataStoreError> {
+    let parent_tree = commit.parents().next().map(|p| p.tree()).transpose()?;
+    tree_paths(repo, parent_tree.as_ref(), &commit.tree()?, prefix)
+}
+
+/// Documents which differ between `old_tree` and `new_tree`, under `prefix` when given.
+pub(crate) fn tree_paths(
+    repo: &Repository,
+    old_tree: Option<&Tree>,
+    new_tree: &Tree,
+    prefix: Option<&str>,
+) -> Result<Vec<PathChange>, GitDataStoreError> {
+    let diff = repo.diff_tree_to_tree(old_tree, Some(new_tree), None)?;
+
+    let mut paths: Vec<PathChange> = Vec::new();
+    for delta in diff.deltas() {
+        let file = match delta.status() {
+            Delta::Deleted => delta.old_file(),
+            _ => delta.new_file(),
+        };
+        let path = match file.path().and_then(|path| path.to_str()) {
+            Some(path) => path,
+            None => continue,
+        };
+        // a change of metadata alone is reported as a modification of its document
+        let (path, status) = if is_reserved_path(path) {
+            (&path[METADATA_DIR.len() + 1..], None)
+        } else {
+            let status = match delta.status() {
+                Delta::Added => ChangeStatus::Added,
+                Delta::Deleted => ChangeStatus::Deleted,
+                _ => ChangeStatus::Modified,
+            };
+            (path, Some(status))
+        };
+        if !matches_prefix(path, prefix) {
+            continue;
+        }
+        match paths.iter_mut().find(|known| known.path == path) {
+            Some(known) => {
+                if let Some(status) = status {
+                    known.status = status;
+                }
+            }
+            None => paths.push(PathChange {
+                path: path.to_string(),
+                status: status.unwrap_or(ChangeStatus::Modified),
+            }),
+        }
+    }
+    Ok(paths)
+}
+
+/// Prefixes match whole path components, `docs` matches `docs/doc1` but not `docs2/doc1`.
+pub(crate) fn matches_prefix(path: &str, prefix: Option<&str>) -> bool {
+    match prefix {
+        Some(prefix) => Path::new(path).starts_with(prefix.trim_matches('/')),
+        None => true,
+    }
+}
@@ -1,23 +1,18 @@
-use anyhow::Result;
-use git2::{Cred, RemoteCallbacks, Repository, RepositoryInitOptions};
-use std::env;
+use crate::credentials::Credentials;
+use anyhow::{Context, Result};
+use git2::{Repository, RepositoryInitOptions};
 use std::path::Path;
 
-pub fn clone_ssh<T: AsRef<Path>>(url: &str, path: T, bare: bool) -> Result<Repository> {
-    // Prepare callbacks.
-    let mut callbacks = RemoteCallbacks::new();
-    callbacks.credentials(|_url, username_from_url, _allowed_types| {
-        Cred::ssh_key(
-            username_from_url.unwrap(),
-            None,
-            std::path::Path::new(&format!("{}/.ssh/id_rsa", env::var("HOME").unwrap())),
-            None,
-        )
-    });
-
+/// Clones `url` into `path`, authenticating with `credentials` when the remote requires it.
+pub fn clone<T: AsRef<Path>>(
+    url: &str,
+    path: T,
+    bare: bool,
+    credentials: &Credentials,
+) -> Result<Repository> {
     // Prepare fetch options.
     let mut fo = git2::FetchOptions::new();
-    fo.remote_callbacks(callbacks);
+    fo.remote_callbacks(credentials.remote_callbacks());
 
     // Prepare builder.
     let mut builder = git2::build::RepoBuilder::new();
@@ -25,7 +20,9 @@ pub fn clone_ssh<T: AsRef<Path>>(url: &str, path: T, bare: bool) -> Result<Repos
     builder.bare(bare);
 
     // Clone the project.
-    Ok(builder.clone(url, path.as_ref())?)
+    builder
+        .clone(url, path.as_ref())
+        .with_context(|| format!("failed to clone {}", url))
 }
 
 /// From https://github.com/rust-lang/git2-rs/blob/7912c90991444abb00f9d0476939d48bc368516b/examples/init.rs
@@ -0,0 +1,179 @@
+use crate::error::GitDataStoreError;
+use chrono::{DateTime, NaiveDate, TimeZone, Utc};
+use git2::{Commit, Oid, Repository, Signature, Sort, Time, Tree};
+use serde::Serialize;
+use std::{collections::HashMap, str::FromStr};
+
+/// Prefix of the references keeping the history from before a compaction.
+pub const ARCHIVE_REFS_PREFIX: &str = "refs/archive/";
+
+/// How the commits older than the cutoff of a compaction are squashed.
+#[derive(Clone, Copy, Debug, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CompactionMode {
+    /// Into a single commit holding the tree of the last commit before the cutoff.
+    Single,
+    /// Into a commit per UTC day, holding the tree of the last commit of the day.
+    Daily,
+}
+
+impl FromStr for CompactionMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "single" => Ok(CompactionMode::Single),
+            "daily" => Ok(CompactionMode::Daily),
+            _ => Err(format!(
+                "unknown compaction mode {}, expected single or daily",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct CompactionReport {
+    /// Commits reachable from the primary branch before and after the compaction.
+    pub commits_before: usize,
+    pub commits_after: usize,
+    pub head: String,
+    /// Reference keeping the history from before the compaction, if it was archived.
+    pub archive_ref: Option<String>,
+}
+
+/// Squashes the first parent history of `head` older than `cutoff` according to `mode`, then
+/// replays the newer commits on top of it with their trees, authors and messages, creating
+/// every commit with `create_commit(author, committer, message, tree, parents)`. Returns the
+/// new head, which is `head` when there is nothing to squash.
+pub(crate) fn compact<F>(
+    repo: &Repository,
+    head: &Commit,
+    cutoff: DateTime<Utc>,
+    mode: CompactionMode,
+    mut create_commit: F,
+) -> Result<Oid, GitDataStoreError>
+where
+    F: FnMut(&Signature, &Signature, &str, &Tree, &[&Commit]) -> Result<Oid, GitDataStoreError>,
+{
+    // first parent history older than the cutoff, oldest first
+    let mut squashed = Vec::new();
+    let mut commit = Some(head.clone());
+    while let Some(current) = commit {
+        commit = current.parents().next();
+        if !squashed.is_empty() || commit_datetime(&current) < cutoff {
+            squashed.push(current);
+        }
+    }
+    squashed.reverse();
+    let base = match squashed.last() {
+        Some(base) => base.id(),
+        None => return Ok(head.id()),
+    };
+
+    let mut buckets: Vec<Vec<Commit>> = Vec::new();
+    let mut bucket_day: Option<NaiveDate> = None;
+    for commit in squashed {
+        let day = commit_datetime(&commit).naive_utc().date();
+        match buckets.last_mut() {
+            Some(bucket) if mode == CompactionMode::Single || bucket_day == Some(day) => {
+                bucket.push(commit)
+            }
+            _ => buckets.push(vec![commit]),
+        }
+        bucket_day = Some(day);
+    }
+
+    let identity = repo.signature()?;
+    let mut commits: HashMap<Oid, Oid> = HashMap::new();
+    let mut previous: Option<Oid> = None;
+    for bucket in &buckets {
+        let last = bucket.last().expect("buckets are not empty");
+        let unchanged =
+            bucket.len() == 1 && last.parent_count() <= 1 && last.parent_ids().next() == previous;
+        let parents = previous
+            .map(|id| repo.find_commit(id))
+            .transpose()?
+            .into_iter()
+            .collect::<Vec<_>>();
+        let parents = parents.iter().collect::<Vec<_>>();
+        let squash_id = if unchanged {
+            last.id()
+        } else if bucket.len() == 1 {
+            create_commit(
+                &last.author(),
+                &last.committer(),
+                last.message().unwrap_or_default(),
+                &last.tree()?,
+                &parents,
+            )?
+        } else {
+            let time = last.committer().when();
+            let signature = Signature::new(
+                identity.name().unwrap_or_default(),
+                identity.email().unwrap_or_default(),
+                &Time::new(time.seconds(), time.offset_minutes()),
+            )?;
+            let message = format!(
+                "Squashed {} commits until {}",
+                bucket.len(),
+                commit_datetime(last).to_rfc3339()
+            );
+            create_commit(&signature, &signature, &message, &last.tree()?, &parents)?
+        };
+        for commit in bucket {
+            commits.insert(commit.id(), squash_id);
+        }
+        previous = Some(squash_id);
+    }
+    // commits merged into the squashed history are part of its last commit
+    let last_squash = previous.expect("at least one bucket");
+
+    let mut rev_walk = repo.revwalk()?;
+    rev_walk.set_sorting(Sort::TOPOLOGICAL | Sort::REVERSE)?;
+    rev_walk.push(head.id())?;
+    rev_walk.hide(base)?;
+    for rev in rev_walk {
+        let commit = repo.find_commit(rev?)?;
+        let mut parent_ids: Vec<Oid> = Vec::new();
+        for parent_id in commit.parent_ids() {
+            let parent_id = commits.get(&parent_id).copied().unwrap_or(last_squash);
+            if !parent_ids.contains(&parent_id) {
+                parent_ids.push(parent_id);
+            }
+        }
+        let new_id = if parent_ids == commit.parent_ids().collect::<Vec<_>>() {
+            commit.id()
+        } else {
+            let parents = parent_ids
+                .iter()
+                .map(|id| repo.find_commit(*id))
+                .collect::<Result<Vec<_>, _>>()?;
+            create_commit(
+                &commit.author(),
+                &commit.committer(),
+                commit.message().unwrap_or_default(),
+                &commit.tree()?,
+                &parents.iter().collect::<Vec<_>>(),
+            )?
+        };
+        commits.insert(commit.id(), new_id);
+    }
+    Ok(commits[&head.id()])
+}
+
+/// Number of commits reachable from `head`.
+pub(crate) fn count_commits(repo: &Repository, head: Oid) -> Result<usize, GitDataStoreError> {
+    let mut rev_walk = repo.revwalk()?;
+    rev_walk.push(head)?;
+    let mut count = 0;
+    for rev in rev_walk {
+        rev?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+fn commit_datetime(commit: &Commit) -> DateTime<Utc> {
+    Utc.timestamp(commit.time().seconds(), 0)
+}
@@ -0,0 +1,133 @@
+use git2::{Cred, CredentialType, RemoteCallbacks};
+use std::{fmt, path::PathBuf};
+
+/// libgit2 calls the credentials callback again after each failed authentication, so the
+/// number of attempts is bounded to fail with an error instead of looping forever.
+const MAX_AUTH_ATTEMPTS: usize = 3;
+
+const REDACTED: &str = "<redacted>";
+
+/// User name used for ssh remotes whose url does not include one, e.g. `host:repo.git`.
+const DEFAULT_SSH_USERNAME: &str = "git";
+
+/// Credentials used to authenticate against remotes when cloning, fetching and pushing.
+#[derive(Clone, Default)]
+pub enum Credentials {
+    /// No authentication, e.g. for `file://` urls and local paths.
+    #[default]
+    None,
+    /// Keys held by the running ssh-agent.
+    SshAgent,
+    /// Private key file with an optional passphrase. The public key is derived from the
+    /// private key when not given.
+    SshKey {
+        private_key: PathBuf,
+        public_key: Option<PathBuf>,
+        passphrase: Option<String>,
+    },
+    /// User name and password for https remotes. Tokens are passed as the password.
+    UserPass { username: String, password: String },
+}
+
+/// Passwords and passphrases are redacted so that credentials can be logged.
+impl fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Credentials::None => f.write_str("None"),
+            Credentials::SshAgent => f.write_str("SshAgent"),
+            Credentials::SshKey {
+                private_key,
+                public_key,
+                passphrase,
+            } => f
+                .debug_struct("SshKey")
+                .field("private_key", private_key)
+                .field("public_key", public_key)
+                .field("passphrase", &passphrase.as_ref().map(|_| REDACTED))
+                .finish(),
+            Credentials::UserPass { username, .. } => f
+                .debug_struct("UserPass")
+                .field("username", username)
+                .field("password", &REDACTED)
+                .finish(),
+        }
+    }
+}
+
+impl Credentials {
+    fn kind(&self) -> &'static str {
+        match self {
+            Credentials::None => "none",
+            Credentials::SshAgent => "ssh-agent",
+            Credentials::SshKey { .. } => "ssh-key",
+            Credentials::UserPass { .. } => "userpass",
+        }
+    }
+
+    /// Callbacks answering the credential requests of a remote with these credentials.
+    pub fn remote_callbacks(&self) -> RemoteCallbacks<'_> {
+        let mut callbacks = RemoteCallbacks::new();
+        let mut attempts = 0;
+        callbacks.credentials(move |url, username_from_url, allowed_types| {
+            attempts += 1;
+            if attempts > MAX_AUTH_ATTEMPTS {
+                return Err(git2::Error::from_str(&format!(
+                    "authentication failed for {} with {} credentials",
+                    url,
+                    self.kind()
+                )));
+            }
+            self.credential(url, username_from_url, allowed_types)
+        });
+        callbacks
+    }
+
+    fn credential(
+        &self,
+        url: &str,
+        username_from_url: Option<&str>,
+        allowed_types: CredentialType,
+    ) -> Result<Cred, git2::Error> {
+        let ssh_username = username_from_url.unwrap_or(DEFAULT_SSH_USERNAME);
+        match self {
+            Credentials::SshAgent if allowed_types.contains(CredentialType::SSH_KEY) => {
+                Cred::ssh_key_from_agent(ssh_username)
+            }
+            Credentials::SshKey {
+                private_key,
+                public_key,
+                passphrase,
+            } if allowed_types.contains(CredentialType::SSH_KEY) => Cred::ssh_key(
+                ssh_username,
+                public_key.as_deref(),
+                private_key,
+                passphrase.as_deref(),
+            ),
+            Credentials::UserPass { username, password }
+                if allowed_types.contains(CredentialType::USER_PASS_PLAINTEXT) =>
+            {
+                Cred::userpass_plaintext(username, password)
+            }
+            // asked for the user name first when it is not part of an ssh url
+            Credentials::SshAgent | Credentials::SshKey { .. }
+                if allowed_types.contains(CredentialType::USERNAME) =>
+            {
+                Cred::username(ssh_username)
+            }
+            Credentials::UserPass { username, .. }
+                if allowed_types.contains(CredentialType::USERNAME) =>
+            {
+                Cred::username(username)
+            }
+            Credentials::None => Err(git2::Error::from_str(&format!(
+                "{} requires authentication but no credentials are configured",
+                url
+            ))),
+            _ => Err(git2::Error::from_str(&format!(
+                "{} credentials cannot be used for {}",
+                self.kind(),
+                url
+            ))),
+        }
+    }
+}
@@ -0,0 +1,207 @@
+use crate::{error::GitDataStoreError, metadata::is_reserved_path, path_glob::PathGlobs};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chacha20poly1305::{
+    aead::{Aead, AeadCore, KeyInit, OsRng, Payload},
+    XChaCha20Poly1305, XNonce,
+};
+use std::{fmt, fs, path::Path};
+
+pub(crate) const ENCRYPTED_HEADER: &str = "nosql-git-encrypted v1\n";
+
+const KEY_SIZE: usize = 32;
+const NONCE_SIZE: usize = 24;
+
+/// Transparent encryption of the documents matching a set of globs. Blobs of encrypted
+/// documents hold:
+///
+/// ```text
+/// nosql-git-encrypted v1
+/// key <key id>
+/// <base64 of the nonce followed by the XChaCha20-Poly1305 ciphertext>
+/// ```
+///
+/// The path of the document is authenticated with the content, so an encrypted blob cannot
+/// be moved to another path. Metadata is not encrypted.
+#[derive(Clone)]
+pub struct Encryption {
+    paths: PathGlobs,
+    /// Oldest first, the last key encrypts new content.
+    keys: Vec<(String, XChaCha20Poly1305)>,
+}
+
+impl fmt::Debug for Encryption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Encryption")
+            .field("paths", &self.paths.globs())
+            .field(
+                "keys",
+                &self.keys.iter().map(|(id, _)| id).collect::<Vec<_>>(),
+            )
+            .finish()
+    }
+}
+
+impl Encryption {
+    /// `keyfile` has a key per line, `<key id> <base64 of 32 random bytes>`, e.g. created with
+    /// `echo "2021-04 $(head -c 32 /dev/urandom | base64)" >> keyfile`. The last key encrypts
+    /// new content, the others are kept to read content encrypted before a key rotation.
+    /// `paths` must hold at least one glob, an empty set of globs would match every path.
+    pub fn new(paths: PathGlobs, keyfile: &str) -> Result<Self, String> {
+        if paths.globs().is_empty() {
+            return Err("at least one glob of encrypted paths is required".to_string());
+        }
+        let mut keys = Vec::new();
+        for line in keyfile.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (id, key) = line
+                .split_once(char::is_whitespace)
+                .ok_or_else(|| "expected `<key id> <base64 key>` lines".to_string())?;
+            let key = STANDARD
+                .decode(key.trim())
+                .map_err(|e| format!("invalid key {}: {}", id, e))?;
+            if key.len() != KEY_SIZE {
+                return Err(format!("key {} must be {} bytes", id, KEY_SIZE));
+            }
+            if keys.iter().any(|(known, _)| known == id) {
+                return Err(format!("duplicate key {}", id));
+            }
+            keys.push((
+                id.to_string(),
+                XChaCha20Poly1305::new_from_slice(&key).map_err(|e| e.to_string())?,
+            ));
+        }
+        if keys.is_empty() {
+            return Err("no keys".to_string());
+        }
+        Ok(Encryption { paths, keys })
+    }
+
+    pub fn load<S: AsRef<str>, P: AsRef<Path>>(
+        paths: &[S],
+        keyfile: P,
+    ) -> Result<Self, GitDataStoreError> {
+        let invalid_config = |error: String| GitDataStoreError::InvalidConfig {
+            path: keyfile.as_ref().to_string_lossy().to_string(),
+            error,
+        };
+        let content =
+            fs::read_to_string(keyfile.as_ref()).map_err(|e| invalid_config(e.to_string()))?;
+        Encryption::new(PathGlobs::new(paths)?, &content).map_err(invalid_config)
+    }
+
+    /// Id of the key new content is encrypted with.
+    pub fn active_key(&self) -> &str {
+        &self.keys.last().expect("at least one key").0
+    }
+
+    /// Whether documents written at `path` are encrypted.
+    pub fn applies_to(&self, path: &str) -> bool {
+        !is_reserved_path(path) && self.paths.is_match(path)
+    }
+
+    pub fn encrypt(&self, path: &str, content: &[u8]) -> Result<Vec<u8>, GitDataStoreError> {
+        let (id, cipher) = self.keys.last().expect("at least one key");
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = cipher
+            .encrypt(
+                &nonce,
+                Payload {
+                    msg: content,
+                    aad: aad(path).as_bytes(),
+                },
+            )
+            .map_err(|e| encryption_error(path, e.to_string()))?;
+        let mut sealed = nonce.to_vec();
+        sealed.extend(ciphertext);
+        Ok(format!(
+            "{}key {}\n{}\n",
+            ENCRYPTED_HEADER,
+            id,
+            STANDARD.encode(sealed)
+        )
+        .into_bytes())
+    }
+
+    /// Decrypts `content` if it is encrypted and documents at `path` are encrypted, `None`
+    /// otherwise, so that plain documents which happen to look encrypted are read as they are.
+    pub fn decrypt(
+        &self,
+        path: &str,
+        content: &[u8],
+    ) -> Result<Option<Vec<u8>>, GitDataStoreError> {
+        let (id, sealed) = match parse(content) {
+            Some(parsed) if self.applies_to(path) => parsed,
+            _ => return Ok(None),
+        };
+        let cipher = self
+            .keys
+            .iter()
+            .find(|(known, _)| known == id)
+            .map(|(_, cipher)| cipher)
+            .ok_or_else(|| encryption_error(path, format!("unknown key {}", id)))?;
+        let sealed = STANDARD
+            .decode(sealed)
+            .map_err(|e| encryption_error(path, e.to_string()))?;
+        if sealed.len() < NONCE_SIZE {
+            return Err(encryption_error(path, "truncated content".to_string()));
+        }
+        let (nonce, ciphertext) = sealed.split_at(NONCE_SIZE);
+        cipher
+            .decrypt(
+                XNonce::from_slice(nonce),
+                Payload {
+                    msg: ciphertext,
+                    aad: aad(path).as_bytes(),
+                },
+            )
+            .map(Some)
+            .map_err(|_| encryption_error(path, "content was tampered with".to_string()))
+    }
+}
+
+/// Id of the key `content` is encrypted with, `None` if it is not encrypted.
+pub fn encryption_key(content: &[u8]) -> Option<&str> {
+    parse(content).map(|(id, _)| id)
+}
+
+pub(crate) fn is_encrypted(content: &[u8]) -> bool {
+    content.starts_with(ENCRYPTED_HEADER.as_bytes())
+}
+
+/// Decrypts `content` with `encryption`, failing if it is encrypted and there is no key:
+/// without the configuration the encrypted paths are unknown.
+pub(crate) fn decrypt(
+    encryption: Option<&Encryption>,
+    path: &str,
+    content: &[u8],
+) -> Result<Option<Vec<u8>>, GitDataStoreError> {
+    match encryption {
+        Some(encryption) => encryption.decrypt(path, content),
+        None if encryption_key(content).is_some() => Err(encryption_error(
+            path,
+            "content is encrypted but no keys are configured".to_string(),
+        )),
+        None => Ok(None),
+    }
+}
+
+fn parse(content: &[u8]) -> Option<(&str, &str)> {
+    let content = content.strip_prefix(ENCRYPTED_HEADER.as_bytes())?;
+    let content = std::str::from_utf8(content).ok()?;
+    let mut lines = content.lines();
+    let id = lines.next()?.strip_prefix("key ")?;
+    Some((id, lines.next()?))
+}
+
+fn aad(path: &str) -> &str {
+    path.trim_matches('/')
+}
+
+fn encryption_error(path: &str, error: String) -> GitDataStoreError {
+    GitDataStoreError::Encryption {
+        path: path.to_string(),
+        error,
+    }
+}
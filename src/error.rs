@@ -1,4 +1,10 @@
-use actix_web::{dev::HttpResponseBuilder, http::StatusCode, HttpResponse};
+use crate::{acl::Permission, merge::MergeConflict, validation::ValidationFailure};
+use actix_web::{
+    dev::HttpResponseBuilder,
+    error::BlockingError,
+    http::{header::WWW_AUTHENTICATE, StatusCode},
+    HttpResponse,
+};
 use serde::Serialize;
 use thiserror::Error;
 
@@ -7,6 +13,12 @@ pub enum GitDataStoreError {
     #[error("Git2 Error {}", .0)]
     Git2(#[from] git2::Error),
 
+    #[error("IO Error {}", .0)]
+    Io(#[from] std::io::Error),
+
+    #[error("Failed to read request body {}", .0)]
+    RequestBody(String),
+
     #[error("Invalid revision could not be found {}", .0)]
     RevNotFound(String),
 
@@ -18,11 +30,90 @@ pub enum GitDataStoreError {
         path: String,
         parent_commit_id: String,
     },
+
+    #[error("Path is reserved and cannot be written to directly {}", .0)]
+    ReservedPath(String),
+
+    #[error("Invalid metadata for {}: {}", .path, .error)]
+    InvalidMetadata { path: String, error: String },
+
+    #[error("Invalid configuration {}: {}", .path, .error)]
+    InvalidConfig { path: String, error: String },
+
+    #[error("Validation failed: {}", .0.iter().map(|f| format!("{} ({}): {}", f.path, f.validator, f.message)).collect::<Vec<_>>().join(", "))]
+    ValidationFailed(Vec<ValidationFailure>),
+
+    #[error("Unsupported patch media type {}, expected application/json-patch+json or application/merge-patch+json", .0)]
+    UnsupportedPatch(String),
+
+    #[error("Patch cannot be applied to {}: {}", .path, .error)]
+    PatchFailed { path: String, error: String },
+
+    #[error("Document {} is not JSON: {}", .path, .error)]
+    NotJson { path: String, error: String },
+
+    #[error("Authentication required: {}", .0)]
+    Unauthenticated(String),
+
+    #[error("Forbidden to {} {}", .permission, .path)]
+    Forbidden {
+        path: String,
+        permission: Permission,
+    },
+
+    #[error("Failed to sign commit: {}", .0)]
+    Signing(String),
+
+    #[error("Encryption failed for {}: {}", .path, .error)]
+    Encryption { path: String, error: String },
+
+    #[error("{} failed: {}", .command, .error)]
+    GitCommand { command: String, error: String },
+
+    #[error("Invalid tag name {}: {}", .name, .error)]
+    InvalidTagName { name: String, error: String },
+
+    #[error("Tag already exists {}", .0)]
+    TagExists(String),
+
+    #[error("Merge resulted in conflicts on paths: {}", .0.iter().map(|c| c.path.as_str()).collect::<Vec<_>>().join(", "))]
+    MergeConflicts(Vec<MergeConflict>),
+
+    #[error("Branch cannot be fast-forwarded. branch: {}", .branch)]
+    NonFastForward { branch: String },
+
+    #[error("Push of {} was rejected by the remote: {}", .reference, .message)]
+    PushRejected { reference: String, message: String },
+
+    #[error("The branch was concurrently updated by other writers too many times. branch: {}", .branch)]
+    ConcurrentUpdate { branch: String },
+
+    #[error("Commit {} is not in the history of branch {}, which may have been rewritten", .commit_id, .branch)]
+    NotAncestor { commit_id: String, branch: String },
+
+    #[error("Too many clients are following changes, the limit is {}", .0)]
+    TooManyClients(usize),
+
+    #[error("The blocking operation was canceled, the thread pool is gone")]
+    Canceled,
+}
+
+impl From<BlockingError<GitDataStoreError>> for GitDataStoreError {
+    fn from(err: BlockingError<GitDataStoreError>) -> Self {
+        match err {
+            BlockingError::Error(err) => err,
+            BlockingError::Canceled => GitDataStoreError::Canceled,
+        }
+    }
 }
 
 #[derive(Serialize)]
 pub struct ErrorJson {
     error: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    conflicts: Option<Vec<MergeConflict>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    failures: Option<Vec<ValidationFailure>>,
 }
 
 impl actix_web::error::ResponseError for GitDataStoreError {
@@ -30,15 +121,54 @@ impl actix_web::error::ResponseError for GitDataStoreError {
         let error_str = self.to_string();
         // TODO add error logger
         println!("[ERROR] {}", self);
-        HttpResponseBuilder::new(self.status_code()).json(ErrorJson { error: error_str })
+        let conflicts = match self {
+            GitDataStoreError::MergeConflicts(conflicts) => Some(conflicts.clone()),
+            _ => None,
+        };
+        let failures = match self {
+            GitDataStoreError::ValidationFailed(failures) => Some(failures.clone()),
+            _ => None,
+        };
+        let mut response = HttpResponseBuilder::new(self.status_code());
+        if let GitDataStoreError::Unauthenticated(..) = self {
+            response.header(WWW_AUTHENTICATE, "Basic realm=\"nosql-git\", Bearer");
+        }
+        response.json(ErrorJson {
+            error: error_str,
+            conflicts,
+            failures,
+        })
     }
 
     fn status_code(&self) -> StatusCode {
         match *self {
             GitDataStoreError::Git2(..) => StatusCode::INTERNAL_SERVER_ERROR,
+            GitDataStoreError::Io(..) => StatusCode::INTERNAL_SERVER_ERROR,
+            GitDataStoreError::RequestBody(..) => StatusCode::BAD_REQUEST,
             GitDataStoreError::NonUtf8Blob { .. } => StatusCode::INTERNAL_SERVER_ERROR,
             GitDataStoreError::RevNotFound(..) => StatusCode::NOT_FOUND,
             GitDataStoreError::ConflictOnWrite { .. } => StatusCode::CONFLICT,
+            GitDataStoreError::ConcurrentUpdate { .. } => StatusCode::CONFLICT,
+            GitDataStoreError::NotAncestor { .. } => StatusCode::CONFLICT,
+            GitDataStoreError::TooManyClients(..) => StatusCode::SERVICE_UNAVAILABLE,
+            GitDataStoreError::Canceled => StatusCode::INTERNAL_SERVER_ERROR,
+            GitDataStoreError::ReservedPath(..) => StatusCode::BAD_REQUEST,
+            GitDataStoreError::MergeConflicts(..) => StatusCode::CONFLICT,
+            GitDataStoreError::NonFastForward { .. } => StatusCode::CONFLICT,
+            GitDataStoreError::PushRejected { .. } => StatusCode::BAD_GATEWAY,
+            GitDataStoreError::InvalidMetadata { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+            GitDataStoreError::InvalidConfig { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+            GitDataStoreError::ValidationFailed(..) => StatusCode::UNPROCESSABLE_ENTITY,
+            GitDataStoreError::UnsupportedPatch(..) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            GitDataStoreError::PatchFailed { .. } => StatusCode::CONFLICT,
+            GitDataStoreError::NotJson { .. } => StatusCode::UNPROCESSABLE_ENTITY,
+            GitDataStoreError::Unauthenticated(..) => StatusCode::UNAUTHORIZED,
+            GitDataStoreError::Forbidden { .. } => StatusCode::FORBIDDEN,
+            GitDataStoreError::Signing(..) => StatusCode::INTERNAL_SERVER_ERROR,
+            GitDataStoreError::Encryption { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+            GitDataStoreError::GitCommand { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+            GitDataStoreError::InvalidTagName { .. } => StatusCode::BAD_REQUEST,
+            GitDataStoreError::TagExists(..) => StatusCode::CONFLICT,
         }
     }
 }
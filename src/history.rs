@@ -1,6 +1,10 @@
 use std::{collections::HashMap, path::Path, time::Instant};
 
-use crate::error::GitDataStoreError;
+use crate::{
+    changes::{self, PathChange},
+    error::GitDataStoreError,
+    signing::SignatureStatus,
+};
 use chrono::{DateTime, FixedOffset, TimeZone};
 use git2::{Commit, DiffOptions, Oid, Repository, Revwalk, Time};
 use serde::Serialize;
@@ -11,7 +15,11 @@ pub struct HistoryEntry {
     pub commit_id: String,
     pub message: Option<String>,
     pub author: String,
+    pub committer: String,
     pub stats: HistoryStats,
+    /// Only set when signatures are verified.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signature: Option<SignatureStatus>,
 }
 
 #[derive(Serialize, Debug)]
@@ -55,6 +63,13 @@ impl HistoryIterator {
             path: path.to_string(),
         })
     }
+
+    /// Paths changed by the commit `commit_id`, like `GitDataStore::commit_changes` but
+    /// without opening the repository again for each commit of the history.
+    pub fn commit_changes(&self, commit_id: &str) -> Result<Vec<PathChange>, GitDataStoreError> {
+        let commit = self.repo.find_commit(Oid::from_str(commit_id)?)?;
+        changes::commit_paths(&self.repo, &commit, None)
+    }
 }
 
 /// Algorithm taken from https://github.com/libgit2/libgit2sharp/blob/f916e79575bea0a99d3c67249090f51ff62d4e23/LibGit2Sharp/Core/FileHistory.cs
@@ -253,7 +268,7 @@ pub fn git_log<'repo>(repo: Repository) -> Result<HistoryIterator, GitDataStoreE
     Ok(HistoryIterator::new(repo))
 }
 
-fn map_rev(
+pub(crate) fn map_rev(
     repo: &Repository,
     rev: Result<Oid, git2::Error>,
 ) -> Result<HistoryEntry, GitDataStoreError> {
@@ -279,8 +294,10 @@ fn map_rev(
             .timestamp(commit_time.seconds(), 0),
         commit_id: commit.id().to_string(),
         author: commit.author().to_string(),
+        committer: commit.committer().to_string(),
         message: commit.message().map(|m| m.to_string()),
         stats: stats,
+        signature: None,
     });
     x
 }
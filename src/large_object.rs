@@ -0,0 +1,305 @@
+use crate::error::GitDataStoreError;
+use git2::{ObjectType, Odb, Oid, Repository, Tree};
+use serde::Serialize;
+use std::{
+    collections::HashSet,
+    fs::{self, File},
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+const POINTER_HEADER: &str = "nosql-git-large-object v1\n";
+
+/// Pointer documents are tiny, any blob larger than this cannot be a pointer.
+pub(crate) const MAX_POINTER_SIZE: usize = 256;
+
+/// Content addressed directory holding the content of files larger than `threshold`.
+/// The git tree only holds a small pointer document referencing the content by its
+/// git blob id, which keeps packs small and clones fast.
+#[derive(Debug, Clone)]
+pub struct LargeObjectStore {
+    dir: PathBuf,
+    threshold: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LargeObjectPointer {
+    pub oid: Oid,
+    pub size: usize,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct LargeObjectGcReport {
+    pub referenced: usize,
+    pub removed: Vec<String>,
+}
+
+impl LargeObjectPointer {
+    pub fn parse(content: &[u8]) -> Option<Self> {
+        if content.len() > MAX_POINTER_SIZE {
+            return None;
+        }
+        let content = std::str::from_utf8(content).ok()?;
+        let mut lines = content.strip_prefix(POINTER_HEADER)?.lines();
+        let oid = Oid::from_str(lines.next()?.strip_prefix("oid ")?).ok()?;
+        let size = lines.next()?.strip_prefix("size ")?.parse().ok()?;
+        Some(LargeObjectPointer { oid, size })
+    }
+
+    pub fn to_document(&self) -> String {
+        format!("{}oid {}\nsize {}\n", POINTER_HEADER, self.oid, self.size)
+    }
+}
+
+impl LargeObjectStore {
+    pub fn new<P: AsRef<Path>>(dir: P, threshold: usize) -> Self {
+        LargeObjectStore {
+            dir: dir.as_ref().to_path_buf(),
+            threshold,
+        }
+    }
+
+    pub fn threshold(&self) -> usize {
+        self.threshold
+    }
+
+    pub fn is_large(&self, size: usize) -> bool {
+        size > self.threshold
+    }
+
+    /// Stores `data` and returns the pointer to commit in its place.
+    pub fn store(&self, data: &[u8]) -> Result<LargeObjectPointer, GitDataStoreError> {
+        let oid = Oid::hash_object(ObjectType::Blob, data)?;
+        self.write_object(oid, data)?;
+        Ok(LargeObjectPointer {
+            oid,
+            size: data.len(),
+        })
+    }
+
+    /// Writes everything from `reader` and returns the id of the blob to commit: the content
+    /// itself when it is not larger than the threshold, otherwise a pointer to the content,
+    /// which is streamed into this store without going through the object database. Content
+    /// which looks like a pointer is stored here too, so that it is not read as the large
+    /// object it points to.
+    pub fn write_blob<R: Read>(
+        &self,
+        repo: &Repository,
+        mut reader: R,
+    ) -> Result<Oid, GitDataStoreError> {
+        let mut head = Vec::new();
+        (&mut reader)
+            .take(self.threshold as u64 + 1)
+            .read_to_end(&mut head)?;
+        if LargeObjectPointer::parse(&head).is_some() {
+            let pointer = self.store(&head)?;
+            return Ok(repo.blob(pointer.to_document().as_bytes())?);
+        }
+        if !self.is_large(head.len()) {
+            return Ok(repo.blob(&head)?);
+        }
+
+        fs::create_dir_all(&self.dir)?;
+        let mut file = tempfile::NamedTempFile::new_in(&self.dir)?;
+        file.write_all(&head)?;
+        let size = head.len() as u64 + io::copy(&mut reader, &mut file)?;
+        file.as_file().sync_all()?;
+        let oid = Oid::hash_file(ObjectType::Blob, file.path())?;
+
+        let path = self.object_path(oid);
+        if path.exists() {
+            touch(&path)?;
+        } else {
+            fs::create_dir_all(path.parent().expect("object path has a parent"))?;
+            file.persist(&path).map_err(|e| e.error)?;
+        }
+        let pointer = LargeObjectPointer {
+            oid,
+            size: size as usize,
+        };
+        Ok(repo.blob(pointer.to_document().as_bytes())?)
+    }
+
+    /// Copies a blob already written in the repository and returns the pointer to commit in its place.
+    pub fn store_blob(
+        &self,
+        repo: &Repository,
+        blob_id: Oid,
+    ) -> Result<LargeObjectPointer, GitDataStoreError> {
+        let blob = repo.find_blob(blob_id)?;
+        self.write_object(blob_id, blob.content())?;
+        Ok(LargeObjectPointer {
+            oid: blob_id,
+            size: blob.size(),
+        })
+    }
+
+    pub fn open(&self, pointer: &LargeObjectPointer) -> Result<File, GitDataStoreError> {
+        Ok(File::open(self.object_path(pointer.oid))?)
+    }
+
+    pub fn read(&self, pointer: &LargeObjectPointer) -> Result<Vec<u8>, GitDataStoreError> {
+        Ok(fs::read(self.object_path(pointer.oid))?)
+    }
+
+    /// Removes the large objects which are not referenced by any commit reachable from a
+    /// reference of the repository. Objects modified less than `grace_period` ago are kept
+    /// since they may belong to a write which has not been committed yet.
+    pub fn gc(
+        &self,
+        repo: &Repository,
+        grace_period: Duration,
+    ) -> Result<LargeObjectGcReport, GitDataStoreError> {
+        let referenced = referenced_pointers(repo)?;
+        let now = SystemTime::now();
+        let mut removed = Vec::new();
+
+        if !self.dir.exists() {
+            return Ok(LargeObjectGcReport {
+                referenced: referenced.len(),
+                removed,
+            });
+        }
+
+        for prefix_dir in fs::read_dir(&self.dir)? {
+            let prefix_dir = prefix_dir?;
+            if !prefix_dir.file_type()?.is_dir() {
+                continue;
+            }
+            let prefix = prefix_dir.file_name().to_string_lossy().to_string();
+            for object in fs::read_dir(prefix_dir.path())? {
+                let object = object?;
+                let name = format!("{}{}", prefix, object.file_name().to_string_lossy());
+                let oid = match Oid::from_str(&name) {
+                    Ok(oid) if name.len() == 40 => oid,
+                    _ => continue,
+                };
+                if referenced.contains(&oid) {
+                    continue;
+                }
+                let age = now
+                    .duration_since(object.metadata()?.modified()?)
+                    .unwrap_or_default();
+                if age < grace_period {
+                    continue;
+                }
+                fs::remove_file(object.path())?;
+                removed.push(name);
+            }
+        }
+
+        Ok(LargeObjectGcReport {
+            referenced: referenced.len(),
+            removed,
+        })
+    }
+
+    /// Removes the large objects of `pointers` which are no longer referenced by any commit
+    /// reachable from a reference of the repository, returning their ids.
+    pub(crate) fn remove_unreferenced(
+        &self,
+        repo: &Repository,
+        pointers: &[LargeObjectPointer],
+    ) -> Result<Vec<String>, GitDataStoreError> {
+        if pointers.is_empty() {
+            return Ok(Vec::new());
+        }
+        let referenced = referenced_pointers(repo)?;
+        let mut removed = Vec::new();
+        for pointer in pointers {
+            let path = self.object_path(pointer.oid);
+            if !referenced.contains(&pointer.oid) && path.exists() {
+                fs::remove_file(path)?;
+                removed.push(pointer.oid.to_string());
+            }
+        }
+        Ok(removed)
+    }
+
+    fn object_path(&self, oid: Oid) -> PathBuf {
+        let hex = oid.to_string();
+        self.dir.join(&hex[..2]).join(&hex[2..])
+    }
+
+    fn write_object(&self, oid: Oid, data: &[u8]) -> Result<(), GitDataStoreError> {
+        let path = self.object_path(oid);
+        if path.exists() {
+            // the object is referenced again, the grace period of `gc` starts over
+            return Ok(touch(&path)?);
+        }
+        let parent = path.parent().expect("object path has a parent");
+        fs::create_dir_all(parent)?;
+
+        // write to a temporary file first so that readers never see a partial object
+        let mut file = tempfile::NamedTempFile::new_in(parent)?;
+        file.write_all(data)?;
+        file.as_file().sync_all()?;
+        file.persist(&path).map_err(|e| e.error)?;
+        Ok(())
+    }
+}
+
+fn touch(path: &Path) -> io::Result<()> {
+    File::options()
+        .write(true)
+        .open(path)?
+        .set_modified(SystemTime::now())
+}
+
+/// Collects the large objects referenced by pointers in every commit reachable from a reference.
+fn referenced_pointers(repo: &Repository) -> Result<HashSet<Oid>, GitDataStoreError> {
+    let mut rev_walk = repo.revwalk()?;
+    for reference in repo.references()? {
+        // references which do not point to commits, e.g. tags of blobs, cannot hold pointers
+        if let Ok(commit) = reference?.peel_to_commit() {
+            rev_walk.push(commit.id())?;
+        }
+    }
+
+    let odb = repo.odb()?;
+    let mut seen = HashSet::new();
+    let mut referenced = HashSet::new();
+    for rev in rev_walk {
+        let tree = repo.find_commit(rev?)?.tree()?;
+        if seen.insert(tree.id()) {
+            collect_pointers(repo, &odb, &tree, &mut seen, &mut referenced)?;
+        }
+    }
+    Ok(referenced)
+}
+
+/// Trees and blobs already in `seen` are skipped, so that unchanged subtrees are only
+/// visited once across all commits.
+fn collect_pointers(
+    repo: &Repository,
+    odb: &Odb,
+    tree: &Tree,
+    seen: &mut HashSet<Oid>,
+    referenced: &mut HashSet<Oid>,
+) -> Result<(), GitDataStoreError> {
+    for entry in tree.iter() {
+        if !seen.insert(entry.id()) {
+            continue;
+        }
+        match entry.kind() {
+            Some(ObjectType::Tree) => {
+                let subtree = repo.find_tree(entry.id())?;
+                collect_pointers(repo, odb, &subtree, seen, referenced)?;
+            }
+            Some(ObjectType::Blob) => {
+                let (size, _kind) = odb.read_header(entry.id())?;
+                if size > MAX_POINTER_SIZE {
+                    continue;
+                }
+                if let Some(pointer) =
+                    LargeObjectPointer::parse(repo.find_blob(entry.id())?.content())
+                {
+                    referenced.insert(pointer.oid);
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
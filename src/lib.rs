@@ -1,32 +1,94 @@
+use blob::{BlobCodec, BlobReader, BlobSource, BlobUpload};
+use changes::{Changes, DocumentChange, PathChange};
+use chrono::{DateTime, Utc};
+use compaction::{CompactionMode, CompactionReport, ARCHIVE_REFS_PREFIX};
+use credentials::Credentials;
+use encryption::{Encryption, ENCRYPTED_HEADER};
 use error::GitDataStoreError;
 use git2::{
-    Commit, DiffOptions, FileMode, Index, IndexEntry, IndexTime, Oid, Reference, Repository,
+    build::TreeUpdateBuilder, Commit, DiffOptions, ErrorCode, FileMode, Index, IndexEntry,
+    IndexTime, ObjectType, Oid, Reference, Repository, Tree, TreeWalkMode, TreeWalkResult,
 };
-use history::HistoryIterator;
+use history::{HistoryEntry, HistoryIterator};
+use large_object::{LargeObjectGcReport, LargeObjectPointer, LargeObjectStore, MAX_POINTER_SIZE};
+use maintenance::{MaintenanceOptions, MaintenanceReport};
+use merge::MergeStrategy;
+use metadata::{metadata_path, Metadata, METADATA_DIR};
 use parking_lot::Mutex;
+use patch::Patch;
+use pointer::PointerEntry;
+use purge::{HistoryRewriter, PurgeReport, PurgeTarget};
+use schema::SchemaEntry;
 use serde::Serialize;
-use std::path::Path;
+use signing::{CommitSigner, CommitVerifier, SignatureStatus};
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, HashSet},
+    io::{self, Read},
+    path::Path,
+    thread,
+    time::Duration,
+};
+use tag::{TagEntry, TAGS_REFS_PREFIX};
+use validation::ValidationPipeline;
 
+pub mod acl;
+pub mod auth;
+pub mod blob;
+pub mod changes;
 pub mod clone;
 pub mod commit;
 pub mod commit_to_branch;
+pub mod compaction;
+pub mod credentials;
+pub mod encryption;
 pub mod error;
 pub mod history;
+pub mod large_object;
+pub mod maintenance;
+pub mod merge;
+pub mod metadata;
+pub mod patch;
+pub mod path_glob;
+pub mod pointer;
+pub mod purge;
+pub mod remote;
+pub mod replica;
 pub mod route;
+pub mod schema;
+pub mod signing;
+pub mod tag;
+pub mod validation;
+pub mod webhook;
 
 const ROOT_PATHS: &'static [&'static str] = &["", "/", "."];
 
+/// Number of times a write is attempted when the primary branch keeps being moved
+/// by other writers sharing the repository.
+const MAX_REF_UPDATE_ATTEMPTS: usize = 10;
+
 #[derive(Debug)]
 pub struct GitDataStore {
     repo_path: String,
     primary_branch: String,
     mutex: Mutex<()>,
+    large_objects: Option<LargeObjectStore>,
+    /// Pointer blobs written by this store. Only these are committed as pointers, any other
+    /// blob which looks like a pointer is stored as a large object like large content is.
+    written_pointers: Mutex<HashSet<Oid>>,
+    encryption: Option<Encryption>,
+    credentials: Credentials,
+    validation: ValidationPipeline,
+    signer: Option<CommitSigner>,
+    verifier: CommitVerifier,
 }
 
 #[derive(Clone, Debug, Serialize, PartialEq, Eq)]
 pub struct GitEntry {
     pub data: GitData,
     pub commit_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<Metadata>,
 }
 
 #[derive(Debug, Clone, Serialize, PartialEq, Eq)]
@@ -62,253 +124,1630 @@ impl GitData {
         } else {
             false
         }
-    }
+    }
+
+    pub fn is_file(&self) -> bool {
+        if let GitData::File { .. } = self {
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn file(&self) -> Option<&str> {
+        if let GitData::File { data } = self {
+            Some(data)
+        } else {
+            None
+        }
+    }
+}
+
+impl GitDataStore {
+    pub fn new(repo_path: &str, primary_branch: &str) -> Self {
+        GitDataStore {
+            repo_path: repo_path.to_string(),
+            primary_branch: primary_branch.to_string(),
+            mutex: Mutex::new(()),
+            large_objects: None,
+            written_pointers: Mutex::new(HashSet::new()),
+            encryption: None,
+            credentials: Credentials::default(),
+            validation: ValidationPipeline::new(),
+            signer: None,
+            verifier: CommitVerifier::default(),
+        }
+    }
+
+    /// Files larger than the threshold of `large_objects` are stored outside of the
+    /// repository and replaced by a pointer document in the git tree. Reads resolve
+    /// pointers transparently.
+    pub fn with_large_objects(mut self, large_objects: LargeObjectStore) -> Self {
+        self.large_objects = Some(large_objects);
+        self
+    }
+
+    /// Documents whose path matches the globs of `encryption` are encrypted before they are
+    /// written to the repository. Reads decrypt them transparently.
+    pub fn with_encryption(mut self, encryption: Encryption) -> Self {
+        self.encryption = Some(encryption);
+        self
+    }
+
+    /// Every commit is checked by `validation` before it is made. Commits with documents
+    /// failing validation are rejected with `GitDataStoreError::ValidationFailed`.
+    pub fn with_validation(mut self, validation: ValidationPipeline) -> Self {
+        self.validation = validation;
+        self
+    }
+
+    /// Every commit made by the store is signed by `signer`.
+    pub fn with_signer(mut self, signer: CommitSigner) -> Self {
+        self.signer = Some(signer);
+        self
+    }
+
+    /// Keys trusted when verifying commit signatures.
+    pub fn with_verifier(mut self, verifier: CommitVerifier) -> Self {
+        self.verifier = verifier;
+        self
+    }
+
+    /// Credentials used to authenticate against remotes when fetching and pushing.
+    pub fn with_credentials(mut self, credentials: Credentials) -> Self {
+        self.credentials = credentials;
+        self
+    }
+
+    pub fn primary_branch(&self) -> &str {
+        &self.primary_branch
+    }
+
+    /// Latest commit of the primary branch.
+    pub fn head(&self) -> Result<HistoryEntry, GitDataStoreError> {
+        let repo = Repository::open(&self.repo_path)?;
+        let head = repo.refname_to_id(&format!("refs/heads/{}", self.primary_branch))?;
+        history::map_rev(&repo, Ok(head))
+    }
+
+    /// Signature status of the commit.
+    pub fn verify(&self, commit_id: &str) -> Result<SignatureStatus, GitDataStoreError> {
+        let repo = Repository::open(&self.repo_path)?;
+        let commit = find_commit(&repo, commit_id)?;
+        self.verifier.verify(&repo, commit.id())
+    }
+
+    /// Lists the commits made on the primary branch after `since`, oldest first, with the
+    /// documents they changed. When `prefix` is given, only documents under it are listed
+    /// and commits which changed none of them are left out. Fails with `NotAncestor` when
+    /// `since` is not in the history of the branch, e.g. after the history was rewritten.
+    pub fn changes_since(
+        &self,
+        since: &str,
+        prefix: Option<&str>,
+    ) -> Result<Changes, GitDataStoreError> {
+        let repo = Repository::open(&self.repo_path)?;
+        let since_id = find_commit(&repo, since)?.id();
+        let head = repo.refname_to_id(&format!("refs/heads/{}", self.primary_branch))?;
+        if since_id != head && !repo.graph_descendant_of(head, since_id)? {
+            return Err(GitDataStoreError::NotAncestor {
+                commit_id: since.to_string(),
+                branch: self.primary_branch.clone(),
+            });
+        }
+        changes::changes_since(&repo, since_id, head, prefix)
+    }
+
+    /// Documents changed by the commit compared to its first parent.
+    pub fn commit_changes(&self, commit_id: &str) -> Result<Vec<PathChange>, GitDataStoreError> {
+        let repo = Repository::open(&self.repo_path)?;
+        let commit = find_commit(&repo, commit_id)?;
+        changes::commit_paths(&repo, &commit, None)
+    }
+
+    /// Returns the latest version of the document at `path` if it or its metadata differs
+    /// from its version at `since`. Versions are compared by blob id, like
+    /// `history::FileHistoryIterator` does.
+    pub fn changed_since(
+        &self,
+        since: &str,
+        path: &str,
+    ) -> Result<Option<DocumentChange>, GitDataStoreError> {
+        let repo = Repository::open(&self.repo_path)?;
+        let since_commit = find_commit(&repo, since)?;
+        let main_ref = repo.find_reference(&format!("refs/heads/{}", self.primary_branch))?;
+        let head_commit = main_ref.peel_to_commit()?;
+
+        let (since_tree, head_tree) = (since_commit.tree()?, head_commit.tree()?);
+        let meta_path = metadata_path(path);
+        if entry_id(&since_tree, path)? == entry_id(&head_tree, path)?
+            && entry_id(&since_tree, &meta_path)? == entry_id(&head_tree, &meta_path)?
+        {
+            return Ok(None);
+        }
+        Ok(Some(DocumentChange {
+            commit_id: head_commit.id().to_string(),
+            entry: read_entry_from_tree(&repo, &head_commit, path, self.codec())?,
+        }))
+    }
+
+    pub fn read_latest(&self, path: &str) -> Result<Option<GitEntry>, GitDataStoreError> {
+        let repo = Repository::open(&self.repo_path)?;
+        let main_ref = repo.find_reference(&format!("refs/heads/{}", self.primary_branch))?;
+        let commit = main_ref.peel_to_commit()?;
+
+        read_entry_from_tree(&repo, &commit, path, self.codec())
+    }
+
+    pub fn read(&self, commit_id: &str, path: &str) -> Result<Option<GitEntry>, GitDataStoreError> {
+        let repo = Repository::open(&self.repo_path)?;
+        let commit = find_commit(&repo, commit_id)?;
+
+        read_entry_from_tree(&repo, &commit, path, self.codec())
+    }
+
+    /// Reads the value at `pointer` in the JSON document at path in the latest version.
+    /// `pointer` is a JSON pointer or a dotted path, see [`pointer::to_json_pointer`].
+    /// Returns `None` if there is no document at path or no value at `pointer`.
+    pub fn read_pointer_latest(
+        &self,
+        path: &str,
+        pointer: &str,
+    ) -> Result<Option<PointerEntry>, GitDataStoreError> {
+        match self.read_latest(path)? {
+            Some(entry) => pointer::select(path, entry, pointer),
+            None => Ok(None),
+        }
+    }
+
+    /// Reads the value at `pointer` in the JSON document at commit id and path.
+    pub fn read_pointer(
+        &self,
+        commit_id: &str,
+        path: &str,
+        pointer: &str,
+    ) -> Result<Option<PointerEntry>, GitDataStoreError> {
+        match self.read(commit_id, path)? {
+            Some(entry) => pointer::select(path, entry, pointer),
+            None => Ok(None),
+        }
+    }
+
+    /// Like `read_latest` but for streaming the content of a file.
+    /// Returns `None` if there is no file at path.
+    pub fn read_blob_latest(&self, path: &str) -> Result<Option<BlobReader>, GitDataStoreError> {
+        let repo = Repository::open(&self.repo_path)?;
+        let blob = {
+            let main_ref = repo.find_reference(&format!("refs/heads/{}", self.primary_branch))?;
+            let commit = main_ref.peel_to_commit()?;
+            find_blob_in_tree(&repo, &commit, path)?
+        };
+
+        blob.map(|blob| self.blob_reader(repo, path, blob))
+            .transpose()
+    }
+
+    /// Like `read` but for streaming the content of a file.
+    /// Returns `None` if there is no file at path.
+    pub fn read_blob(
+        &self,
+        commit_id: &str,
+        path: &str,
+    ) -> Result<Option<BlobReader>, GitDataStoreError> {
+        let repo = Repository::open(&self.repo_path)?;
+        let blob = {
+            let commit = find_commit(&repo, commit_id)?;
+            find_blob_in_tree(&repo, &commit, path)?
+        };
+
+        blob.map(|blob| self.blob_reader(repo, path, blob))
+            .transpose()
+    }
+
+    /// Starts streaming content into the repository. The resulting blob id can then be
+    /// committed at a path with `put_blob` or `put_blob_latest`. Content of encrypted paths
+    /// should be written with `write_blob_for` instead, otherwise the plain content is left
+    /// in the object database until it is pruned.
+    pub fn blob_upload(&self) -> Result<BlobUpload, GitDataStoreError> {
+        let repo = Repository::open(&self.repo_path)?;
+        Ok(BlobUpload::new(repo))
+    }
+
+    /// Writes everything from `reader` into the repository and returns the blob id. Content
+    /// larger than the threshold of the large object store is streamed into the store and
+    /// the id of a pointer to it is returned instead.
+    pub fn write_blob<R: Read>(&self, mut reader: R) -> Result<String, GitDataStoreError> {
+        if let Some(large_objects) = &self.large_objects {
+            let repo = Repository::open(&self.repo_path)?;
+            let blob_id = large_objects.write_blob(&repo, reader)?;
+            // content which looks like a pointer is stored as a large object, so any pointer
+            // returned here was written by the store
+            if is_pointer_blob(&repo, blob_id)? {
+                self.written_pointers.lock().insert(blob_id);
+            }
+            return Ok(blob_id.to_string());
+        }
+        let upload = self.blob_upload()?;
+        let mut writer = upload.writer()?;
+        io::copy(&mut reader, &mut writer)?;
+        Ok(writer.commit()?.to_string())
+    }
+
+    /// Like `write_blob` for content which is committed at `path`. When documents at `path`
+    /// are encrypted, the content is read into memory and encrypted before it is written, so
+    /// that the plain content never reaches the object database.
+    pub fn write_blob_for<R: Read>(
+        &self,
+        path: &str,
+        mut reader: R,
+    ) -> Result<String, GitDataStoreError> {
+        match &self.encryption {
+            Some(encryption) if encryption.applies_to(path) => {
+                let mut content = Vec::new();
+                reader.read_to_end(&mut content)?;
+                let repo = Repository::open(&self.repo_path)?;
+                let encrypted = encryption.encrypt(path, &content)?;
+                Ok(self.write_content(&repo, &encrypted)?.to_string())
+            }
+            _ => self.write_blob(reader),
+        }
+    }
+
+    /// Whether documents written at `path` are encrypted.
+    pub fn encrypts(&self, path: &str) -> bool {
+        self.encryption
+            .as_ref()
+            .is_some_and(|encryption| encryption.applies_to(path))
+    }
+
+    pub fn put(
+        &self,
+        parent_rev_id: &str,
+        path: &str,
+        data: &str,
+        overwrite: bool,
+        signature: Option<&Signature>,
+        commit_msg: Option<&str>,
+    ) -> Result<String, GitDataStoreError> {
+        self.put_with_metadata(
+            parent_rev_id,
+            path,
+            data,
+            None,
+            overwrite,
+            signature,
+            commit_msg,
+        )
+    }
+
+    /// Like `put` but also replaces the metadata of the document when `metadata` is given.
+    /// The metadata of the document is left unchanged otherwise.
+    #[allow(clippy::too_many_arguments)]
+    pub fn put_with_metadata(
+        &self,
+        parent_rev_id: &str,
+        path: &str,
+        data: &str,
+        metadata: Option<&Metadata>,
+        overwrite: bool,
+        signature: Option<&Signature>,
+        commit_msg: Option<&str>,
+    ) -> Result<String, GitDataStoreError> {
+        check_path(path)?;
+        // get last commit from primary branch and parent commit
+        // if they are the same or the overwrite flag is set, create new commit with that as parent and update primary branch
+        // if they are not the same, diff between the 2 commits and check that path hasn't been updated since parent commit
+        // if it has been updated, create conflict error
+        let repo = Repository::open(&self.repo_path)?;
+
+        let parent_commit = find_commit(&repo, parent_rev_id)?;
+
+        // lock mutex
+        let _mutex = self.mutex.lock();
+        self.commit_on_primary_branch(
+            &repo,
+            signature,
+            commit_msg.unwrap_or(format!("Updated {}", path).as_str()),
+            |head_commit| {
+                check_conflict(&repo, path, &parent_commit, head_commit, overwrite)?;
+                self.create_tree(&repo, path, data, metadata, head_commit)
+            },
+        )
+    }
+
+    pub fn put_latest(
+        &self,
+        path: &str,
+        data: &str,
+        signature: Option<&Signature>,
+        commit_msg: Option<&str>,
+    ) -> Result<String, GitDataStoreError> {
+        self.put_latest_with_metadata(path, data, None, signature, commit_msg)
+    }
+
+    /// Like `put_latest` but also replaces the metadata of the document when `metadata` is given.
+    pub fn put_latest_with_metadata(
+        &self,
+        path: &str,
+        data: &str,
+        metadata: Option<&Metadata>,
+        signature: Option<&Signature>,
+        commit_msg: Option<&str>,
+    ) -> Result<String, GitDataStoreError> {
+        check_path(path)?;
+        let repo = Repository::open(&self.repo_path)?;
+
+        let _mutex = self.mutex.lock();
+        self.commit_on_primary_branch(
+            &repo,
+            signature,
+            commit_msg.unwrap_or(format!("Updated {}", path).as_str()),
+            |head_commit| self.create_tree(&repo, path, data, metadata, head_commit),
+        )
+    }
+
+    /// Like `put_with_metadata` but commits a blob previously written with `blob_upload` or `write_blob`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn put_blob(
+        &self,
+        parent_rev_id: &str,
+        path: &str,
+        blob_id: &str,
+        metadata: Option<&Metadata>,
+        overwrite: bool,
+        signature: Option<&Signature>,
+        commit_msg: Option<&str>,
+    ) -> Result<String, GitDataStoreError> {
+        check_path(path)?;
+        let repo = Repository::open(&self.repo_path)?;
+
+        let parent_commit = find_commit(&repo, parent_rev_id)?;
+        let blob_id = self.prepare_blob(&repo, path, Oid::from_str(blob_id)?)?;
+
+        let _mutex = self.mutex.lock();
+        self.commit_on_primary_branch(
+            &repo,
+            signature,
+            commit_msg.unwrap_or(format!("Updated {}", path).as_str()),
+            |head_commit| {
+                check_conflict(&repo, path, &parent_commit, head_commit, overwrite)?;
+                self.create_tree_with_blob(&repo, path, blob_id, metadata, head_commit)
+            },
+        )
+    }
+
+    /// Like `put_latest_with_metadata` but commits a blob previously written with `blob_upload`
+    /// or `write_blob`.
+    pub fn put_blob_latest(
+        &self,
+        path: &str,
+        blob_id: &str,
+        metadata: Option<&Metadata>,
+        signature: Option<&Signature>,
+        commit_msg: Option<&str>,
+    ) -> Result<String, GitDataStoreError> {
+        check_path(path)?;
+        let repo = Repository::open(&self.repo_path)?;
+        let blob_id = self.prepare_blob(&repo, path, Oid::from_str(blob_id)?)?;
+
+        let _mutex = self.mutex.lock();
+        self.commit_on_primary_branch(
+            &repo,
+            signature,
+            commit_msg.unwrap_or(format!("Updated {}", path).as_str()),
+            |head_commit| self.create_tree_with_blob(&repo, path, blob_id, metadata, head_commit),
+        )
+    }
+
+    /// Like `put_blob` but only sets the content type in the metadata of the document,
+    /// keeping the rest of its metadata. The content type is left unchanged if `None`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn put_raw(
+        &self,
+        parent_rev_id: &str,
+        path: &str,
+        blob_id: &str,
+        content_type: Option<&str>,
+        overwrite: bool,
+        signature: Option<&Signature>,
+        commit_msg: Option<&str>,
+    ) -> Result<String, GitDataStoreError> {
+        check_path(path)?;
+        let repo = Repository::open(&self.repo_path)?;
+
+        let parent_commit = find_commit(&repo, parent_rev_id)?;
+        let blob_id = self.prepare_blob(&repo, path, Oid::from_str(blob_id)?)?;
+
+        let _mutex = self.mutex.lock();
+        self.commit_on_primary_branch(
+            &repo,
+            signature,
+            commit_msg.unwrap_or(format!("Updated {}", path).as_str()),
+            |head_commit| {
+                check_conflict(&repo, path, &parent_commit, head_commit, overwrite)?;
+                let metadata = with_content_type(&repo, head_commit, path, content_type)?;
+                self.create_tree_with_blob(&repo, path, blob_id, metadata.as_ref(), head_commit)
+            },
+        )
+    }
+
+    /// Like `put_blob_latest` but only sets the content type in the metadata of the document,
+    /// keeping the rest of its metadata. The content type is left unchanged if `None`.
+    pub fn put_raw_latest(
+        &self,
+        path: &str,
+        blob_id: &str,
+        content_type: Option<&str>,
+        signature: Option<&Signature>,
+        commit_msg: Option<&str>,
+    ) -> Result<String, GitDataStoreError> {
+        check_path(path)?;
+        let repo = Repository::open(&self.repo_path)?;
+        let blob_id = self.prepare_blob(&repo, path, Oid::from_str(blob_id)?)?;
+
+        let _mutex = self.mutex.lock();
+        self.commit_on_primary_branch(
+            &repo,
+            signature,
+            commit_msg.unwrap_or(format!("Updated {}", path).as_str()),
+            |head_commit| {
+                let metadata = with_content_type(&repo, head_commit, path, content_type)?;
+                self.create_tree_with_blob(&repo, path, blob_id, metadata.as_ref(), head_commit)
+            },
+        )
+    }
+
+    /// Returns the paths of the documents of the latest version which have all of `labels`
+    /// in their metadata.
+    pub fn find_by_labels_latest(
+        &self,
+        labels: &BTreeMap<String, String>,
+    ) -> Result<Vec<String>, GitDataStoreError> {
+        let repo = Repository::open(&self.repo_path)?;
+        let main_ref = repo.find_reference(&format!("refs/heads/{}", self.primary_branch))?;
+        let commit = main_ref.peel_to_commit()?;
+
+        let tree = commit.tree()?;
+        metadata::find_by_labels(&repo, &tree, labels)
+    }
+
+    /// Returns the paths of the documents at commit id which have all of `labels` in their metadata.
+    pub fn find_by_labels(
+        &self,
+        commit_id: &str,
+        labels: &BTreeMap<String, String>,
+    ) -> Result<Vec<String>, GitDataStoreError> {
+        let repo = Repository::open(&self.repo_path)?;
+        let commit = find_commit(&repo, commit_id)?;
+
+        let tree = commit.tree()?;
+        metadata::find_by_labels(&repo, &tree, labels)
+    }
+
+    /// Lists the schemas of the registry which apply to the document at `path` in the latest version.
+    /// Invalid schemas are listed too, with their error.
+    pub fn schemas_for_latest(&self, path: &str) -> Result<Vec<SchemaEntry>, GitDataStoreError> {
+        let repo = Repository::open(&self.repo_path)?;
+        let main_ref = repo.find_reference(&format!("refs/heads/{}", self.primary_branch))?;
+        let commit = main_ref.peel_to_commit()?;
+
+        let tree = commit.tree()?;
+        schema::schemas_for(&repo, &tree, path, self.codec())
+    }
+
+    /// Lists the schemas of the registry which apply to the document at `path` at commit id.
+    pub fn schemas_for(
+        &self,
+        commit_id: &str,
+        path: &str,
+    ) -> Result<Vec<SchemaEntry>, GitDataStoreError> {
+        let repo = Repository::open(&self.repo_path)?;
+        let commit = find_commit(&repo, commit_id)?;
+
+        let tree = commit.tree()?;
+        schema::schemas_for(&repo, &tree, path, self.codec())
+    }
+
+    /// Removes the large objects which are no longer referenced from any reference of the
+    /// repository and are older than `grace_period`.
+    pub fn gc_large_objects(
+        &self,
+        grace_period: Duration,
+    ) -> Result<LargeObjectGcReport, GitDataStoreError> {
+        let repo = Repository::open(&self.repo_path)?;
+
+        let _mutex = self.mutex.lock();
+        match &self.large_objects {
+            Some(large_objects) => large_objects.gc(&repo, grace_period),
+            None => Ok(LargeObjectGcReport {
+                referenced: 0,
+                removed: Vec::new(),
+            }),
+        }
+    }
+
+    /// Repacks the repository, prunes the unreachable objects and the unreferenced large
+    /// objects older than the grace period, and verifies the repository.
+    pub fn maintain(
+        &self,
+        options: &MaintenanceOptions,
+    ) -> Result<MaintenanceReport, GitDataStoreError> {
+        let repo = Repository::open(&self.repo_path)?;
+        let mut report = maintenance::run(repo.path(), options)?;
+        if let Some(large_objects) = &self.large_objects {
+            let _mutex = self.mutex.lock();
+            report.large_objects = Some(large_objects.gc(&repo, options.grace_period)?);
+        }
+        Ok(report)
+    }
+
+    /// Adds a remote to the repository, or updates its url if it already exists.
+    pub fn set_remote(&self, name: &str, url: &str) -> Result<(), GitDataStoreError> {
+        let repo = Repository::open(&self.repo_path)?;
+        match repo.find_remote(name) {
+            Ok(remote) if remote.url() == Some(url) => {}
+            Ok(_) => repo.remote_set_url(name, url)?,
+            Err(err) if err.code() == ErrorCode::NotFound => {
+                repo.remote(name, url)?;
+            }
+            Err(err) => return Err(err.into()),
+        }
+        Ok(())
+    }
+
+    /// Fetches `branch` of the remote into `refs/remotes/<remote>/<branch>` and returns the
+    /// fetched commit id.
+    pub fn fetch(&self, remote: &str, branch: &str) -> Result<String, GitDataStoreError> {
+        let repo = Repository::open(&self.repo_path)?;
+        Ok(remote::fetch(&repo, remote, branch, &self.credentials)?.to_string())
+    }
+
+    /// Pushes the primary branch to `branch` of the remote. Fails with
+    /// `GitDataStoreError::NonFastForward` if the remote branch has commits which have not
+    /// been pulled yet.
+    pub fn push(&self, remote: &str, branch: &str) -> Result<(), GitDataStoreError> {
+        let repo = Repository::open(&self.repo_path)?;
+        remote::push(
+            &repo,
+            remote,
+            &self.primary_branch,
+            branch,
+            &self.credentials,
+        )
+    }
+
+    /// Fetches `branch` of the remote and integrates it into the primary branch, either by
+    /// fast-forwarding or by creating a merge commit according to `strategy`.
+    /// Returns the new head of the primary branch.
+    pub fn pull(
+        &self,
+        remote: &str,
+        branch: &str,
+        strategy: MergeStrategy,
+    ) -> Result<String, GitDataStoreError> {
+        let repo = Repository::open(&self.repo_path)?;
+        let their_commit =
+            repo.find_commit(remote::fetch(&repo, remote, branch, &self.credentials)?)?;
+        let commit_msg = format!("Merge {}/{}", remote, branch);
+        let branch_ref = format!("refs/heads/{}", self.primary_branch);
+
+        let _mutex = self.mutex.lock();
+        for attempt in 0..MAX_REF_UPDATE_ATTEMPTS {
+            if attempt > 0 {
+                thread::sleep(Duration::from_millis(1 << attempt));
+            }
+            let head_commit = repo.find_reference(&branch_ref)?.peel_to_commit()?;
+
+            if head_commit.id() == their_commit.id()
+                || repo.graph_descendant_of(head_commit.id(), their_commit.id())?
+            {
+                return Ok(head_commit.id().to_string());
+            }
+
+            let (new_head, log_message) =
+                if repo.graph_descendant_of(their_commit.id(), head_commit.id())? {
+                    (their_commit.id(), "pull: fast-forward".to_string())
+                } else if strategy == MergeStrategy::FastForwardOnly {
+                    return Err(GitDataStoreError::NonFastForward { branch: branch_ref });
+                } else {
+                    let tree = repo.find_tree(merge::merge_trees(
+                        &repo,
+                        &head_commit,
+                        &their_commit,
+                        strategy,
+                    )?)?;
+                    let signature = repo.signature()?;
+                    let merge_id = self.create_commit(
+                        &repo,
+                        &signature,
+                        &signature,
+                        &commit_msg,
+                        &tree,
+                        &[&head_commit, &their_commit],
+                    )?;
+                    (merge_id, format!("pull: {}", commit_msg))
+                };
+
+            if self.compare_and_swap_primary_branch(
+                &repo,
+                head_commit.id(),
+                new_head,
+                &log_message,
+            )? {
+                return Ok(new_head.to_string());
+            }
+        }
+
+        Err(GitDataStoreError::ConcurrentUpdate {
+            branch: self.primary_branch.clone(),
+        })
+    }
+
+    /// Pulls `branch` of the remote then pushes the result back. If the remote branch moved
+    /// in between, pulls and pushes once more. Returns the new head of the primary branch.
+    pub fn sync(
+        &self,
+        remote: &str,
+        branch: &str,
+        strategy: MergeStrategy,
+    ) -> Result<String, GitDataStoreError> {
+        let head = self.pull(remote, branch, strategy)?;
+        match self.push(remote, branch) {
+            Err(GitDataStoreError::NonFastForward { .. }) => {
+                let head = self.pull(remote, branch, strategy)?;
+                self.push(remote, branch)?;
+                Ok(head)
+            }
+            result => result.map(|_| head),
+        }
+    }
+
+    /// Fetches `branch` of the remote and resets the primary branch to it, discarding any
+    /// local commit. Meant for read-only replicas, see [`replica::Replica`].
+    /// Returns the new head of the primary branch.
+    pub fn follow(&self, remote: &str, branch: &str) -> Result<String, GitDataStoreError> {
+        let repo = Repository::open(&self.repo_path)?;
+        let upstream = remote::fetch(&repo, remote, branch, &self.credentials)?;
+
+        let _mutex = self.mutex.lock();
+        repo.reference(
+            &format!("refs/heads/{}", self.primary_branch),
+            upstream,
+            true,
+            &format!("follow: {}/{}", remote, branch),
+        )?;
+        Ok(upstream.to_string())
+    }
+
+    /// Applies `patch` to the document at path as it is on the primary branch when the commit
+    /// is made. Like `put`, fails with `GitDataStoreError::ConflictOnWrite` if the document was
+    /// changed since `parent_rev_id` unless `overwrite` is set.
+    pub fn patch(
+        &self,
+        parent_rev_id: &str,
+        path: &str,
+        patch: &Patch,
+        overwrite: bool,
+        signature: Option<&Signature>,
+        commit_msg: Option<&str>,
+    ) -> Result<String, GitDataStoreError> {
+        check_path(path)?;
+        let repo = Repository::open(&self.repo_path)?;
+
+        let parent_commit = find_commit(&repo, parent_rev_id)?;
+
+        let _mutex = self.mutex.lock();
+        self.commit_on_primary_branch(
+            &repo,
+            signature,
+            commit_msg.unwrap_or(format!("Patched {}", path).as_str()),
+            |head_commit| {
+                check_conflict(&repo, path, &parent_commit, head_commit, overwrite)?;
+                let data = self.patch_document(&repo, path, patch, head_commit)?;
+                self.create_tree(&repo, path, &data, None, head_commit)
+            },
+        )
+    }
+
+    /// Applies `patch` to the latest version of the document at path.
+    pub fn patch_latest(
+        &self,
+        path: &str,
+        patch: &Patch,
+        signature: Option<&Signature>,
+        commit_msg: Option<&str>,
+    ) -> Result<String, GitDataStoreError> {
+        check_path(path)?;
+        let repo = Repository::open(&self.repo_path)?;
+
+        let _mutex = self.mutex.lock();
+        self.commit_on_primary_branch(
+            &repo,
+            signature,
+            commit_msg.unwrap_or(format!("Patched {}", path).as_str()),
+            |head_commit| {
+                let data = self.patch_document(&repo, path, patch, head_commit)?;
+                self.create_tree(&repo, path, &data, None, head_commit)
+            },
+        )
+    }
+
+    /// Applies the inverse of the changes made by `commit_id`, compared to its first parent,
+    /// on top of the primary branch. Fails with `GitDataStoreError::MergeConflicts` if a
+    /// document it changed was changed again since.
+    pub fn revert(
+        &self,
+        commit_id: &str,
+        signature: Option<&Signature>,
+        commit_msg: Option<&str>,
+    ) -> Result<String, GitDataStoreError> {
+        let repo = Repository::open(&self.repo_path)?;
+        let commit = find_commit(&repo, commit_id)?;
+        let mainline = if commit.parent_count() > 1 { 1 } else { 0 };
+        let commit_msg = commit_msg.map(str::to_string).unwrap_or_else(|| {
+            format!(
+                "Revert \"{}\"\n\nThis reverts commit {}.",
+                commit.summary().unwrap_or_default(),
+                commit.id()
+            )
+        });
+
+        let _mutex = self.mutex.lock();
+        self.commit_on_primary_branch(&repo, signature, &commit_msg, |head_commit| {
+            let index = repo.revert_commit(&commit, head_commit, mainline, None)?;
+            merge::write_tree(&repo, index)
+        })
+    }
+
+    /// Restores the document or directory at `path`, with its metadata, to its content at
+    /// `from_rev`. It is deleted if it did not exist at `from_rev`.
+    pub fn restore(
+        &self,
+        path: &str,
+        from_rev: &str,
+        signature: Option<&Signature>,
+        commit_msg: Option<&str>,
+    ) -> Result<String, GitDataStoreError> {
+        check_path(path)?;
+        let repo = Repository::open(&self.repo_path)?;
+        let from_tree = find_commit(&repo, from_rev)?.tree()?;
+
+        let _mutex = self.mutex.lock();
+        self.commit_on_primary_branch(
+            &repo,
+            signature,
+            commit_msg.unwrap_or(format!("Restored {} from {}", path, from_rev).as_str()),
+            |head_commit| restore_path(&repo, &from_tree, &head_commit.tree()?, path),
+        )
+    }
+
+    /// Applies the changes made by `commit_id`, compared to its first parent, on top of the
+    /// local branch `onto_branch`, e.g. to bring a fix made on a staging branch to the primary
+    /// branch. The commit keeps its author and message, which records the picked commit like
+    /// `git cherry-pick -x` does, and who asked for the pick in a `Cherry-picked-by` trailer
+    /// when `signature` is given. Fails with `GitDataStoreError::MergeConflicts` if a document
+    /// it changed differs on `onto_branch`, where our side is `onto_branch` and their side is
+    /// the picked commit. Returns the new head of `onto_branch`.
+    pub fn cherry_pick(
+        &self,
+        commit_id: &str,
+        onto_branch: &str,
+        signature: Option<&Signature>,
+    ) -> Result<String, GitDataStoreError> {
+        let repo = Repository::open(&self.repo_path)?;
+        let commit = find_commit(&repo, commit_id)?;
+        repo.find_reference(&format!("refs/heads/{}", onto_branch))
+            .map_err(|_| GitDataStoreError::RevNotFound(onto_branch.to_string()))?;
+        let mainline = if commit.parent_count() > 1 { 1 } else { 0 };
+        let mut commit_msg = format!(
+            "{}\n\n(cherry picked from commit {})",
+            commit.message().unwrap_or_default().trim_end(),
+            commit.id()
+        );
+        if let Some(signature) = signature {
+            commit_msg.push_str(&format!(
+                "\nCherry-picked-by: {} <{}>",
+                signature.name, signature.email
+            ));
+        }
+        let author = commit.author();
+        let committer = repo.signature()?;
+
+        let _mutex = self.mutex.lock();
+        self.commit_on_branch(
+            &repo,
+            onto_branch,
+            &author,
+            &committer,
+            &commit_msg,
+            |head_commit| {
+                let index = repo.cherrypick_commit(&commit, head_commit, mainline, None)?;
+                merge::write_tree(&repo, index)
+            },
+        )
+    }
+
+    /// Documents `restore` would change if it was made on the current head of the primary
+    /// branch, e.g. to check the permissions of the caller on each of them.
+    pub fn restore_changes(
+        &self,
+        path: &str,
+        from_rev: &str,
+    ) -> Result<Vec<PathChange>, GitDataStoreError> {
+        check_path(path)?;
+        let repo = Repository::open(&self.repo_path)?;
+        let from_tree = find_commit(&repo, from_rev)?.tree()?;
+        let head_tree = repo
+            .find_reference(&format!("refs/heads/{}", self.primary_branch))?
+            .peel_to_commit()?
+            .tree()?;
+        let restored = repo.find_tree(restore_path(&repo, &from_tree, &head_tree, path)?)?;
+        changes::tree_paths(&repo, Some(&head_tree), &restored, None)
+    }
+
+    /// Re-encrypts the documents of the head of the primary branch which are not encrypted
+    /// with the active key, e.g. after a key was appended to the keyfile or a glob was added.
+    /// Returns the commit made, `None` when every document is up to date. Older commits keep
+    /// their content, so the previous keys must be kept to read them.
+    pub fn rotate_encryption(
+        &self,
+        signature: Option<&Signature>,
+        commit_msg: Option<&str>,
+    ) -> Result<Option<String>, GitDataStoreError> {
+        let encryption = match &self.encryption {
+            Some(encryption) => encryption,
+            None => return Ok(None),
+        };
+        let repo = Repository::open(&self.repo_path)?;
+
+        let _mutex = self.mutex.lock();
+        let head_tree = repo
+            .find_reference(&format!("refs/heads/{}", self.primary_branch))?
+            .peel_to_commit()?
+            .tree()?;
+        if self
+            .stale_encrypted_documents(&repo, encryption, &head_tree)?
+            .is_empty()
+        {
+            return Ok(None);
+        }
+
+        let commit_msg = commit_msg
+            .map(str::to_string)
+            .unwrap_or_else(|| format!("Encrypted documents with key {}", encryption.active_key()));
+        self.commit_on_primary_branch(&repo, signature, &commit_msg, |head_commit| {
+            let head_tree = head_commit.tree()?;
+            let mut index = Index::new()?;
+            index.read_tree(&head_tree)?;
+            for (path, stored) in self.stale_encrypted_documents(&repo, encryption, &head_tree)? {
+                let content = encryption.decrypt(&path, &stored)?.unwrap_or(stored);
+                let mut entry = make_index_entry(&path);
+                entry.id = self.write_content(&repo, &encryption.encrypt(&path, &content)?)?;
+                index.add(&entry)?;
+            }
+            Ok(index.write_tree_to(&repo)?)
+        })
+        .map(Some)
+    }
+
+    /// Documents of `tree` at encrypted paths, with their stored content, which are not
+    /// encrypted with the active key.
+    fn stale_encrypted_documents(
+        &self,
+        repo: &Repository,
+        encryption: &Encryption,
+        tree: &Tree,
+    ) -> Result<Vec<(String, Vec<u8>)>, GitDataStoreError> {
+        let mut paths = Vec::new();
+        tree.walk(TreeWalkMode::PreOrder, |root, entry| {
+            let path = format!("{}{}", root, entry.name().unwrap_or_default());
+            if entry.kind() == Some(ObjectType::Blob) && encryption.applies_to(&path) {
+                paths.push(path);
+            }
+            TreeWalkResult::Ok
+        })?;
+
+        let mut stale = Vec::new();
+        for path in paths {
+            let blob = repo.find_blob(tree.get_path(Path::new(&path))?.id())?;
+            let stored = match (
+                &self.large_objects,
+                LargeObjectPointer::parse(blob.content()),
+            ) {
+                (Some(large_objects), Some(pointer)) => large_objects.read(&pointer)?,
+                _ => blob.content().to_vec(),
+            };
+            if encryption::encryption_key(&stored) != Some(encryption.active_key()) {
+                stale.push((path, stored));
+            }
+        }
+        Ok(stale)
+    }
+
+    /// Permanently removes `target` from every commit of the local branches, the archives and
+    /// the tags, e.g. to honour a data deletion request which `delete` cannot since the content stays
+    /// in the history.
+    ///
+    /// The commits are rewritten with the same authors, committers and messages, and signed
+    /// again when the store signs commits. Commits which only changed the purged content are
+    /// dropped. Remote tracking branches are deleted, then the whole reflogs of the rewritten
+    /// references, and of `HEAD` when it points to one of them, are expired and the objects
+    /// only the old history reached are removed, as are the large objects of the purged
+    /// content. The reflogs of other references are kept. Other unreachable objects, e.g. blobs uploaded for a write which is
+    /// not committed yet, are left to the grace period of [`GitDataStore::maintain`].
+    ///
+    /// Every commit id after the first commit holding the content changes: clones, remotes
+    /// and replicas keep the content until they are purged or cloned again, and a remote the
+    /// store syncs with must be force pushed, otherwise the next pull merges the content back.
+    pub fn purge(&self, target: PurgeTarget) -> Result<PurgeReport, GitDataStoreError> {
+        let repo = Repository::open(&self.repo_path)?;
+
+        let _mutex = self.mutex.lock();
+        let mut rewriter = HistoryRewriter::new(&repo, target);
+        let mut report = rewriter.rewrite(|commit, tree, parents| {
+            self.create_commit(
+                &repo,
+                &commit.author(),
+                &commit.committer(),
+                commit.message().unwrap_or_default(),
+                tree,
+                parents,
+            )
+        })?;
+        // read before the purged blobs are pruned
+        let purged_large_objects = rewriter.purged_large_objects()?;
+
+        // the objects only the replaced history reaches are removed once no reference does
+        let mut old_tips = Vec::new();
+        for (name, old_id, new_id) in &rewriter.moved_refs {
+            let reference = repo.find_reference(name)?;
+            old_tips.extend(reference.target());
+            old_tips.push(*old_id);
+            let tag = reference
+                .target()
+                .and_then(|target| repo.find_tag(target).ok());
+            match (name.strip_prefix(TAGS_REFS_PREFIX), tag) {
+                // annotated tags are created again with their tagger and message
+                (Some(tag_name), Some(tag)) => {
+                    let tagger = tag.tagger().map_or_else(|| repo.signature(), Ok)?;
+                    repo.tag(
+                        tag_name,
+                        &repo.find_object(*new_id, None)?,
+                        &tagger,
+                        tag.message().unwrap_or_default(),
+                        true,
+                    )?;
+                }
+                (Some(_), None) => {
+                    repo.reference(name, *new_id, true, "purge: rewrite history")?;
+                }
+                (None, _) => match repo.reference_matching(
+                    name,
+                    *new_id,
+                    true,
+                    *old_id,
+                    "purge: rewrite history",
+                ) {
+                    Ok(_) => {}
+                    Err(err)
+                        if err.code() == ErrorCode::Modified || err.code() == ErrorCode::Locked =>
+                    {
+                        return Err(GitDataStoreError::ConcurrentUpdate {
+                            branch: name.clone(),
+                        })
+                    }
+                    Err(err) => return Err(err.into()),
+                },
+            }
+        }
+        for reference in repo.references_glob("refs/remotes/*")? {
+            let mut reference = reference?;
+            if let Some(name) = reference.name() {
+                report.deleted_refs.push(name.to_string());
+            }
+            old_tips.extend(reference.target());
+            reference.delete()?;
+        }
 
-    pub fn is_file(&self) -> bool {
-        if let GitData::File { .. } = self {
-            true
-        } else {
-            false
+        // `HEAD` has a reflog of its own when it points to a rewritten branch
+        let mut rewritten_refs = rewriter
+            .moved_refs
+            .iter()
+            .map(|(name, _, _)| name.clone())
+            .collect::<Vec<_>>();
+        let head = repo.find_reference("HEAD")?;
+        if let Some(target) = head.symbolic_target() {
+            if rewritten_refs.iter().any(|name| name == target) {
+                rewritten_refs.push("HEAD".to_string());
+            }
         }
+        // git fails on references without a reflog
+        let mut with_reflog = Vec::new();
+        for name in rewritten_refs {
+            if !repo.reflog(&name)?.is_empty() {
+                with_reflog.push(name);
+            }
+        }
+        maintenance::prune_purged(repo.path(), &with_reflog, &old_tips)?;
+        if let Some(large_objects) = &self.large_objects {
+            report.removed_large_objects =
+                large_objects.remove_unreferenced(&repo, &purged_large_objects)?;
+        }
+        Ok(report)
     }
 
-    pub fn file(&self) -> Option<&str> {
-        if let GitData::File { data } = self {
-            Some(data)
-        } else {
-            None
+    /// Squashes the history of the primary branch older than `cutoff`, so that history walks
+    /// of stores with many small commits stay fast. The tree of every commit newer than the
+    /// cutoff, the head included, is unchanged, but their ids change. When `archive` is set,
+    /// the history before the compaction is kept under `refs/archive/<archive>`, otherwise
+    /// its objects are left unreachable until they are pruned.
+    ///
+    /// Like a purge, clones, remotes and replicas keep the old history and a remote the store
+    /// syncs with must be force pushed.
+    pub fn compact(
+        &self,
+        cutoff: DateTime<Utc>,
+        mode: CompactionMode,
+        archive: Option<&str>,
+    ) -> Result<CompactionReport, GitDataStoreError> {
+        let repo = Repository::open(&self.repo_path)?;
+        let branch_ref = format!("refs/heads/{}", self.primary_branch);
+
+        let _mutex = self.mutex.lock();
+        let head = repo.find_reference(&branch_ref)?.peel_to_commit()?;
+        let new_head = compaction::compact(
+            &repo,
+            &head,
+            cutoff,
+            mode,
+            |author, committer, message, tree, parents| {
+                self.create_commit(&repo, author, committer, message, tree, parents)
+            },
+        )?;
+
+        let archive_ref = match archive {
+            Some(archive) if new_head != head.id() => {
+                let archive_ref = format!("{}{}", ARCHIVE_REFS_PREFIX, archive);
+                repo.reference(
+                    &archive_ref,
+                    head.id(),
+                    false,
+                    &format!("compact: archive {}", self.primary_branch),
+                )?;
+                Some(archive_ref)
+            }
+            _ => None,
+        };
+        let swapped = self.compare_and_swap_primary_branch(
+            &repo,
+            head.id(),
+            new_head,
+            &format!("compact: squash history before {}", cutoff.to_rfc3339()),
+        );
+        if !matches!(swapped, Ok(true)) {
+            // the archive is only kept once the history it points to was replaced, so that
+            // the compaction can be retried with the same archive
+            if let Some(archive_ref) = &archive_ref {
+                repo.find_reference(archive_ref)?.delete()?;
+            }
         }
+        if !swapped? {
+            return Err(GitDataStoreError::ConcurrentUpdate {
+                branch: self.primary_branch.clone(),
+            });
+        }
+
+        Ok(CompactionReport {
+            commits_before: compaction::count_commits(&repo, head.id())?,
+            commits_after: compaction::count_commits(&repo, new_head)?,
+            head: new_head.to_string(),
+            archive_ref,
+        })
     }
-}
 
-impl GitDataStore {
-    pub fn new(repo_path: &str, primary_branch: &str) -> Self {
-        GitDataStore {
-            repo_path: repo_path.to_string(),
-            primary_branch: primary_branch.to_string(),
-            mutex: Mutex::new(()),
+    /// Creates the annotated tag `name` of the commit `commit_id`, which can itself be a tag.
+    /// The tagger is the identity of the store when `tagger` is `None`.
+    pub fn create_tag(
+        &self,
+        name: &str,
+        commit_id: &str,
+        message: &str,
+        tagger: Option<&Signature>,
+    ) -> Result<TagEntry, GitDataStoreError> {
+        tag::check_tag_name(name)?;
+        let repo = Repository::open(&self.repo_path)?;
+        let commit = find_commit(&repo, commit_id)?;
+        let tagger: git2::Signature = match tagger {
+            Some(tagger) => tagger.into(),
+            None => repo.signature(),
+        }?;
+
+        let _mutex = self.mutex.lock();
+        match repo.tag(name, commit.as_object(), &tagger, message, false) {
+            Ok(_) => {}
+            Err(err) if err.code() == ErrorCode::Exists => {
+                return Err(GitDataStoreError::TagExists(name.to_string()))
+            }
+            Err(err) => return Err(err.into()),
         }
+        tag::find_tag(&repo, name)?.ok_or_else(|| GitDataStoreError::RevNotFound(name.to_string()))
     }
 
-    pub fn read_latest(&self, path: &str) -> Result<Option<GitEntry>, GitDataStoreError> {
+    /// Creates the annotated tag `name` of the head of the primary branch.
+    pub fn create_tag_latest(
+        &self,
+        name: &str,
+        message: &str,
+        tagger: Option<&Signature>,
+    ) -> Result<TagEntry, GitDataStoreError> {
+        let head = self.head()?.commit_id;
+        self.create_tag(name, &head, message, tagger)
+    }
+
+    /// Tag `name`, `None` if there is no such tag.
+    pub fn tag(&self, name: &str) -> Result<Option<TagEntry>, GitDataStoreError> {
         let repo = Repository::open(&self.repo_path)?;
-        let main_ref = repo.find_reference(&format!("refs/heads/{}", self.primary_branch))?;
-        let commit = main_ref.peel_to_commit()?;
+        tag::find_tag(&repo, name)
+    }
 
-        read_entry_from_tree(&repo, &commit, path)
+    /// Every tag of a commit, by name.
+    pub fn tags(&self) -> Result<Vec<TagEntry>, GitDataStoreError> {
+        let repo = Repository::open(&self.repo_path)?;
+        tag::list_tags(&repo)
     }
 
-    pub fn read(&self, commit_id: &str, path: &str) -> Result<Option<GitEntry>, GitDataStoreError> {
+    /// Deletes the tag `name` and returns it. The commits it pointed to are kept.
+    pub fn delete_tag(&self, name: &str) -> Result<TagEntry, GitDataStoreError> {
         let repo = Repository::open(&self.repo_path)?;
 
-        // TODO allow rev spec
-        let commit = repo
-            .find_commit(
-                Oid::from_str(commit_id)
-                    .map_err(|_e| GitDataStoreError::RevNotFound(commit_id.to_string()))?,
-            )
-            .map_err(|_e| GitDataStoreError::RevNotFound(commit_id.to_string()))?;
+        let _mutex = self.mutex.lock();
+        let tag = tag::find_tag(&repo, name)?
+            .ok_or_else(|| GitDataStoreError::RevNotFound(name.to_string()))?;
+        repo.tag_delete(name)?;
+        Ok(tag)
+    }
 
-        read_entry_from_tree(&repo, &commit, path)
+    pub fn history(&self) -> Result<HistoryIterator, GitDataStoreError> {
+        let repo = Repository::open(&self.repo_path)?;
+        history::git_log(repo)
     }
 
-    pub fn put(
+    pub fn delete(
         &self,
         parent_rev_id: &str,
         path: &str,
-        data: &str,
         overwrite: bool,
         signature: Option<&Signature>,
         commit_msg: Option<&str>,
     ) -> Result<String, GitDataStoreError> {
-        // get last commit from primary branch and parent commit
-        // if they are the same or the overwrite flag is set, create new commit with that as parent and update primary branch
-        // if they are not the same, diff between the 2 commits and check that path hasn't been updated since parent commit
-        // if it has been updated, create conflict error
+        check_path(path)?;
         let repo = Repository::open(&self.repo_path)?;
 
-        let parent_rev = repo.revparse_single(parent_rev_id)?;
-        let parent_commit = parent_rev.peel_to_commit()?;
+        let parent_commit = find_commit(&repo, parent_rev_id)?;
 
-        // lock mutex
         let _mutex = self.mutex.lock();
-        let main_ref = repo.find_reference(&format!("refs/heads/{}", self.primary_branch))?;
-
-        let head_commit = main_ref.peel_to_commit()?;
-
-        if head_commit.id() != parent_commit.id()
-            && !overwrite
-            && has_conflict(&repo, path, &parent_commit, &head_commit)?
-        {
-            return Err(GitDataStoreError::ConflictOnWrite {
-                path: path.to_string(),
-                parent_commit_id: parent_commit.id().to_string(),
-            });
-        }
-
-        let tree_oid = self.create_tree(&repo, path, data, &head_commit)?;
-        let tree = repo.find_tree(tree_oid)?;
-
-        let author_commiter: git2::Signature = signature
-            .map(|s| s.into())
-            .unwrap_or_else(|| repo.signature())?;
-
-        let commit_id = repo.commit(
-            Some(&format!("refs/heads/{}", self.primary_branch)),
-            &author_commiter,
-            &author_commiter,
-            commit_msg.unwrap_or(format!("Updated {}", path).as_str()),
-            &tree,
-            &[&head_commit],
-        )?;
-        Ok(commit_id.to_string())
+        self.commit_on_primary_branch(
+            &repo,
+            signature,
+            commit_msg.unwrap_or(format!("Deleted {}", path).as_str()),
+            |head_commit| {
+                check_conflict(&repo, path, &parent_commit, head_commit, overwrite)?;
+                self.remove_from_tree(&repo, path, head_commit)
+            },
+        )
     }
 
-    pub fn put_latest(
+    pub fn delete_latest(
         &self,
         path: &str,
-        data: &str,
         signature: Option<&Signature>,
         commit_msg: Option<&str>,
     ) -> Result<String, GitDataStoreError> {
+        check_path(path)?;
         let repo = Repository::open(&self.repo_path)?;
 
         let _mutex = self.mutex.lock();
-        let main_ref = repo.find_reference(&format!("refs/heads/{}", self.primary_branch))?;
+        self.commit_on_primary_branch(
+            &repo,
+            signature,
+            commit_msg.unwrap_or(format!("Deleted {}", path).as_str()),
+            |head_commit| self.remove_from_tree(&repo, path, head_commit),
+        )
+    }
 
-        let head_commit = main_ref.peel_to_commit()?;
+    /// Creates a commit authored by `signature` on top of the primary branch and moves the
+    /// branch to it, see [`GitDataStore::commit_on_branch`].
+    fn commit_on_primary_branch<F>(
+        &self,
+        repo: &Repository,
+        signature: Option<&Signature>,
+        commit_msg: &str,
+        build_tree: F,
+    ) -> Result<String, GitDataStoreError>
+    where
+        F: FnMut(&Commit) -> Result<Oid, GitDataStoreError>,
+    {
+        // the author is who asked for the change, the committer is the identity of the store
+        let committer = repo.signature()?;
+        let author: git2::Signature = match signature {
+            Some(signature) => signature.into(),
+            None => Ok(committer.clone()),
+        }?;
+        self.commit_on_branch(
+            repo,
+            &self.primary_branch,
+            &author,
+            &committer,
+            commit_msg,
+            build_tree,
+        )
+    }
 
-        let tree_oid = self.create_tree(&repo, path, data, &head_commit)?;
+    /// Creates a commit on top of `branch` and moves the branch to it.
+    ///
+    /// `self.mutex` only serializes writers within this process, so the branch is
+    /// updated with a compare-and-swap against the head the tree was built from.
+    /// If another process moved the branch in the meantime, the tree is rebuilt
+    /// on top of the new head (re-running any conflict checks in `build_tree`)
+    /// and the update is retried with an exponential backoff.
+    fn commit_on_branch<F>(
+        &self,
+        repo: &Repository,
+        branch: &str,
+        author: &git2::Signature,
+        committer: &git2::Signature,
+        commit_msg: &str,
+        mut build_tree: F,
+    ) -> Result<String, GitDataStoreError>
+    where
+        F: FnMut(&Commit) -> Result<Oid, GitDataStoreError>,
+    {
+        let branch_ref = format!("refs/heads/{}", branch);
+        for attempt in 0..MAX_REF_UPDATE_ATTEMPTS {
+            if attempt > 0 {
+                thread::sleep(Duration::from_millis(1 << attempt));
+            }
+            let head_commit = repo.find_reference(&branch_ref)?.peel_to_commit()?;
 
-        let tree = repo.find_tree(tree_oid)?;
-        let author_commiter: git2::Signature = signature
-            .map(|s| s.into())
-            .unwrap_or_else(|| repo.signature())?;
+            let tree = repo.find_tree(build_tree(&head_commit)?)?;
+            self.validation
+                .validate(repo, &head_commit.tree()?, &tree, self.codec())?;
+            let commit_id =
+                self.create_commit(repo, author, committer, commit_msg, &tree, &[&head_commit])?;
 
-        let commit_id = repo.commit(
-            Some(&format!("refs/heads/{}", self.primary_branch)),
-            &author_commiter,
-            &author_commiter,
-            commit_msg.unwrap_or(format!("Updated {}", path).as_str()),
-            &tree,
-            &[&head_commit],
-        )?;
-        Ok(commit_id.to_string())
+            if self.compare_and_swap_branch(
+                repo,
+                branch,
+                head_commit.id(),
+                commit_id,
+                &format!("commit: {}", commit_msg),
+            )? {
+                return Ok(commit_id.to_string());
+            }
+        }
+
+        Err(GitDataStoreError::ConcurrentUpdate {
+            branch: branch.to_string(),
+        })
     }
 
-    pub fn history(&self) -> Result<HistoryIterator, GitDataStoreError> {
-        let repo = Repository::open(&self.repo_path)?;
-        history::git_log(repo)
+    /// Creates a commit without updating any reference, signed when the store has a signer.
+    fn create_commit(
+        &self,
+        repo: &Repository,
+        author: &git2::Signature,
+        committer: &git2::Signature,
+        commit_msg: &str,
+        tree: &Tree,
+        parents: &[&Commit],
+    ) -> Result<Oid, GitDataStoreError> {
+        let signer = match &self.signer {
+            Some(signer) => signer,
+            None => return Ok(repo.commit(None, author, committer, commit_msg, tree, parents)?),
+        };
+        let buffer = repo.commit_create_buffer(author, committer, commit_msg, tree, parents)?;
+        let buffer = buffer
+            .as_str()
+            .ok_or_else(|| GitDataStoreError::Signing("commit is not valid utf-8".to_string()))?;
+        let signature = signer.sign(buffer.as_bytes())?;
+        Ok(repo.commit_signed(buffer, &signature, None)?)
     }
 
-    pub fn delete(
+    /// Moves the primary branch to `new_id` only if it still points to `expected_id`.
+    /// Returns false if the branch was moved or is being moved by another writer.
+    fn compare_and_swap_primary_branch(
         &self,
-        parent_rev_id: &str,
-        path: &str,
-        overwrite: bool,
-        signature: Option<&Signature>,
-        commit_msg: Option<&str>,
-    ) -> Result<String, GitDataStoreError> {
-        let repo = Repository::open(&self.repo_path)?;
+        repo: &Repository,
+        expected_id: Oid,
+        new_id: Oid,
+        log_message: &str,
+    ) -> Result<bool, GitDataStoreError> {
+        self.compare_and_swap_branch(repo, &self.primary_branch, expected_id, new_id, log_message)
+    }
 
-        let parent_rev = repo.revparse_single(parent_rev_id)?;
-        let parent_commit = parent_rev.peel_to_commit()?;
+    /// Moves `branch` to `new_id` only if it still points to `expected_id`.
+    /// Returns false if the branch was moved or is being moved by another writer.
+    fn compare_and_swap_branch(
+        &self,
+        repo: &Repository,
+        branch: &str,
+        expected_id: Oid,
+        new_id: Oid,
+        log_message: &str,
+    ) -> Result<bool, GitDataStoreError> {
+        match repo.reference_matching(
+            &format!("refs/heads/{}", branch),
+            new_id,
+            true,
+            expected_id,
+            log_message,
+        ) {
+            Ok(_) => Ok(true),
+            Err(err) if err.code() == ErrorCode::Modified || err.code() == ErrorCode::Locked => {
+                Ok(false)
+            }
+            Err(err) => Err(err.into()),
+        }
+    }
 
-        let _mutex = self.mutex.lock();
-        let main_ref = repo.find_reference(&format!("refs/heads/{}", self.primary_branch))?;
+    fn create_tree(
+        &self,
+        repo: &Repository,
+        path: &str,
+        data: &str,
+        metadata: Option<&Metadata>,
+        head_commit: &Commit,
+    ) -> Result<Oid, GitDataStoreError> {
+        let mut index = Index::new()?;
+        index.read_tree(&head_commit.tree()?)?;
+        repo.set_index(&mut index)?;
+        let data = match &self.encryption {
+            Some(encryption) if encryption.applies_to(path) => {
+                Cow::Owned(encryption.encrypt(path, data.as_bytes())?)
+            }
+            _ => Cow::Borrowed(data.as_bytes()),
+        };
+        let mut entry = make_index_entry(path);
+        entry.id = self.write_content(repo, &data)?;
+        index.add(&entry)?;
+        add_metadata(&mut index, path, metadata)?;
 
-        let head_commit = main_ref.peel_to_commit()?;
+        let tree_oid = index.write_tree_to(repo)?;
+        Ok(tree_oid)
+    }
 
-        if head_commit.id() != parent_commit.id()
-            && !overwrite
-            && has_conflict(&repo, path, &parent_commit, &head_commit)?
-        {
-            return Err(GitDataStoreError::ConflictOnWrite {
+    fn patch_document(
+        &self,
+        repo: &Repository,
+        path: &str,
+        patch: &Patch,
+        head_commit: &Commit,
+    ) -> Result<String, GitDataStoreError> {
+        let entry = read_entry_from_tree(repo, head_commit, path, self.codec())?;
+        match entry.map(|entry| entry.data) {
+            Some(GitData::File { data }) => patch.apply(path, Some(&data)),
+            Some(GitData::Dir { .. }) => Err(GitDataStoreError::PatchFailed {
                 path: path.to_string(),
-                parent_commit_id: parent_commit.id().to_string(),
-            });
+                error: "path is a directory".to_string(),
+            }),
+            None => patch.apply(path, None),
         }
+    }
 
+    fn create_tree_with_blob(
+        &self,
+        repo: &Repository,
+        path: &str,
+        blob_id: Oid,
+        metadata: Option<&Metadata>,
+        head_commit: &Commit,
+    ) -> Result<Oid, GitDataStoreError> {
         let mut index = Index::new()?;
         index.read_tree(&head_commit.tree()?)?;
         repo.set_index(&mut index)?;
+        let mut entry = make_index_entry(path);
+        entry.id = blob_id;
+        index.add(&entry)?;
+        add_metadata(&mut index, path, metadata)?;
 
-        // https://libgit2.org/libgit2/#HEAD/type/git_index_stage_t
-        index.remove(&Path::new(path), -1)?;
-
-        let tree_oid = index.write_tree_to(&repo)?;
-
-        let tree = repo.find_tree(tree_oid)?;
-        let author_commiter: git2::Signature = signature
-            .map(|s| s.into())
-            .unwrap_or_else(|| repo.signature())?;
+        let tree_oid = index.write_tree_to(repo)?;
+        Ok(tree_oid)
+    }
 
-        let commit_id = repo.commit(
-            Some(&format!("refs/heads/{}", self.primary_branch)),
-            &author_commiter,
-            &author_commiter,
-            commit_msg.unwrap_or(format!("Deleted {}", path).as_str()),
-            &tree,
-            &[&head_commit],
-        )?;
-        Ok(commit_id.to_string())
+    fn codec(&self) -> BlobCodec<'_> {
+        BlobCodec {
+            large_objects: self.large_objects.as_ref(),
+            encryption: self.encryption.as_ref(),
+        }
     }
 
-    pub fn delete_latest(
+    /// Encrypts the blob if documents at `path` are encrypted and it is not encrypted yet, then
+    /// moves it to the large object store if it is over the threshold or looks like a pointer
+    /// which was not written by this store. Returns the id of the blob to commit in its place.
+    fn prepare_blob(
         &self,
+        repo: &Repository,
         path: &str,
-        signature: Option<&Signature>,
-        commit_msg: Option<&str>,
-    ) -> Result<String, GitDataStoreError> {
-        let repo = Repository::open(&self.repo_path)?;
-
-        let _mutex = self.mutex.lock();
-        let main_ref = repo.find_reference(&format!("refs/heads/{}", self.primary_branch))?;
+        blob_id: Oid,
+    ) -> Result<Oid, GitDataStoreError> {
+        let pointer = if self.written_pointers.lock().contains(&blob_id) {
+            LargeObjectPointer::parse(repo.find_blob(blob_id)?.content())
+        } else {
+            None
+        };
+        let encryption = match &self.encryption {
+            Some(encryption) if encryption.applies_to(path) => encryption,
+            _ if pointer.is_some() => return Ok(blob_id),
+            _ => return self.offload_large_blob(repo, blob_id),
+        };
+        let blob = repo.find_blob(blob_id)?;
+        let stored = match (&self.large_objects, &pointer) {
+            (Some(large_objects), Some(pointer)) => Cow::Owned(large_objects.read(pointer)?),
+            _ => Cow::Borrowed(blob.content()),
+        };
+        // content written with `write_blob_for` is already encrypted for `path`
+        if matches!(encryption.decrypt(path, &stored), Ok(Some(_))) {
+            return match pointer {
+                Some(_) => Ok(blob_id),
+                None => self.offload_large_blob(repo, blob_id),
+            };
+        }
+        // the plain content is left unreferenced until it is pruned
+        self.write_content(repo, &encryption.encrypt(path, &stored)?)
+    }
 
-        let head_commit = main_ref.peel_to_commit()?;
+    /// Moves the blob to the large object store if it is over the threshold or looks like a
+    /// pointer, and returns the id of the blob to commit in its place. Committing content
+    /// which looks like a pointer as it is would let it be read as the large object it points
+    /// to.
+    fn offload_large_blob(
+        &self,
+        repo: &Repository,
+        blob_id: Oid,
+    ) -> Result<Oid, GitDataStoreError> {
+        let (size, _kind) = repo.odb()?.read_header(blob_id)?;
+        match &self.large_objects {
+            Some(large_objects)
+                if large_objects.is_large(size) || is_pointer_blob(repo, blob_id)? =>
+            {
+                // the original blob is left unreachable in the object database
+                let pointer = large_objects.store_blob(repo, blob_id)?;
+                self.write_pointer(repo, &pointer)
+            }
+            _ => Ok(repo.find_blob(blob_id)?.id()),
+        }
+    }
 
-        let mut index = Index::new()?;
-        index.read_tree(&head_commit.tree()?)?;
-        repo.set_index(&mut index)?;
+    /// Writes `content` into the large object store if it is over the threshold or looks like
+    /// a pointer, into the repository otherwise, and returns the id of the blob to commit.
+    fn write_content(&self, repo: &Repository, content: &[u8]) -> Result<Oid, GitDataStoreError> {
+        match &self.large_objects {
+            Some(large_objects)
+                if large_objects.is_large(content.len())
+                    || LargeObjectPointer::parse(content).is_some() =>
+            {
+                let pointer = large_objects.store(content)?;
+                self.write_pointer(repo, &pointer)
+            }
+            _ => Ok(repo.blob(content)?),
+        }
+    }
 
-        // https://libgit2.org/libgit2/#HEAD/type/git_index_stage_t
-        index.remove(&Path::new(path), -1)?;
+    fn write_pointer(
+        &self,
+        repo: &Repository,
+        pointer: &LargeObjectPointer,
+    ) -> Result<Oid, GitDataStoreError> {
+        let blob_id = repo.blob(pointer.to_document().as_bytes())?;
+        self.written_pointers.lock().insert(blob_id);
+        Ok(blob_id)
+    }
 
-        let tree_oid = index.write_tree_to(&repo)?;
+    fn blob_reader(
+        &self,
+        repo: Repository,
+        path: &str,
+        blob: FoundBlob,
+    ) -> Result<BlobReader, GitDataStoreError> {
+        let FoundBlob {
+            blob_id,
+            size,
+            commit_id,
+            metadata,
+        } = blob;
+        let pointer = match &self.large_objects {
+            Some(_) if size <= MAX_POINTER_SIZE => {
+                LargeObjectPointer::parse(repo.find_blob(blob_id)?.content())
+            }
+            _ => None,
+        };
 
-        let tree = repo.find_tree(tree_oid)?;
-        let author_commiter: git2::Signature = signature
-            .map(|s| s.into())
-            .unwrap_or_else(|| repo.signature())?;
+        let reader = match (&self.large_objects, pointer) {
+            (Some(large_objects), Some(pointer)) => BlobReader::new(
+                BlobSource::LargeObject {
+                    large_objects: large_objects.clone(),
+                    size: pointer.size,
+                    pointer,
+                },
+                commit_id,
+                metadata,
+            ),
+            _ => BlobReader::new(
+                BlobSource::Git {
+                    repo,
+                    blob_id,
+                    size,
+                },
+                commit_id,
+                metadata,
+            ),
+        };
 
-        let commit_id = repo.commit(
-            Some(&format!("refs/heads/{}", self.primary_branch)),
-            &author_commiter,
-            &author_commiter,
-            commit_msg.unwrap_or(format!("Deleted {}", path).as_str()),
-            &tree,
-            &[&head_commit],
-        )?;
-        Ok(commit_id.to_string())
+        // encrypted documents are decrypted in memory, they cannot be read in chunks
+        let mut header = Vec::new();
+        reader.for_each_chunk(0..ENCRYPTED_HEADER.len(), |chunk| {
+            header = chunk;
+            false
+        })?;
+        let encrypted_path = self
+            .encryption
+            .as_ref()
+            .is_none_or(|encryption| encryption.applies_to(path));
+        if !encrypted_path || !encryption::is_encrypted(&header) {
+            return Ok(reader);
+        }
+        let mut content = Vec::with_capacity(reader.size());
+        for chunk in reader.chunks(0..reader.size())? {
+            content.extend(chunk?);
+        }
+        let data =
+            encryption::decrypt(self.encryption.as_ref(), path, &content)?.unwrap_or(content);
+        Ok(BlobReader::new(
+            BlobSource::Memory {
+                blob_id: reader.blob_id(),
+                data,
+            },
+            reader.commit_id().to_string(),
+            reader.metadata().cloned(),
+        ))
     }
 
-    fn create_tree(
+    fn remove_from_tree(
         &self,
         repo: &Repository,
         path: &str,
-        data: &str,
         head_commit: &Commit,
     ) -> Result<Oid, GitDataStoreError> {
         let mut index = Index::new()?;
         index.read_tree(&head_commit.tree()?)?;
         repo.set_index(&mut index)?;
-        index.add_frombuffer(&make_index_entry(&path), data.as_bytes())?;
 
-        let tree_oid = index.write_tree_to(&repo)?;
+        // https://libgit2.org/libgit2/#HEAD/type/git_index_stage_t
+        index.remove(Path::new(path), -1)?;
+        match index.remove(Path::new(&metadata_path(path)), -1) {
+            Err(err) if err.code() != ErrorCode::NotFound => return Err(err.into()),
+            _ => {}
+        }
+
+        let tree_oid = index.write_tree_to(repo)?;
         Ok(tree_oid)
     }
 }
 
+fn add_metadata(
+    index: &mut Index,
+    path: &str,
+    metadata: Option<&Metadata>,
+) -> Result<(), GitDataStoreError> {
+    if let Some(metadata) = metadata {
+        index.add_frombuffer(
+            &make_index_entry(&metadata_path(path)),
+            metadata.to_document(path)?.as_bytes(),
+        )?;
+    }
+    Ok(())
+}
+
 pub fn make_index_entry(path: &str) -> IndexEntry {
     IndexEntry {
         ctime: IndexTime::new(0, 0),
@@ -339,10 +1778,12 @@ pub fn create_branch<'repo>(
     )?)
 }
 
-fn tree_to_dir(tree: &git2::Tree) -> GitData {
+/// The reserved metadata tree is hidden from the listing of the root directory.
+fn tree_to_dir(tree: &git2::Tree, is_root: bool) -> GitData {
     GitData::Dir {
         entries: tree
             .into_iter()
+            .filter(|e| !(is_root && e.name() == Some(METADATA_DIR)))
             .map(|e| {
                 let is_dir = match e.kind().expect("TreeEntry should have kind") {
                     git2::ObjectType::Tree => true,
@@ -363,13 +1804,15 @@ fn read_entry_from_tree(
     repo: &Repository,
     commit: &git2::Commit,
     path: &str,
+    codec: BlobCodec,
 ) -> Result<Option<GitEntry>, GitDataStoreError> {
     let tree = commit.tree()?;
 
     if ROOT_PATHS.contains(&path) {
         Ok(Some(GitEntry {
-            data: tree_to_dir(&tree),
+            data: tree_to_dir(&tree, true),
             commit_id: commit.id().to_string(),
+            metadata: None,
         }))
     } else {
         let entry = match tree.get_path(Path::new(path)) {
@@ -390,15 +1833,16 @@ fn read_entry_from_tree(
                     git2::ObjectType::Tree => {
                         let obj = entry.to_object(&repo)?;
                         let tree = obj.as_tree().expect("tree is not a tree");
-                        tree_to_dir(&tree)
+                        tree_to_dir(&tree, false)
                     }
                     git2::ObjectType::Blob => {
                         let obj = entry.to_object(&repo)?;
                         let blob = obj.as_blob().expect("blob is not blob");
+                        let content = codec.decode(path, blob.content())?.into_owned();
 
                         // Should non-utf8 data be returned as base-64 encoded?
                         GitData::File {
-                            data: String::from_utf8(blob.content().to_owned()).map_err(|_e| {
+                            data: String::from_utf8(content).map_err(|_e| {
                                 GitDataStoreError::NonUtf8Blob {
                                     commit_id: commit.id().to_string(),
                                     path: path.to_string(),
@@ -411,15 +1855,168 @@ fn read_entry_from_tree(
                     }
                 };
 
+                let metadata = if git_data.is_file() {
+                    metadata::read_metadata(repo, &tree, path)?
+                } else {
+                    None
+                };
+
                 Ok(GitEntry {
                     data: git_data,
                     commit_id: commit.id().to_string(),
+                    metadata,
                 })
             })
             .transpose()
     }
 }
 
+/// Id of the blob or tree at `path`, `None` if there is nothing at `path`.
+/// Whether the blob looks like a large object pointer, without reading larger blobs.
+fn is_pointer_blob(repo: &Repository, blob_id: Oid) -> Result<bool, GitDataStoreError> {
+    let (size, _kind) = repo.odb()?.read_header(blob_id)?;
+    Ok(size <= MAX_POINTER_SIZE
+        && LargeObjectPointer::parse(repo.find_blob(blob_id)?.content()).is_some())
+}
+
+fn entry_id(tree: &git2::Tree, path: &str) -> Result<Option<Oid>, GitDataStoreError> {
+    if ROOT_PATHS.contains(&path) {
+        return Ok(Some(tree.id()));
+    }
+    match tree.get_path(Path::new(path)) {
+        Ok(entry) => Ok(Some(entry.id())),
+        Err(err) if err.code() == ErrorCode::NotFound => Ok(None),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Tree of `head_tree` with the entry at `path`, and its metadata, as they are in `from_tree`.
+fn restore_path(
+    repo: &Repository,
+    from_tree: &Tree,
+    head_tree: &Tree,
+    path: &str,
+) -> Result<Oid, GitDataStoreError> {
+    let path = path.trim_matches('/');
+    if ROOT_PATHS.contains(&path) {
+        return Ok(from_tree.id());
+    }
+    let mut update = TreeUpdateBuilder::new();
+    for path in &[path.to_string(), metadata_path(path)] {
+        match from_tree.get_path(Path::new(path)) {
+            Ok(entry) => {
+                let mode = match entry.kind() {
+                    Some(ObjectType::Tree) => FileMode::Tree,
+                    _ => FileMode::Blob,
+                };
+                update.upsert(path.as_str(), entry.id(), mode);
+            }
+            Err(err) if err.code() == ErrorCode::NotFound => {
+                if entry_id(head_tree, path)?.is_some() {
+                    update.remove(path.as_str());
+                }
+            }
+            Err(err) => return Err(err.into()),
+        }
+    }
+    Ok(update.create_updated(repo, head_tree)?)
+}
+
+/// Commit `commit_id`, which is the name of a tag or any revision `git rev-parse` accepts,
+/// e.g. a commit id, possibly abbreviated, or a branch name.
+fn find_commit<'repo>(
+    repo: &'repo Repository,
+    commit_id: &str,
+) -> Result<Commit<'repo>, GitDataStoreError> {
+    let not_found = |_| GitDataStoreError::RevNotFound(commit_id.to_string());
+    match repo
+        .find_reference(&format!("{}{}", TAGS_REFS_PREFIX, commit_id))
+        .and_then(|tag| tag.peel_to_commit())
+    {
+        Ok(commit) => Ok(commit),
+        Err(_) => repo
+            .revparse_single(commit_id)
+            .and_then(|object| object.peel_to_commit())
+            .map_err(not_found),
+    }
+}
+
+struct FoundBlob {
+    blob_id: Oid,
+    size: usize,
+    commit_id: String,
+    metadata: Option<Metadata>,
+}
+
+fn find_blob_in_tree(
+    repo: &Repository,
+    commit: &Commit,
+    path: &str,
+) -> Result<Option<FoundBlob>, GitDataStoreError> {
+    let tree = commit.tree()?;
+    let entry = match tree.get_path(Path::new(path)) {
+        Ok(entry) => entry,
+        Err(err) if err.code() == git2::ErrorCode::NotFound => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+
+    if entry.kind() != Some(git2::ObjectType::Blob) {
+        return Ok(None);
+    }
+    let (size, _kind) = repo.odb()?.read_header(entry.id())?;
+
+    Ok(Some(FoundBlob {
+        blob_id: entry.id(),
+        size,
+        commit_id: commit.id().to_string(),
+        metadata: metadata::read_metadata(repo, &tree, path)?,
+    }))
+}
+
+/// Returns the metadata of path at `head_commit` with its content type replaced,
+/// or `None` if there is no content type to set.
+fn with_content_type(
+    repo: &Repository,
+    head_commit: &Commit,
+    path: &str,
+    content_type: Option<&str>,
+) -> Result<Option<Metadata>, GitDataStoreError> {
+    content_type
+        .map(|content_type| {
+            let mut metadata =
+                metadata::read_metadata(repo, &head_commit.tree()?, path)?.unwrap_or_default();
+            metadata.content_type = Some(content_type.to_string());
+            Ok(metadata)
+        })
+        .transpose()
+}
+
+fn check_path(path: &str) -> Result<(), GitDataStoreError> {
+    if metadata::is_reserved_path(path) {
+        return Err(GitDataStoreError::ReservedPath(path.to_string()));
+    }
+    Ok(())
+}
+
+fn check_conflict(
+    repo: &Repository,
+    path: &str,
+    parent_commit: &Commit,
+    head_commit: &Commit,
+    overwrite: bool,
+) -> Result<(), GitDataStoreError> {
+    if head_commit.id() != parent_commit.id()
+        && !overwrite
+        && has_conflict(repo, path, parent_commit, head_commit)?
+    {
+        return Err(GitDataStoreError::ConflictOnWrite {
+            path: path.to_string(),
+            parent_commit_id: parent_commit.id().to_string(),
+        });
+    }
+    Ok(())
+}
+
 fn has_conflict(
     repo: &Repository,
     path: &str,
@@ -428,6 +2025,7 @@ fn has_conflict(
 ) -> Result<bool, GitDataStoreError> {
     let mut diff_options = DiffOptions::new();
     diff_options.pathspec(path);
+    diff_options.pathspec(metadata_path(path));
     let diff = repo.diff_tree_to_tree(
         Some(&parent_commit.tree()?),
         Some(&head_commit.tree()?),
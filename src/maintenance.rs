@@ -0,0 +1,261 @@
+use crate::{error::GitDataStoreError, large_object::LargeObjectGcReport, GitDataStore};
+use chrono::{DateTime, Utc};
+use git2::Oid;
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::{
+    fs, io,
+    path::Path,
+    process::{Command, Output},
+    time::{Duration, Instant},
+};
+
+#[derive(Clone, Debug)]
+pub struct MaintenanceOptions {
+    /// Unreachable objects, and unreferenced large objects, younger than this are kept since
+    /// they may belong to writes which are not committed yet.
+    pub grace_period: Duration,
+    /// Verifies the integrity of every object and the consistency of the references.
+    pub fsck: bool,
+}
+
+impl Default for MaintenanceOptions {
+    fn default() -> Self {
+        MaintenanceOptions {
+            grace_period: Duration::from_secs(3600),
+            fsck: true,
+        }
+    }
+}
+
+/// Object database statistics, as reported by `git count-objects`.
+#[derive(Clone, Debug, Default, Serialize, PartialEq, Eq)]
+pub struct ObjectCounts {
+    pub loose_objects: u64,
+    pub loose_size_kib: u64,
+    pub packed_objects: u64,
+    pub packs: u64,
+    pub packs_size_kib: u64,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct MaintenanceReport {
+    pub datetime: DateTime<Utc>,
+    pub duration_ms: u128,
+    pub before: ObjectCounts,
+    pub after: ObjectCounts,
+    /// Problems found by fsck, empty when the repository is consistent or fsck was skipped.
+    pub fsck_problems: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub large_objects: Option<LargeObjectGcReport>,
+}
+
+impl MaintenanceReport {
+    pub fn is_healthy(&self) -> bool {
+        self.fsck_problems.is_empty()
+    }
+}
+
+/// Repacks the repository at `repo_path` into a single pack, prunes the unreachable objects
+/// older than the grace period and verifies the repository. Runs `git` since libgit2 can
+/// neither repack nor prune. Safe to run while the store is written to, like `git gc`.
+pub(crate) fn run(
+    repo_path: &Path,
+    options: &MaintenanceOptions,
+) -> Result<MaintenanceReport, GitDataStoreError> {
+    let datetime = Utc::now();
+    let started = Instant::now();
+    let before = count_objects(repo_path)?;
+
+    // unreachable objects leave the packs as loose objects, which prune removes once they
+    // are older than the grace period
+    git(repo_path, &["repack", "-A", "-d", "-q"])?;
+    git(
+        repo_path,
+        &[
+            "prune",
+            &format!("--expire={}.seconds.ago", options.grace_period.as_secs()),
+        ],
+    )?;
+    let fsck_problems = if options.fsck {
+        fsck(repo_path)?
+    } else {
+        Vec::new()
+    };
+
+    Ok(MaintenanceReport {
+        datetime,
+        duration_ms: started.elapsed().as_millis(),
+        before,
+        after: count_objects(repo_path)?,
+        fsck_problems,
+        large_objects: None,
+    })
+}
+
+fn count_objects(repo_path: &Path) -> Result<ObjectCounts, GitDataStoreError> {
+    let output = git(repo_path, &["count-objects", "-v"])?;
+    let mut counts = ObjectCounts::default();
+    for line in output.lines() {
+        let (name, value) = match line.split_once(": ") {
+            Some((name, value)) => (name, value.trim().parse().unwrap_or_default()),
+            None => continue,
+        };
+        match name {
+            "count" => counts.loose_objects = value,
+            "size" => counts.loose_size_kib = value,
+            "in-pack" => counts.packed_objects = value,
+            "packs" => counts.packs = value,
+            "size-pack" => counts.packs_size_kib = value,
+            _ => {}
+        }
+    }
+    Ok(counts)
+}
+
+/// Corrupt or missing objects and broken references. Dangling objects are expected since
+/// unreachable objects are kept for the grace period.
+fn fsck(repo_path: &Path) -> Result<Vec<String>, GitDataStoreError> {
+    let output = git_output(
+        repo_path,
+        &[
+            "fsck",
+            "--full",
+            "--strict",
+            "--no-progress",
+            "--no-dangling",
+        ],
+    )?;
+    let mut problems: Vec<String> = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .chain(String::from_utf8_lossy(&output.stderr).lines())
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+        .collect();
+    if problems.is_empty() && !output.status.success() {
+        problems.push(format!("git fsck failed with {}", output.status));
+    }
+    Ok(problems)
+}
+
+/// Expires the reflogs of the rewritten references `refs`, which still reach the replaced
+/// history, and removes from the object database, packs included, the objects reachable from
+/// `old_tips` but from no reference anymore, i.e. the history a purge replaced. Other
+/// unreachable objects, such as the blobs of writes which are not committed yet, are left to
+/// the grace period of the maintenance.
+pub(crate) fn prune_purged(
+    repo_path: &Path,
+    refs: &[String],
+    old_tips: &[Oid],
+) -> Result<(), GitDataStoreError> {
+    if !refs.is_empty() {
+        let mut args = vec![
+            "reflog",
+            "expire",
+            "--expire=now",
+            "--expire-unreachable=now",
+        ];
+        args.extend(refs.iter().map(String::as_str));
+        git(repo_path, &args)?;
+    }
+    // the unreachable objects leave the packs as loose objects, which can be removed one by one
+    git(repo_path, &["repack", "-A", "-d", "-q"])?;
+    if old_tips.is_empty() {
+        return Ok(());
+    }
+    let tips = old_tips.iter().map(Oid::to_string).collect::<Vec<_>>();
+    let mut args = vec!["rev-list", "--objects"];
+    args.extend(tips.iter().map(String::as_str));
+    args.extend(&["--not", "--all"]);
+    for line in git(repo_path, &args)?.lines() {
+        let id = match line.split_whitespace().next() {
+            Some(id) if id.len() > 2 => id,
+            _ => continue,
+        };
+        let path = repo_path.join("objects").join(&id[..2]).join(&id[2..]);
+        match fs::remove_file(&path) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err.into()),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Runs `git` in the repository at `repo_path`, returning its standard output.
+pub(crate) fn git(repo_path: &Path, args: &[&str]) -> Result<String, GitDataStoreError> {
+    let output = git_output(repo_path, args)?;
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    } else {
+        Err(GitDataStoreError::GitCommand {
+            command: format!("git {}", args.join(" ")),
+            error: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        })
+    }
+}
+
+fn git_output(repo_path: &Path, args: &[&str]) -> Result<Output, GitDataStoreError> {
+    Command::new("git")
+        .arg("-C")
+        .arg(repo_path)
+        .args(args)
+        .output()
+        .map_err(|e| GitDataStoreError::GitCommand {
+            command: format!("git {}", args.join(" ")),
+            error: e.to_string(),
+        })
+}
+
+/// Runs the maintenance of the server on a schedule and keeps the outcome of the last run
+/// for the status endpoint.
+pub struct Maintenance {
+    options: MaintenanceOptions,
+    last_run: Mutex<Option<LastMaintenance>>,
+    last_report: Mutex<Option<MaintenanceReport>>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct LastMaintenance {
+    pub datetime: DateTime<Utc>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MaintenanceStatus {
+    pub last_run: Option<LastMaintenance>,
+    /// Report of the last run which succeeded.
+    pub last_report: Option<MaintenanceReport>,
+}
+
+impl Maintenance {
+    pub fn new(options: MaintenanceOptions) -> Self {
+        Maintenance {
+            options,
+            last_run: Mutex::new(None),
+            last_report: Mutex::new(None),
+        }
+    }
+
+    /// Runs the maintenance of `store` and records the outcome for [`Maintenance::status`].
+    pub fn run(&self, store: &GitDataStore) -> Result<MaintenanceReport, GitDataStoreError> {
+        let datetime = Utc::now();
+        let result = store.maintain(&self.options);
+        if let Ok(report) = &result {
+            *self.last_report.lock() = Some(report.clone());
+        }
+        *self.last_run.lock() = Some(LastMaintenance {
+            datetime,
+            error: result.as_ref().err().map(|err| err.to_string()),
+        });
+        result
+    }
+
+    pub fn status(&self) -> MaintenanceStatus {
+        MaintenanceStatus {
+            last_run: self.last_run.lock().clone(),
+            last_report: self.last_report.lock().clone(),
+        }
+    }
+}
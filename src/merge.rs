@@ -0,0 +1,106 @@
+use crate::error::GitDataStoreError;
+use git2::{Commit, FileFavor, Index, IndexEntry, MergeOptions, Oid, Repository};
+use serde::Serialize;
+use std::str::FromStr;
+
+/// How diverged histories are reconciled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MergeStrategy {
+    /// Fail with `GitDataStoreError::NonFastForward` instead of creating a merge commit.
+    FastForwardOnly,
+    /// Create a merge commit, failing with `GitDataStoreError::MergeConflicts` on conflicts.
+    Merge,
+    /// Create a merge commit, resolving conflicting content in favour of our side.
+    Ours,
+    /// Create a merge commit, resolving conflicting content in favour of their side.
+    Theirs,
+}
+
+impl FromStr for MergeStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ff-only" => Ok(MergeStrategy::FastForwardOnly),
+            "merge" => Ok(MergeStrategy::Merge),
+            "ours" => Ok(MergeStrategy::Ours),
+            "theirs" => Ok(MergeStrategy::Theirs),
+            _ => Err(format!(
+                "invalid merge strategy {}, expected one of ff-only, merge, ours, theirs",
+                s
+            )),
+        }
+    }
+}
+
+impl MergeStrategy {
+    pub(crate) fn merge_options(&self) -> MergeOptions {
+        let mut options = MergeOptions::new();
+        match self {
+            MergeStrategy::Ours => {
+                options.file_favor(FileFavor::Ours);
+            }
+            MergeStrategy::Theirs => {
+                options.file_favor(FileFavor::Theirs);
+            }
+            MergeStrategy::FastForwardOnly | MergeStrategy::Merge => {}
+        }
+        options
+    }
+}
+
+/// A path which could not be merged. The ids are the blob ids of each side,
+/// `None` when the path does not exist on that side.
+#[derive(Clone, Debug, Serialize, PartialEq, Eq)]
+pub struct MergeConflict {
+    pub path: String,
+    pub ancestor_id: Option<String>,
+    pub our_id: Option<String>,
+    pub their_id: Option<String>,
+}
+
+pub(crate) fn conflicts(index: &Index) -> Result<Vec<MergeConflict>, GitDataStoreError> {
+    let entry_path = |entry: &Option<IndexEntry>| {
+        entry
+            .as_ref()
+            .map(|e| String::from_utf8_lossy(&e.path).to_string())
+    };
+    let entry_id = |entry: &Option<IndexEntry>| entry.as_ref().map(|e| e.id.to_string());
+
+    index
+        .conflicts()?
+        .map(|conflict| {
+            let conflict = conflict?;
+            Ok(MergeConflict {
+                path: entry_path(&conflict.our)
+                    .or_else(|| entry_path(&conflict.their))
+                    .or_else(|| entry_path(&conflict.ancestor))
+                    .unwrap_or_default(),
+                ancestor_id: entry_id(&conflict.ancestor),
+                our_id: entry_id(&conflict.our),
+                their_id: entry_id(&conflict.their),
+            })
+        })
+        .collect()
+}
+
+/// Merges `their_commit` into `our_commit` without a working directory and returns the
+/// merged tree.
+pub(crate) fn merge_trees(
+    repo: &Repository,
+    our_commit: &Commit,
+    their_commit: &Commit,
+    strategy: MergeStrategy,
+) -> Result<Oid, GitDataStoreError> {
+    let index = repo.merge_commits(our_commit, their_commit, Some(&strategy.merge_options()))?;
+    write_tree(repo, index)
+}
+
+/// Writes the tree of a merged `index`, failing with `GitDataStoreError::MergeConflicts` if
+/// it has conflicts.
+pub(crate) fn write_tree(repo: &Repository, mut index: Index) -> Result<Oid, GitDataStoreError> {
+    if index.has_conflicts() {
+        return Err(GitDataStoreError::MergeConflicts(conflicts(&index)?));
+    }
+    Ok(index.write_tree_to(repo)?)
+}
@@ -0,0 +1,113 @@
+use crate::error::GitDataStoreError;
+use git2::{ObjectType, Repository, Tree};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, path::Path};
+
+/// Reserved tree holding the metadata of every document at the same path as the document,
+/// e.g. the metadata of `docs/doc1` is stored at `.meta/docs/doc1`. Since it is part of the
+/// same tree, metadata is versioned with the document.
+pub const METADATA_DIR: &str = ".meta";
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Metadata {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub creator: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub labels: BTreeMap<String, String>,
+}
+
+impl Metadata {
+    pub fn matches_labels(&self, labels: &BTreeMap<String, String>) -> bool {
+        labels
+            .iter()
+            .all(|(key, value)| self.labels.get(key) == Some(value))
+    }
+
+    pub(crate) fn to_document(&self, path: &str) -> Result<String, GitDataStoreError> {
+        serde_json::to_string(self).map_err(|e| GitDataStoreError::InvalidMetadata {
+            path: path.to_string(),
+            error: e.to_string(),
+        })
+    }
+
+    fn from_document(path: &str, content: &[u8]) -> Result<Self, GitDataStoreError> {
+        serde_json::from_slice(content).map_err(|e| GitDataStoreError::InvalidMetadata {
+            path: path.to_string(),
+            error: e.to_string(),
+        })
+    }
+}
+
+pub fn metadata_path(path: &str) -> String {
+    format!("{}/{}", METADATA_DIR, path.trim_start_matches('/'))
+}
+
+/// Documents cannot be written inside the reserved metadata tree.
+pub fn is_reserved_path(path: &str) -> bool {
+    Path::new(path.trim_start_matches('/')).starts_with(METADATA_DIR)
+}
+
+pub(crate) fn read_metadata(
+    repo: &Repository,
+    tree: &Tree,
+    path: &str,
+) -> Result<Option<Metadata>, GitDataStoreError> {
+    let entry = match tree.get_path(Path::new(&metadata_path(path))) {
+        Ok(entry) => entry,
+        Err(err) if err.code() == git2::ErrorCode::NotFound => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+    if entry.kind() != Some(ObjectType::Blob) {
+        return Ok(None);
+    }
+    let blob = repo.find_blob(entry.id())?;
+    Ok(Some(Metadata::from_document(path, blob.content())?))
+}
+
+/// Returns the paths of the documents whose metadata has all of `labels`.
+pub(crate) fn find_by_labels(
+    repo: &Repository,
+    tree: &Tree,
+    labels: &BTreeMap<String, String>,
+) -> Result<Vec<String>, GitDataStoreError> {
+    let metadata_tree = match tree.get_name(METADATA_DIR) {
+        Some(entry) if entry.kind() == Some(ObjectType::Tree) => repo.find_tree(entry.id())?,
+        _ => return Ok(Vec::new()),
+    };
+
+    let mut paths = Vec::new();
+    collect_matching(repo, &metadata_tree, "", labels, &mut paths)?;
+    Ok(paths)
+}
+
+fn collect_matching(
+    repo: &Repository,
+    tree: &Tree,
+    prefix: &str,
+    labels: &BTreeMap<String, String>,
+    paths: &mut Vec<String>,
+) -> Result<(), GitDataStoreError> {
+    for entry in tree.iter() {
+        let name = match entry.name() {
+            Some(name) => name,
+            None => continue,
+        };
+        let path = format!("{}{}", prefix, name);
+        match entry.kind() {
+            Some(ObjectType::Tree) => {
+                let subtree = repo.find_tree(entry.id())?;
+                collect_matching(repo, &subtree, &format!("{}/", path), labels, paths)?;
+            }
+            Some(ObjectType::Blob) => {
+                let blob = repo.find_blob(entry.id())?;
+                if Metadata::from_document(&path, blob.content())?.matches_labels(labels) {
+                    paths.push(path);
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,86 @@
+use crate::error::GitDataStoreError;
+use serde::Serialize;
+use serde_json::{ser::PrettyFormatter, Serializer, Value};
+
+/// Media type of RFC 6902 JSON Patch documents.
+pub const JSON_PATCH_CONTENT_TYPE: &str = "application/json-patch+json";
+
+/// Media type of RFC 7396 JSON Merge Patch documents.
+pub const MERGE_PATCH_CONTENT_TYPE: &str = "application/merge-patch+json";
+
+/// Change to apply to a JSON document instead of replacing it whole.
+#[derive(Clone, Debug)]
+pub enum Patch {
+    /// RFC 6902 list of operations, applied atomically.
+    Json(json_patch::Patch),
+    /// RFC 7396 document merged into the current one, `null` members removing fields.
+    Merge(Value),
+}
+
+impl Patch {
+    /// Parses `body` according to its media type, parameters such as `charset` are ignored.
+    pub fn parse(content_type: &str, body: &[u8]) -> Result<Self, GitDataStoreError> {
+        let invalid_body = |e: serde_json::Error| {
+            GitDataStoreError::RequestBody(format!("invalid {}: {}", content_type, e))
+        };
+        match content_type.split(';').next().unwrap_or_default().trim() {
+            JSON_PATCH_CONTENT_TYPE => Ok(Patch::Json(
+                serde_json::from_slice(body).map_err(invalid_body)?,
+            )),
+            MERGE_PATCH_CONTENT_TYPE => Ok(Patch::Merge(
+                serde_json::from_slice(body).map_err(invalid_body)?,
+            )),
+            _ => Err(GitDataStoreError::UnsupportedPatch(
+                content_type.to_string(),
+            )),
+        }
+    }
+
+    /// Applies the patch to `document`, a missing document being patched as `null`.
+    /// Returns the patched document, formatted like `document` so that only the patched
+    /// fields show up in the history.
+    pub fn apply(&self, path: &str, document: Option<&str>) -> Result<String, GitDataStoreError> {
+        let patch_failed = |error: String| GitDataStoreError::PatchFailed {
+            path: path.to_string(),
+            error,
+        };
+        let mut value = match document {
+            Some(document) => serde_json::from_str(document)
+                .map_err(|e| patch_failed(format!("document is not JSON: {}", e)))?,
+            None => Value::Null,
+        };
+        match self {
+            Patch::Json(patch) => {
+                json_patch::patch(&mut value, patch).map_err(|e| patch_failed(e.to_string()))?
+            }
+            Patch::Merge(patch) => json_patch::merge(&mut value, patch),
+        }
+        to_string_like(&value, document).map_err(|e| patch_failed(e.to_string()))
+    }
+}
+
+/// Serializes `value` on one line if `document` was on one line, otherwise indented like
+/// `document`, keeping its trailing whitespace. Fields keep their order. New documents are
+/// indented with two spaces.
+fn to_string_like(value: &Value, document: Option<&str>) -> serde_json::Result<String> {
+    let document = match document {
+        Some(document) => document,
+        None => return serde_json::to_string_pretty(value),
+    };
+    let content = document.trim_end();
+    let mut json = if content.contains('\n') {
+        let indent = content
+            .lines()
+            .map(|line| &line[..line.len() - line.trim_start().len()])
+            .find(|indent| !indent.is_empty())
+            .unwrap_or("  ");
+        let mut json = Vec::new();
+        let formatter = PrettyFormatter::with_indent(indent.as_bytes());
+        value.serialize(&mut Serializer::with_formatter(&mut json, formatter))?;
+        String::from_utf8(json).expect("serde_json writes utf-8")
+    } else {
+        serde_json::to_string(value)?
+    };
+    json.push_str(&document[content.len()..]);
+    Ok(json)
+}
@@ -0,0 +1,55 @@
+use crate::error::GitDataStoreError;
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+
+/// Set of globs matched against document paths. `*` does not match `/`, `**` matches any
+/// number of directories, e.g. `docs/**/*.json`. An empty set matches every path.
+#[derive(Clone, Debug)]
+pub struct PathGlobs {
+    globs: Vec<String>,
+    set: GlobSet,
+}
+
+impl PathGlobs {
+    pub fn new<S: AsRef<str>>(globs: &[S]) -> Result<Self, GitDataStoreError> {
+        let mut builder = GlobSetBuilder::new();
+        for glob in globs {
+            let glob = GlobBuilder::new(glob.as_ref().trim_start_matches('/'))
+                .literal_separator(true)
+                .build()
+                .map_err(|e| GitDataStoreError::InvalidConfig {
+                    path: glob.as_ref().to_string(),
+                    error: e.to_string(),
+                })?;
+            builder.add(glob);
+        }
+        Ok(PathGlobs {
+            globs: globs.iter().map(|glob| glob.as_ref().to_string()).collect(),
+            set: builder
+                .build()
+                .map_err(|e| GitDataStoreError::InvalidConfig {
+                    path: globs
+                        .iter()
+                        .map(|glob| glob.as_ref())
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                    error: e.to_string(),
+                })?,
+        })
+    }
+
+    /// Matches every path.
+    pub fn all() -> Self {
+        PathGlobs {
+            globs: Vec::new(),
+            set: GlobSet::empty(),
+        }
+    }
+
+    pub fn globs(&self) -> &[String] {
+        &self.globs
+    }
+
+    pub fn is_match(&self, path: &str) -> bool {
+        self.globs.is_empty() || self.set.is_match(path.trim_start_matches('/'))
+    }
+}
@@ -0,0 +1,54 @@
+use crate::{error::GitDataStoreError, GitData, GitEntry};
+use serde::Serialize;
+use serde_json::Value;
+
+/// Part of a JSON document selected with a JSON pointer.
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct PointerEntry {
+    pub pointer: String,
+    pub value: Value,
+    pub commit_id: String,
+}
+
+/// Turns `expression` into an RFC 6901 JSON pointer. Expressions starting with `/` already are
+/// JSON pointers, others are simple paths whose segments are separated by dots, e.g.
+/// `spec.replicas` for `/spec/replicas`.
+pub fn to_json_pointer(expression: &str) -> String {
+    if expression.is_empty() || expression.starts_with('/') {
+        return expression.to_string();
+    }
+    expression
+        .split('.')
+        .map(|segment| format!("/{}", segment.replace('~', "~0").replace('/', "~1")))
+        .collect()
+}
+
+/// Selects the value at `expression` in the document of `entry`. Returns `None` if the
+/// document has no value there.
+pub(crate) fn select(
+    path: &str,
+    entry: GitEntry,
+    expression: &str,
+) -> Result<Option<PointerEntry>, GitDataStoreError> {
+    let commit_id = entry.commit_id;
+    let data = match entry.data {
+        GitData::File { data } => data,
+        GitData::Dir { .. } => {
+            return Err(GitDataStoreError::NotJson {
+                path: path.to_string(),
+                error: "path is a directory".to_string(),
+            })
+        }
+    };
+    let document: Value = serde_json::from_str(&data).map_err(|e| GitDataStoreError::NotJson {
+        path: path.to_string(),
+        error: e.to_string(),
+    })?;
+
+    let pointer = to_json_pointer(expression);
+    Ok(document.pointer(&pointer).map(|value| PointerEntry {
+        value: value.clone(),
+        pointer,
+        commit_id,
+    }))
+}
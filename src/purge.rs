@@ -0,0 +1,260 @@
+use crate::{
+    compaction::ARCHIVE_REFS_PREFIX,
+    error::GitDataStoreError,
+    large_object::{LargeObjectPointer, MAX_POINTER_SIZE},
+    metadata::metadata_path,
+    tag::TAGS_REFS_PREFIX,
+};
+use git2::{Commit, ObjectType, Oid, Repository, Sort, Tree, TreeWalkMode, TreeWalkResult};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+const BRANCHES_REFS_PREFIX: &str = "refs/heads/";
+
+/// Content removed from every commit by a purge.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PurgeTarget {
+    /// A document with its metadata, or a directory and everything in it.
+    Path(String),
+    /// A blob, at whatever path it appears.
+    Blob(Oid),
+}
+
+impl PurgeTarget {
+    /// Whether the tree entry at `path` is removed.
+    fn matches(&self, path: &str, id: Oid, kind: Option<ObjectType>) -> bool {
+        match self {
+            PurgeTarget::Path(target) => path == target || *path == metadata_path(target),
+            PurgeTarget::Blob(blob_id) => kind == Some(ObjectType::Blob) && id == *blob_id,
+        }
+    }
+
+    /// Whether the tree at `dir` may hold removed entries.
+    fn may_contain(&self, dir: &str) -> bool {
+        match self {
+            PurgeTarget::Path(target) => {
+                let prefix = format!("{}/", dir);
+                target.starts_with(&prefix) || metadata_path(target).starts_with(&prefix)
+            }
+            PurgeTarget::Blob(_) => true,
+        }
+    }
+}
+
+/// Mapping of the history before a purge to the history after it, e.g. to fix references to
+/// commit ids kept outside of the repository.
+#[derive(Debug, Default, Serialize)]
+pub struct PurgeReport {
+    /// Id after the purge of every commit whose id changed, by id before the purge.
+    pub commits: BTreeMap<String, String>,
+    /// Commits which only changed purged content and were removed from the history.
+    pub dropped: Vec<String>,
+    /// New head of the branches, and of the archives of compacted histories, which were
+    /// rewritten.
+    pub branches: BTreeMap<String, String>,
+    /// New commit of the tags which were moved to the rewritten history.
+    pub tags: BTreeMap<String, String>,
+    /// Remote tracking branches deleted since they may still reference the purged content.
+    /// They are recreated by the next fetch.
+    pub deleted_refs: Vec<String>,
+    /// Large objects of the purged content which were removed from the large object store.
+    pub removed_large_objects: Vec<String>,
+}
+
+/// Rewrites the commits reachable from the local branches, the archives and the tags
+/// without the purged content.
+pub(crate) struct HistoryRewriter<'repo> {
+    repo: &'repo Repository,
+    target: PurgeTarget,
+    /// Rewritten trees by directory and id, `None` when nothing is left in the tree.
+    trees: HashMap<(String, Oid), Option<Oid>>,
+    /// Blobs of the removed entries, which may be pointers to large objects.
+    purged_blobs: HashSet<Oid>,
+    /// Branches and tags to move, with their commit before and after the rewrite.
+    pub moved_refs: Vec<(String, Oid, Oid)>,
+}
+
+impl<'repo> HistoryRewriter<'repo> {
+    pub fn new(repo: &'repo Repository, target: PurgeTarget) -> Self {
+        let target = match target {
+            PurgeTarget::Path(path) => PurgeTarget::Path(path.trim_matches('/').to_string()),
+            target => target,
+        };
+        HistoryRewriter {
+            repo,
+            target,
+            trees: HashMap::new(),
+            purged_blobs: HashSet::new(),
+            moved_refs: Vec::new(),
+        }
+    }
+
+    /// Rewrites every commit of the local branches and the tags, oldest first, creating the
+    /// rewritten commits with `create_commit(original, tree, parents)`. Returns the report
+    /// without moving the branches and tags.
+    pub fn rewrite<F>(&mut self, mut create_commit: F) -> Result<PurgeReport, GitDataStoreError>
+    where
+        F: FnMut(&Commit, &Tree, &[&Commit]) -> Result<Oid, GitDataStoreError>,
+    {
+        let mut refs = Vec::new();
+        for reference in self.repo.references()? {
+            let reference = reference?;
+            let name = match reference.name() {
+                Some(name) if name.starts_with(BRANCHES_REFS_PREFIX) => name,
+                Some(name) if name.starts_with(TAGS_REFS_PREFIX) => name,
+                Some(name) if name.starts_with(ARCHIVE_REFS_PREFIX) => name,
+                _ => continue,
+            };
+            if let Ok(commit) = reference.peel_to_commit() {
+                refs.push((name.to_string(), commit.id()));
+            }
+        }
+        let mut rev_walk = self.repo.revwalk()?;
+        rev_walk.set_sorting(Sort::TOPOLOGICAL | Sort::REVERSE)?;
+        for (_, commit_id) in &refs {
+            rev_walk.push(*commit_id)?;
+        }
+
+        let mut report = PurgeReport::default();
+        let mut commits: HashMap<Oid, Oid> = HashMap::new();
+        for rev in rev_walk {
+            let commit = self.repo.find_commit(rev?)?;
+            let tree = match self.rewrite_tree(&commit.tree()?, "")? {
+                Some(tree_id) => self.repo.find_tree(tree_id)?,
+                None => self.repo.find_tree(self.repo.treebuilder(None)?.write()?)?,
+            };
+            let mut parent_ids: Vec<Oid> = Vec::new();
+            for parent_id in commit.parent_ids() {
+                let parent_id = commits[&parent_id];
+                if !parent_ids.contains(&parent_id) {
+                    parent_ids.push(parent_id);
+                }
+            }
+
+            let new_id = if tree.id() == commit.tree_id()
+                && parent_ids == commit.parent_ids().collect::<Vec<_>>()
+            {
+                commit.id()
+            } else if parent_ids.len() == 1
+                && self.repo.find_commit(parent_ids[0])?.tree_id() == tree.id()
+                && commit.parent(0)?.tree_id() != commit.tree_id()
+            {
+                report.dropped.push(commit.id().to_string());
+                parent_ids[0]
+            } else {
+                let parents = parent_ids
+                    .iter()
+                    .map(|id| self.repo.find_commit(*id))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let new_id = create_commit(&commit, &tree, &parents.iter().collect::<Vec<_>>())?;
+                report
+                    .commits
+                    .insert(commit.id().to_string(), new_id.to_string());
+                new_id
+            };
+            commits.insert(commit.id(), new_id);
+        }
+
+        for (name, commit_id) in refs {
+            let new_id = commits[&commit_id];
+            if new_id == commit_id {
+                continue;
+            }
+            match name.strip_prefix(TAGS_REFS_PREFIX) {
+                Some(tag) => report.tags.insert(tag.to_string(), new_id.to_string()),
+                None => report.branches.insert(name.clone(), new_id.to_string()),
+            };
+            self.moved_refs.push((name, commit_id, new_id));
+        }
+        Ok(report)
+    }
+
+    fn rewrite_tree(&mut self, tree: &Tree, dir: &str) -> Result<Option<Oid>, GitDataStoreError> {
+        let key = (dir.to_string(), tree.id());
+        if let Some(rewritten) = self.trees.get(&key) {
+            return Ok(*rewritten);
+        }
+
+        let mut builder = self.repo.treebuilder(Some(tree))?;
+        let mut changed = false;
+        for entry in tree.iter() {
+            let name = entry.name().unwrap_or_default();
+            let path = if dir.is_empty() {
+                name.to_string()
+            } else {
+                format!("{}/{}", dir, name)
+            };
+            if self.target.matches(&path, entry.id(), entry.kind()) {
+                self.collect_blobs(entry.id(), entry.kind())?;
+                builder.remove(entry.name_bytes())?;
+                changed = true;
+            } else if entry.kind() == Some(ObjectType::Tree) && self.target.may_contain(&path) {
+                let subtree = self.repo.find_tree(entry.id())?;
+                match self.rewrite_tree(&subtree, &path)? {
+                    Some(subtree_id) if subtree_id == entry.id() => {}
+                    Some(subtree_id) => {
+                        builder.insert(entry.name_bytes(), subtree_id, entry.filemode())?;
+                        changed = true;
+                    }
+                    None => {
+                        builder.remove(entry.name_bytes())?;
+                        changed = true;
+                    }
+                }
+            }
+        }
+
+        let rewritten = if !changed {
+            Some(tree.id())
+        } else if builder.is_empty() {
+            None
+        } else {
+            Some(builder.write()?)
+        };
+        self.trees.insert(key, rewritten);
+        Ok(rewritten)
+    }
+
+    fn collect_blobs(
+        &mut self,
+        id: Oid,
+        kind: Option<ObjectType>,
+    ) -> Result<(), GitDataStoreError> {
+        match kind {
+            Some(ObjectType::Blob) => {
+                self.purged_blobs.insert(id);
+            }
+            Some(ObjectType::Tree) => {
+                let purged_blobs = &mut self.purged_blobs;
+                self.repo
+                    .find_tree(id)?
+                    .walk(TreeWalkMode::PreOrder, |_, entry| {
+                        if entry.kind() == Some(ObjectType::Blob) {
+                            purged_blobs.insert(entry.id());
+                        }
+                        TreeWalkResult::Ok
+                    })?;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Large objects the purged blobs point to.
+    pub fn purged_large_objects(&self) -> Result<Vec<LargeObjectPointer>, GitDataStoreError> {
+        let odb = self.repo.odb()?;
+        let mut pointers = Vec::new();
+        for blob_id in &self.purged_blobs {
+            let (size, _) = odb.read_header(*blob_id)?;
+            if size > MAX_POINTER_SIZE {
+                continue;
+            }
+            if let Some(pointer) =
+                LargeObjectPointer::parse(self.repo.find_blob(*blob_id)?.content())
+            {
+                pointers.push(pointer);
+            }
+        }
+        Ok(pointers)
+    }
+}
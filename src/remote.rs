@@ -0,0 +1,79 @@
+use crate::{credentials::Credentials, error::GitDataStoreError};
+use git2::{ErrorCode, FetchOptions, Oid, PushOptions, Repository};
+
+/// Reference under which the branch of a remote is fetched.
+pub fn tracking_ref(remote_name: &str, branch: &str) -> String {
+    format!("refs/remotes/{}/{}", remote_name, branch)
+}
+
+/// Fetches `branch` of the remote into its tracking reference and returns the fetched commit id.
+pub fn fetch(
+    repo: &Repository,
+    remote_name: &str,
+    branch: &str,
+    credentials: &Credentials,
+) -> Result<Oid, GitDataStoreError> {
+    let mut remote = repo.find_remote(remote_name)?;
+    let tracking_ref = tracking_ref(remote_name, branch);
+
+    let mut fetch_options = FetchOptions::new();
+    fetch_options.remote_callbacks(credentials.remote_callbacks());
+    remote.fetch(
+        &[&format!("+refs/heads/{}:{}", branch, tracking_ref)],
+        Some(&mut fetch_options),
+        None,
+    )?;
+
+    Ok(repo.refname_to_id(&tracking_ref)?)
+}
+
+/// Pushes `local_branch` to `branch` of the remote. The remote branch is only updated if it
+/// is a fast-forward.
+pub fn push(
+    repo: &Repository,
+    remote_name: &str,
+    local_branch: &str,
+    branch: &str,
+    credentials: &Credentials,
+) -> Result<(), GitDataStoreError> {
+    let mut remote = repo.find_remote(remote_name)?;
+    let remote_ref = format!("refs/heads/{}", branch);
+
+    let mut rejection = None;
+    let push_result = {
+        let mut callbacks = credentials.remote_callbacks();
+        callbacks.push_update_reference(|reference, status| {
+            if let Some(status) = status {
+                rejection = Some((reference.to_string(), status.to_string()));
+            }
+            Ok(())
+        });
+        let mut push_options = PushOptions::new();
+        push_options.remote_callbacks(callbacks);
+
+        remote.push(
+            &[&format!("refs/heads/{}:{}", local_branch, remote_ref)],
+            Some(&mut push_options),
+        )
+    };
+    match push_result {
+        Err(err) if err.code() == ErrorCode::NotFastForward => {
+            return Err(GitDataStoreError::NonFastForward { branch: remote_ref })
+        }
+        result => result?,
+    }
+
+    if let Some((reference, message)) = rejection {
+        return Err(GitDataStoreError::PushRejected { reference, message });
+    }
+
+    // keep the tracking reference in sync with what was pushed
+    let pushed = repo.refname_to_id(&format!("refs/heads/{}", local_branch))?;
+    repo.reference(
+        &tracking_ref(remote_name, branch),
+        pushed,
+        true,
+        "push: updating tracking reference",
+    )?;
+    Ok(())
+}
@@ -0,0 +1,73 @@
+use crate::{error::GitDataStoreError, GitDataStore};
+use chrono::{DateTime, Utc};
+use parking_lot::Mutex;
+use serde::Serialize;
+
+/// Read-only copy of the primary branch of an upstream repository. A replica never commits
+/// itself: every fetch resets its primary branch to the upstream branch.
+pub struct Replica {
+    remote: String,
+    branch: String,
+    last_fetch: Mutex<Option<LastFetch>>,
+    last_success: Mutex<Option<DateTime<Utc>>>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct LastFetch {
+    pub datetime: DateTime<Utc>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReplicationStatus {
+    pub remote: String,
+    pub branch: String,
+    /// Commit reads are currently served from.
+    pub commit_id: String,
+    /// Seconds since `commit_id` was committed upstream.
+    pub commit_age_secs: i64,
+    pub last_fetch: Option<LastFetch>,
+    /// Time of the last fetch which succeeded. Reads are at most this old.
+    pub last_success: Option<DateTime<Utc>>,
+}
+
+impl Replica {
+    pub fn new(remote: &str, branch: &str) -> Self {
+        Replica {
+            remote: remote.to_string(),
+            branch: branch.to_string(),
+            last_fetch: Mutex::new(None),
+            last_success: Mutex::new(None),
+        }
+    }
+
+    /// Fetches the upstream branch into the primary branch of `store` and records the outcome
+    /// for [`Replica::status`]. Returns the new head of the primary branch.
+    pub fn fetch(&self, store: &GitDataStore) -> Result<String, GitDataStoreError> {
+        let result = store.follow(&self.remote, &self.branch);
+        let now = Utc::now();
+        if result.is_ok() {
+            *self.last_success.lock() = Some(now);
+        }
+        *self.last_fetch.lock() = Some(LastFetch {
+            datetime: now,
+            error: result.as_ref().err().map(|err| err.to_string()),
+        });
+        result
+    }
+
+    pub fn status(&self, store: &GitDataStore) -> Result<ReplicationStatus, GitDataStoreError> {
+        let head = store.head()?;
+        Ok(ReplicationStatus {
+            remote: self.remote.clone(),
+            branch: self.branch.clone(),
+            commit_id: head.commit_id,
+            commit_age_secs: Utc::now()
+                .signed_duration_since(head.datetime)
+                .num_seconds(),
+            last_fetch: self.last_fetch.lock().clone(),
+            last_success: *self.last_success.lock(),
+        })
+    }
+}
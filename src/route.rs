@@ -1,17 +1,94 @@
-use crate::{error::GitDataStoreError, history::HistoryEntry, GitDataStore};
-use actix_web::{body::Body, delete, get, post, web, HttpResponse};
+use crate::{
+    acl::{Access, Permission},
+    auth::Identity,
+    blob::BlobReader,
+    changes::{ChangeStatus, CommitChange},
+    error::GitDataStoreError,
+    history::HistoryEntry,
+    maintenance::{Maintenance, MaintenanceStatus},
+    metadata::Metadata,
+    patch::Patch,
+    replica::{Replica, ReplicationStatus},
+    schema::SchemaEntry,
+    tag::TagEntry,
+    GitDataStore, Signature,
+};
+use actix_web::{
+    body::Body,
+    delete, get,
+    http::header::{
+        ContentDisposition, DispositionParam, DispositionType, ACCEPT_RANGES, CACHE_CONTROL,
+        CONTENT_RANGE, CONTENT_TYPE, RANGE, X_CONTENT_TYPE_OPTIONS,
+    },
+    patch, post, web, HttpRequest, HttpResponse,
+};
+use futures::{channel::mpsc, executor::block_on, SinkExt, StreamExt};
 use serde::{Deserialize, Serialize};
-use std::sync::Arc;
+use std::{
+    collections::BTreeMap,
+    io::{self, Read},
+    ops::Range,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Content type of raw files written without one.
+const DEFAULT_CONTENT_TYPE: &str = "application/octet-stream";
+
+/// Number of chunks buffered between the thread reading a blob and the response.
+const STREAM_BUFFER_CHUNKS: usize = 4;
+
+/// Interval at which change feeds and watches check the primary branch for new commits.
+/// Polling the branch also picks up commits made by other processes sharing the repository.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Number of polls without changes after which a comment is sent on a change feed, to keep
+/// the connection open and notice clients which went away.
+const CHANGES_KEEP_ALIVE_POLLS: u32 = 15;
+
+/// Most change feeds followed at once, since each of them polls the branch on its own thread.
+const MAX_CHANGES_CLIENTS: usize = 64;
+
+/// Number of change feeds being followed.
+static CHANGES_CLIENTS: AtomicUsize = AtomicUsize::new(0);
+
+/// Seconds a watch waits for a change when no timeout is given.
+const WATCH_DEFAULT_TIMEOUT_SECS: u64 = 30;
+
+/// Longest timeout a watch accepts, in seconds.
+const WATCH_MAX_TIMEOUT_SECS: u64 = 300;
+
+/// Header sent by `EventSource` clients when reconnecting.
+const LAST_EVENT_ID: &str = "Last-Event-ID";
+
+#[derive(Serialize, Deserialize)]
+pub struct GetDataQuery {
+    /// Only returns the value at this JSON pointer, or dotted path, of the document.
+    pointer: Option<String>,
+}
 
 #[get("/commits/{commit_id}/{file_path:.*}")]
 pub async fn get_data(
     store: web::Data<Arc<GitDataStore>>,
+    access: Access,
     path_params: web::Path<(String, String)>,
+    web::Query(query): web::Query<GetDataQuery>,
 ) -> Result<HttpResponse, GitDataStoreError> {
     let (commit_id, file_path) = path_params.into_inner();
+    access.check(&file_path, Permission::Read)?;
 
+    if let Some(pointer) = &query.pointer {
+        return Ok(match store.read_pointer(&commit_id, &file_path, pointer)? {
+            Some(pointer_entry) => HttpResponse::Ok().json(pointer_entry),
+            None => HttpResponse::NotFound().body(Body::None),
+        });
+    }
     Ok(match store.read(&commit_id, &file_path)? {
-        Some(git_data) => HttpResponse::Ok().json(git_data),
+        Some(git_data) => HttpResponse::Ok().json(access.filter_entry(&file_path, git_data)),
         None => HttpResponse::NotFound().body(Body::None),
     })
 }
@@ -19,11 +96,20 @@ pub async fn get_data(
 #[get("/latest/{file_path:.*}")]
 pub async fn get_latest_data(
     store: web::Data<Arc<GitDataStore>>,
+    access: Access,
     path_params: web::Path<(String,)>,
+    web::Query(query): web::Query<GetDataQuery>,
 ) -> Result<HttpResponse, GitDataStoreError> {
     let file_path = path_params.into_inner().0;
+    access.check(&file_path, Permission::Read)?;
+    if let Some(pointer) = &query.pointer {
+        return Ok(match store.read_pointer_latest(&file_path, pointer)? {
+            Some(pointer_entry) => HttpResponse::Ok().json(pointer_entry),
+            None => HttpResponse::NotFound().body(Body::None),
+        });
+    }
     Ok(match store.read_latest(&file_path)? {
-        Some(git_data) => HttpResponse::Ok().json(git_data),
+        Some(git_data) => HttpResponse::Ok().json(access.filter_entry(&file_path, git_data)),
         None => HttpResponse::NotFound().body(Body::None),
     })
 }
@@ -33,6 +119,7 @@ pub struct PutDataReq {
     data: String,
     overwrite: Option<bool>,
     commit_msg: Option<String>,
+    metadata: Option<Metadata>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -43,16 +130,20 @@ pub struct PutDataResp {
 #[post("/commits/{commit_id}/{file_path:.*}")]
 pub async fn put_data(
     store: web::Data<Arc<GitDataStore>>,
+    access: Access,
+    identity: Option<web::ReqData<Identity>>,
     path_params: web::Path<(String, String)>,
     data: web::Json<PutDataReq>,
 ) -> Result<HttpResponse, GitDataStoreError> {
     let (commit_id, file_path) = path_params.into_inner();
-    let new_commit_id = store.put(
+    access.check(&file_path, Permission::Write)?;
+    let new_commit_id = store.put_with_metadata(
         &commit_id,
         &file_path,
         &data.data,
+        data.metadata.as_ref(),
         data.overwrite.unwrap_or(false),
-        None,
+        author(identity).as_ref(),
         data.commit_msg.as_deref(),
     )?;
 
@@ -64,23 +155,233 @@ pub async fn put_data(
 #[post("/latest/{file_path:.*}")]
 pub async fn put_latest_data(
     store: web::Data<Arc<GitDataStore>>,
+    access: Access,
+    identity: Option<web::ReqData<Identity>>,
     path_params: web::Path<(String,)>,
     data: web::Json<PutDataReq>,
 ) -> Result<HttpResponse, GitDataStoreError> {
     let file_path = path_params.into_inner().0;
-    let new_commit_id =
-        store.put_latest(&file_path, &data.data, None, data.commit_msg.as_deref())?;
+    access.check(&file_path, Permission::Write)?;
+    let new_commit_id = store.put_latest_with_metadata(
+        &file_path,
+        &data.data,
+        data.metadata.as_ref(),
+        author(identity).as_ref(),
+        data.commit_msg.as_deref(),
+    )?;
 
     Ok(HttpResponse::Ok().json(PutDataResp {
         commit_id: new_commit_id,
     }))
 }
 
+#[derive(Serialize)]
+pub struct LabelsResp {
+    paths: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct PatchDataQuery {
+    overwrite: Option<bool>,
+    commit_msg: Option<String>,
+}
+
+/// The body is a JSON Patch or a JSON Merge Patch according to its `Content-Type`.
+#[patch("/commits/{commit_id}/{file_path:.*}")]
+pub async fn patch_data(
+    req: HttpRequest,
+    store: web::Data<Arc<GitDataStore>>,
+    access: Access,
+    identity: Option<web::ReqData<Identity>>,
+    path_params: web::Path<(String, String)>,
+    web::Query(query): web::Query<PatchDataQuery>,
+    body: web::Bytes,
+) -> Result<HttpResponse, GitDataStoreError> {
+    let (commit_id, file_path) = path_params.into_inner();
+    access.check(&file_path, Permission::Write)?;
+    let patch = Patch::parse(request_content_type(&req).unwrap_or_default(), &body)?;
+    let new_commit_id = store.patch(
+        &commit_id,
+        &file_path,
+        &patch,
+        query.overwrite.unwrap_or(false),
+        author(identity).as_ref(),
+        query.commit_msg.as_deref(),
+    )?;
+
+    Ok(HttpResponse::Ok().json(PutDataResp {
+        commit_id: new_commit_id,
+    }))
+}
+
+/// The body is a JSON Patch or a JSON Merge Patch according to its `Content-Type`.
+#[patch("/latest/{file_path:.*}")]
+pub async fn patch_latest_data(
+    req: HttpRequest,
+    store: web::Data<Arc<GitDataStore>>,
+    access: Access,
+    identity: Option<web::ReqData<Identity>>,
+    path_params: web::Path<(String,)>,
+    web::Query(query): web::Query<PatchDataQuery>,
+    body: web::Bytes,
+) -> Result<HttpResponse, GitDataStoreError> {
+    let file_path = path_params.into_inner().0;
+    access.check(&file_path, Permission::Write)?;
+    let patch = Patch::parse(request_content_type(&req).unwrap_or_default(), &body)?;
+    let new_commit_id = store.patch_latest(
+        &file_path,
+        &patch,
+        author(identity).as_ref(),
+        query.commit_msg.as_deref(),
+    )?;
+
+    Ok(HttpResponse::Ok().json(PutDataResp {
+        commit_id: new_commit_id,
+    }))
+}
+
+/// Every query parameter is a label the metadata of the documents must have.
+#[get("/labels/commits/{commit_id}")]
+pub async fn find_by_labels(
+    store: web::Data<Arc<GitDataStore>>,
+    access: Access,
+    path_params: web::Path<(String,)>,
+    web::Query(labels): web::Query<BTreeMap<String, String>>,
+) -> Result<HttpResponse, GitDataStoreError> {
+    let commit_id = path_params.into_inner().0;
+    let mut paths = store.find_by_labels(&commit_id, &labels)?;
+    paths.retain(|path| access.allows(path, Permission::Read));
+
+    Ok(HttpResponse::Ok().json(LabelsResp { paths }))
+}
+
+/// Every query parameter is a label the metadata of the documents must have.
+#[get("/labels/latest")]
+pub async fn find_by_labels_latest(
+    store: web::Data<Arc<GitDataStore>>,
+    access: Access,
+    web::Query(labels): web::Query<BTreeMap<String, String>>,
+) -> Result<HttpResponse, GitDataStoreError> {
+    let mut paths = store.find_by_labels_latest(&labels)?;
+    paths.retain(|path| access.allows(path, Permission::Read));
+
+    Ok(HttpResponse::Ok().json(LabelsResp { paths }))
+}
+
+#[derive(Serialize)]
+pub struct TagsResp {
+    tags: Vec<TagEntry>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CreateTagReq {
+    name: String,
+    /// Commit id, or tag, to tag. Defaults to the head of the primary branch.
+    commit_id: Option<String>,
+    message: Option<String>,
+}
+
+/// Tags are snapshots of every document: listing them requires the permission to read the
+/// whole repository, like reading one does.
+#[get("/tags")]
+pub async fn tags(
+    store: web::Data<Arc<GitDataStore>>,
+    access: Access,
+) -> Result<HttpResponse, GitDataStoreError> {
+    access.check("", Permission::Read)?;
+    Ok(HttpResponse::Ok().json(TagsResp {
+        tags: store.tags()?,
+    }))
+}
+
+#[get("/tags/{tag_name}")]
+pub async fn get_tag(
+    store: web::Data<Arc<GitDataStore>>,
+    access: Access,
+    path_params: web::Path<(String,)>,
+) -> Result<HttpResponse, GitDataStoreError> {
+    let tag_name = path_params.into_inner().0;
+    access.check("", Permission::Read)?;
+    Ok(match store.tag(&tag_name)? {
+        Some(tag) => HttpResponse::Ok().json(tag),
+        None => HttpResponse::NotFound().body(Body::None),
+    })
+}
+
+/// A tag is a snapshot of every document: creating one requires the permission to write
+/// the whole repository.
+#[post("/tags")]
+pub async fn create_tag(
+    store: web::Data<Arc<GitDataStore>>,
+    access: Access,
+    identity: Option<web::ReqData<Identity>>,
+    data: web::Json<CreateTagReq>,
+) -> Result<HttpResponse, GitDataStoreError> {
+    access.check("", Permission::Write)?;
+    let message = data
+        .message
+        .clone()
+        .unwrap_or_else(|| format!("Tagged {}", data.name));
+    let tag = match &data.commit_id {
+        Some(commit_id) => {
+            store.create_tag(&data.name, commit_id, &message, author(identity).as_ref())?
+        }
+        None => store.create_tag_latest(&data.name, &message, author(identity).as_ref())?,
+    };
+
+    Ok(HttpResponse::Ok().json(tag))
+}
+
+/// Deleting a tag requires the permission to delete in the whole repository.
+#[delete("/tags/{tag_name}")]
+pub async fn delete_tag(
+    store: web::Data<Arc<GitDataStore>>,
+    access: Access,
+    path_params: web::Path<(String,)>,
+) -> Result<HttpResponse, GitDataStoreError> {
+    let tag_name = path_params.into_inner().0;
+    access.check("", Permission::Delete)?;
+    Ok(HttpResponse::Ok().json(store.delete_tag(&tag_name)?))
+}
+
+#[derive(Serialize)]
+pub struct SchemasResp {
+    schemas: Vec<SchemaEntry>,
+}
+
+#[get("/schemas/commits/{commit_id}/{file_path:.*}")]
+pub async fn schemas_for(
+    store: web::Data<Arc<GitDataStore>>,
+    access: Access,
+    path_params: web::Path<(String, String)>,
+) -> Result<HttpResponse, GitDataStoreError> {
+    let (commit_id, file_path) = path_params.into_inner();
+    access.check(&file_path, Permission::Read)?;
+    let schemas = store.schemas_for(&commit_id, &file_path)?;
+
+    Ok(HttpResponse::Ok().json(SchemasResp { schemas }))
+}
+
+#[get("/schemas/latest/{file_path:.*}")]
+pub async fn schemas_for_latest(
+    store: web::Data<Arc<GitDataStore>>,
+    access: Access,
+    path_params: web::Path<(String,)>,
+) -> Result<HttpResponse, GitDataStoreError> {
+    let file_path = path_params.into_inner().0;
+    access.check(&file_path, Permission::Read)?;
+    let schemas = store.schemas_for_latest(&file_path)?;
+
+    Ok(HttpResponse::Ok().json(SchemasResp { schemas }))
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct HistoryReqQuery {
     first: usize,
     after: usize,
     path: Option<String>,
+    /// Reports the signature status of every entry.
+    verify: Option<bool>,
 }
 
 #[derive(Serialize)]
@@ -91,27 +392,233 @@ pub struct HistoryResp {
 #[get("/history")]
 pub async fn history(
     store: web::Data<Arc<GitDataStore>>,
+    access: Access,
     web::Query(history_req): web::Query<HistoryReqQuery>,
 ) -> Result<HttpResponse, GitDataStoreError> {
     let history = store.history()?;
     let entries: Result<Vec<_>, GitDataStoreError> = if let Some(path) = history_req.path {
+        access.check(&path, Permission::Read)?;
         history
             .iter_path(&path)?
             .skip(history_req.after)
             .take(history_req.first + 1)
             .collect()
     } else {
+        // commits which only changed documents that cannot be read are left out
         history
             .iter()?
+            .filter_map(|entry| {
+                let readable = match &entry {
+                    Ok(entry) if access.is_restricted() => {
+                        match history.commit_changes(&entry.commit_id) {
+                            Ok(paths) => paths
+                                .iter()
+                                .any(|change| access.allows(&change.path, Permission::Read)),
+                            Err(err) => return Some(Err(err)),
+                        }
+                    }
+                    _ => true,
+                };
+                readable.then_some(entry)
+            })
             .skip(history_req.after)
             .take(history_req.first + 1)
             .collect()
     };
-    let entries = entries?;
+    let mut entries = entries?;
+    if history_req.verify.unwrap_or(false) {
+        for entry in &mut entries {
+            entry.signature = Some(store.verify(&entry.commit_id)?);
+        }
+    }
 
     Ok(HttpResponse::Ok().json(HistoryResp { entries: entries }))
 }
 
+#[derive(Deserialize)]
+pub struct ChangesQuery {
+    since: Option<String>,
+    prefix: Option<String>,
+}
+
+/// Server-sent events feed with an event per commit on the primary branch. Starts after
+/// `since` or the `Last-Event-ID` header when given, after the current head otherwise.
+/// A `reset` event ends the feed when the history was rewritten, after which clients
+/// start over from the current head.
+#[get("/changes")]
+pub async fn changes(
+    req: HttpRequest,
+    store: web::Data<Arc<GitDataStore>>,
+    access: Access,
+    web::Query(query): web::Query<ChangesQuery>,
+) -> Result<HttpResponse, GitDataStoreError> {
+    let since = match query.since.or_else(|| {
+        req.headers()
+            .get(LAST_EVENT_ID)
+            .and_then(|header| header.to_str().ok())
+            .map(|header| header.to_string())
+    }) {
+        Some(since) => since,
+        None => store.head()?.commit_id,
+    };
+    let prefix = query.prefix;
+    let client = ChangesClient::acquire()?;
+    // unknown commits, and commits no longer in the history, are reported before the stream starts
+    let mut changes = store.changes_since(&since, prefix.as_deref())?;
+
+    let store = store.get_ref().clone();
+    let (mut sender, receiver) = mpsc::channel(STREAM_BUFFER_CHUNKS);
+    thread::spawn(move || {
+        let _client = client;
+        let mut idle_polls = 0;
+        loop {
+            let mut events: Vec<String> = changes
+                .commits
+                .into_iter()
+                .filter_map(|commit| access.filter_commit(commit))
+                .map(|commit| commit_event(&commit))
+                .collect();
+            idle_polls = if events.is_empty() { idle_polls + 1 } else { 0 };
+            if idle_polls >= CHANGES_KEEP_ALIVE_POLLS {
+                events.push(":\n\n".to_string());
+                idle_polls = 0;
+            }
+            for event in events {
+                if block_on(sender.send(Ok(web::Bytes::from(event)))).is_err() {
+                    // the client went away
+                    return;
+                }
+            }
+
+            thread::sleep(POLL_INTERVAL);
+            changes = match store.changes_since(&changes.head, prefix.as_deref()) {
+                Ok(changes) => changes,
+                Err(err) => {
+                    // clients start over from the current head when the history was rewritten
+                    let kind = match err {
+                        GitDataStoreError::NotAncestor { .. } => "reset",
+                        _ => "error",
+                    };
+                    let event = format!("event: {}\ndata: {}\n\n", kind, err);
+                    let _ =
+                        block_on(sender.send(Ok::<_, GitDataStoreError>(web::Bytes::from(event))));
+                    return;
+                }
+            };
+        }
+    });
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .header(CACHE_CONTROL, "no-cache")
+        .streaming(receiver))
+}
+
+/// Slot of a change feed in [`CHANGES_CLIENTS`], released when the feed ends.
+struct ChangesClient;
+
+impl ChangesClient {
+    fn acquire() -> Result<Self, GitDataStoreError> {
+        CHANGES_CLIENTS
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |clients| {
+                (clients < MAX_CHANGES_CLIENTS).then_some(clients + 1)
+            })
+            .map(|_| ChangesClient)
+            .map_err(|_| GitDataStoreError::TooManyClients(MAX_CHANGES_CLIENTS))
+    }
+}
+
+impl Drop for ChangesClient {
+    fn drop(&mut self) {
+        CHANGES_CLIENTS.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+#[derive(Deserialize)]
+pub struct WatchQuery {
+    since: String,
+    timeout: Option<u64>,
+}
+
+/// Long poll answering as soon as the document differs from its version at `since`, with
+/// 304 if it did not change within `timeout` seconds.
+#[get("/watch/{file_path:.*}")]
+pub async fn watch(
+    store: web::Data<Arc<GitDataStore>>,
+    access: Access,
+    path_params: web::Path<(String,)>,
+    web::Query(query): web::Query<WatchQuery>,
+) -> Result<HttpResponse, GitDataStoreError> {
+    let file_path = path_params.into_inner().0;
+    access.check(&file_path, Permission::Read)?;
+    let timeout = Duration::from_secs(
+        query
+            .timeout
+            .unwrap_or(WATCH_DEFAULT_TIMEOUT_SECS)
+            .min(WATCH_MAX_TIMEOUT_SECS),
+    );
+    let deadline = Instant::now() + timeout;
+
+    loop {
+        let changed = {
+            let (store, since, path) = (store.clone(), query.since.clone(), file_path.clone());
+            web::block(move || store.changed_since(&since, &path)).await?
+        };
+        if let Some(mut change) = changed {
+            change.entry = change
+                .entry
+                .map(|entry| access.filter_entry(&file_path, entry));
+            return Ok(HttpResponse::Ok().json(change));
+        }
+        let now = Instant::now();
+        if now >= deadline {
+            return Ok(HttpResponse::NotModified().body(Body::None));
+        }
+        actix_rt::time::delay_for(POLL_INTERVAL.min(deadline - now)).await;
+    }
+}
+
+/// The commit id is the event id, so that reconnecting clients resume after the last
+/// commit they received.
+fn commit_event(commit: &CommitChange) -> String {
+    format!(
+        "id: {}\nevent: commit\ndata: {}\n\n",
+        commit.commit_id,
+        serde_json::to_string(commit).expect("commit changes are serializable")
+    )
+}
+
+#[derive(Serialize)]
+pub struct StatusResp {
+    primary_branch: String,
+    head: HistoryEntry,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    replication: Option<ReplicationStatus>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    maintenance: Option<MaintenanceStatus>,
+}
+
+/// The replication status is only reported when the server runs as a replica, the
+/// maintenance status when maintenance is scheduled. The message of the head commit names
+/// the documents it changed: the status requires the permission to read the whole
+/// repository.
+#[get("/status")]
+pub async fn status(
+    store: web::Data<Arc<GitDataStore>>,
+    access: Access,
+    replica: Option<web::Data<Arc<Replica>>>,
+    maintenance: Option<web::Data<Arc<Maintenance>>>,
+) -> Result<HttpResponse, GitDataStoreError> {
+    access.check("", Permission::Read)?;
+    let replication = replica.map(|replica| replica.status(&store)).transpose()?;
+    Ok(HttpResponse::Ok().json(StatusResp {
+        primary_branch: store.primary_branch().to_string(),
+        head: store.head()?,
+        replication,
+        maintenance: maintenance.map(|maintenance| maintenance.status()),
+    }))
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct DeleteReq {
     overwrite: Option<bool>,
@@ -121,15 +628,18 @@ pub struct DeleteReq {
 #[delete("/commits/{commit_id}/{file_path:.*}")]
 pub async fn delete(
     store: web::Data<Arc<GitDataStore>>,
+    access: Access,
+    identity: Option<web::ReqData<Identity>>,
     path_params: web::Path<(String, String)>,
     data: web::Json<PutDataReq>,
 ) -> Result<HttpResponse, GitDataStoreError> {
     let (commit_id, file_path) = path_params.into_inner();
+    access.check(&file_path, Permission::Delete)?;
     let new_commit_id = store.delete(
         &commit_id,
         &file_path,
         data.overwrite.unwrap_or(false),
-        None,
+        author(identity).as_ref(),
         data.commit_msg.as_deref(),
     )?;
 
@@ -141,13 +651,396 @@ pub async fn delete(
 #[delete("/latest/{file_path:.*}")]
 pub async fn delete_latest(
     store: web::Data<Arc<GitDataStore>>,
+    access: Access,
+    identity: Option<web::ReqData<Identity>>,
     path_params: web::Path<(String,)>,
     data: web::Json<DeleteReq>,
 ) -> Result<HttpResponse, GitDataStoreError> {
     let file_path = path_params.into_inner().0;
-    let new_commit_id = store.delete_latest(&file_path, None, data.commit_msg.as_deref())?;
+    access.check(&file_path, Permission::Delete)?;
+    let new_commit_id = store.delete_latest(
+        &file_path,
+        author(identity).as_ref(),
+        data.commit_msg.as_deref(),
+    )?;
 
     Ok(HttpResponse::Ok().json(PutDataResp {
         commit_id: new_commit_id,
     }))
 }
+
+#[derive(Serialize, Deserialize)]
+pub struct CommitMsgReq {
+    commit_msg: Option<String>,
+}
+
+/// Reverting deletes the documents the commit added and writes the others.
+#[post("/revert/commits/{commit_id}")]
+pub async fn revert(
+    store: web::Data<Arc<GitDataStore>>,
+    access: Access,
+    identity: Option<web::ReqData<Identity>>,
+    path_params: web::Path<(String,)>,
+    data: web::Json<CommitMsgReq>,
+) -> Result<HttpResponse, GitDataStoreError> {
+    let commit_id = path_params.into_inner().0;
+    for change in store.commit_changes(&commit_id)? {
+        let permission = match change.status {
+            ChangeStatus::Added => Permission::Delete,
+            ChangeStatus::Modified | ChangeStatus::Deleted => Permission::Write,
+        };
+        access.check(&change.path, permission)?;
+    }
+    let new_commit_id = store.revert(
+        &commit_id,
+        author(identity).as_ref(),
+        data.commit_msg.as_deref(),
+    )?;
+
+    Ok(HttpResponse::Ok().json(PutDataResp {
+        commit_id: new_commit_id,
+    }))
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CherryPickReq {
+    /// Local branch the commit is applied to. Defaults to the primary branch.
+    onto_branch: Option<String>,
+}
+
+/// Cherry-picking deletes the documents the commit deleted and writes the others.
+#[post("/cherry-pick/commits/{commit_id}")]
+pub async fn cherry_pick(
+    store: web::Data<Arc<GitDataStore>>,
+    access: Access,
+    identity: Option<web::ReqData<Identity>>,
+    path_params: web::Path<(String,)>,
+    data: web::Json<CherryPickReq>,
+) -> Result<HttpResponse, GitDataStoreError> {
+    let commit_id = path_params.into_inner().0;
+    for change in store.commit_changes(&commit_id)? {
+        let permission = match change.status {
+            ChangeStatus::Deleted => Permission::Delete,
+            ChangeStatus::Added | ChangeStatus::Modified => Permission::Write,
+        };
+        access.check(&change.path, permission)?;
+    }
+    let onto_branch = data
+        .onto_branch
+        .as_deref()
+        .unwrap_or_else(|| store.primary_branch());
+    let new_commit_id = store.cherry_pick(&commit_id, onto_branch, author(identity).as_ref())?;
+
+    Ok(HttpResponse::Ok().json(PutDataResp {
+        commit_id: new_commit_id,
+    }))
+}
+
+/// Restoring deletes the documents which did not exist at commit id and writes the others.
+#[post("/restore/commits/{commit_id}/{file_path:.*}")]
+pub async fn restore(
+    store: web::Data<Arc<GitDataStore>>,
+    access: Access,
+    identity: Option<web::ReqData<Identity>>,
+    path_params: web::Path<(String, String)>,
+    data: web::Json<CommitMsgReq>,
+) -> Result<HttpResponse, GitDataStoreError> {
+    let (commit_id, file_path) = path_params.into_inner();
+    access.check(&file_path, Permission::Write)?;
+    for change in store.restore_changes(&file_path, &commit_id)? {
+        let permission = match change.status {
+            ChangeStatus::Deleted => Permission::Delete,
+            ChangeStatus::Added | ChangeStatus::Modified => Permission::Write,
+        };
+        access.check(&change.path, permission)?;
+    }
+    let new_commit_id = store.restore(
+        &file_path,
+        &commit_id,
+        author(identity).as_ref(),
+        data.commit_msg.as_deref(),
+    )?;
+
+    Ok(HttpResponse::Ok().json(PutDataResp {
+        commit_id: new_commit_id,
+    }))
+}
+
+#[get("/raw/commits/{commit_id}/{file_path:.*}")]
+pub async fn get_raw_data(
+    req: HttpRequest,
+    store: web::Data<Arc<GitDataStore>>,
+    access: Access,
+    path_params: web::Path<(String, String)>,
+) -> Result<HttpResponse, GitDataStoreError> {
+    let (commit_id, file_path) = path_params.into_inner();
+    access.check(&file_path, Permission::Read)?;
+
+    let (reading, path) = (store.get_ref().clone(), file_path.clone());
+    Ok(
+        match web::block(move || reading.read_blob(&commit_id, &path)).await? {
+            Some(blob_reader) => stream_blob(&req, &file_path, blob_reader),
+            None => HttpResponse::NotFound().body(Body::None),
+        },
+    )
+}
+
+#[get("/raw/latest/{file_path:.*}")]
+pub async fn get_latest_raw_data(
+    req: HttpRequest,
+    store: web::Data<Arc<GitDataStore>>,
+    access: Access,
+    path_params: web::Path<(String,)>,
+) -> Result<HttpResponse, GitDataStoreError> {
+    let file_path = path_params.into_inner().0;
+    access.check(&file_path, Permission::Read)?;
+
+    let (reading, path) = (store.get_ref().clone(), file_path.clone());
+    Ok(
+        match web::block(move || reading.read_blob_latest(&path)).await? {
+            Some(blob_reader) => stream_blob(&req, &file_path, blob_reader),
+            None => HttpResponse::NotFound().body(Body::None),
+        },
+    )
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct PutRawDataQuery {
+    overwrite: Option<bool>,
+    commit_msg: Option<String>,
+}
+
+/// The `Content-Type` of the request is recorded in the metadata of the file
+/// and used when the file is served from the raw endpoints.
+#[post("/raw/commits/{commit_id}/{file_path:.*}")]
+pub async fn put_raw_data(
+    req: HttpRequest,
+    store: web::Data<Arc<GitDataStore>>,
+    access: Access,
+    identity: Option<web::ReqData<Identity>>,
+    path_params: web::Path<(String, String)>,
+    web::Query(query): web::Query<PutRawDataQuery>,
+    payload: web::Payload,
+) -> Result<HttpResponse, GitDataStoreError> {
+    let (commit_id, file_path) = path_params.into_inner();
+    access.check(&file_path, Permission::Write)?;
+    let blob_id = write_payload(&store, &file_path, payload).await?;
+    let content_type = request_content_type(&req).map(str::to_string);
+    let author = author(identity);
+    let store = store.get_ref().clone();
+    let new_commit_id = web::block(move || {
+        store.put_raw(
+            &commit_id,
+            &file_path,
+            &blob_id,
+            content_type.as_deref(),
+            query.overwrite.unwrap_or(false),
+            author.as_ref(),
+            query.commit_msg.as_deref(),
+        )
+    })
+    .await?;
+
+    Ok(HttpResponse::Ok().json(PutDataResp {
+        commit_id: new_commit_id,
+    }))
+}
+
+/// The `Content-Type` of the request is recorded in the metadata of the file
+/// and used when the file is served from the raw endpoints.
+#[post("/raw/latest/{file_path:.*}")]
+pub async fn put_latest_raw_data(
+    req: HttpRequest,
+    store: web::Data<Arc<GitDataStore>>,
+    access: Access,
+    identity: Option<web::ReqData<Identity>>,
+    path_params: web::Path<(String,)>,
+    web::Query(query): web::Query<PutRawDataQuery>,
+    payload: web::Payload,
+) -> Result<HttpResponse, GitDataStoreError> {
+    let file_path = path_params.into_inner().0;
+    access.check(&file_path, Permission::Write)?;
+    let blob_id = write_payload(&store, &file_path, payload).await?;
+    let content_type = request_content_type(&req).map(str::to_string);
+    let author = author(identity);
+    let store = store.get_ref().clone();
+    let new_commit_id = web::block(move || {
+        store.put_raw_latest(
+            &file_path,
+            &blob_id,
+            content_type.as_deref(),
+            author.as_ref(),
+            query.commit_msg.as_deref(),
+        )
+    })
+    .await?;
+
+    Ok(HttpResponse::Ok().json(PutDataResp {
+        commit_id: new_commit_id,
+    }))
+}
+
+/// Commits are authored by the authenticated identity of the request, if any.
+fn author(identity: Option<web::ReqData<Identity>>) -> Option<Signature> {
+    identity.map(|identity| identity.signature())
+}
+
+fn request_content_type(req: &HttpRequest) -> Option<&str> {
+    req.headers()
+        .get(CONTENT_TYPE)
+        .and_then(|header| header.to_str().ok())
+}
+
+/// Writes the request body into the repository as it is received and returns the blob id.
+/// The body is written on the thread pool since git writes block, and bodies larger than the
+/// threshold of the large object store are streamed into the store. The body of a document
+/// at an encrypted path is encrypted in memory first, so that the plain content never
+/// reaches the object database.
+async fn write_payload(
+    store: &web::Data<Arc<GitDataStore>>,
+    path: &str,
+    mut payload: web::Payload,
+) -> Result<String, GitDataStoreError> {
+    let (mut sender, receiver) = mpsc::channel(STREAM_BUFFER_CHUNKS);
+    let (store, path) = (store.get_ref().clone(), path.to_string());
+    let written = web::block(move || store.write_blob_for(&path, PayloadReader::new(receiver)));
+    while let Some(chunk) = payload.next().await {
+        match chunk {
+            Ok(chunk) => {
+                if sender.send(chunk).await.is_err() {
+                    // the write failed, its error is returned below
+                    break;
+                }
+            }
+            Err(err) => {
+                drop(sender);
+                let _ = written.await;
+                return Err(GitDataStoreError::RequestBody(err.to_string()));
+            }
+        }
+    }
+    drop(sender);
+    Ok(written.await?)
+}
+
+/// Reads the chunks of a request body sent by [`write_payload`] on the thread writing them.
+struct PayloadReader {
+    receiver: mpsc::Receiver<web::Bytes>,
+    chunk: web::Bytes,
+}
+
+impl PayloadReader {
+    fn new(receiver: mpsc::Receiver<web::Bytes>) -> Self {
+        PayloadReader {
+            receiver,
+            chunk: web::Bytes::new(),
+        }
+    }
+}
+
+impl Read for PayloadReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.chunk.is_empty() {
+            match block_on(self.receiver.next()) {
+                Some(chunk) => self.chunk = chunk,
+                None => return Ok(0),
+            }
+        }
+        let len = buf.len().min(self.chunk.len());
+        buf[..len].copy_from_slice(&self.chunk.split_to(len));
+        Ok(len)
+    }
+}
+
+/// Streams the blob as a chunked response, honouring a single `Range` header.
+/// The response has the content type recorded when the file was written, if any, and is sent
+/// as an attachment named after the file.
+/// The blob is read on its own thread so that the response can be sent while it is read.
+fn stream_blob(req: &HttpRequest, path: &str, blob_reader: BlobReader) -> HttpResponse {
+    let size = blob_reader.size();
+    let content_type = blob_reader
+        .content_type()
+        .unwrap_or(DEFAULT_CONTENT_TYPE)
+        .to_string();
+    let range = req
+        .headers()
+        .get(RANGE)
+        .and_then(|header| header.to_str().ok())
+        .map(|header| parse_range(header, size));
+
+    let mut response = match range {
+        Some(Some(Err(()))) => {
+            return HttpResponse::RangeNotSatisfiable()
+                .header(CONTENT_RANGE, format!("bytes */{}", size))
+                .body(Body::None)
+        }
+        Some(Some(Ok(ref range))) => {
+            let mut response = HttpResponse::PartialContent();
+            response.header(
+                CONTENT_RANGE,
+                format!("bytes {}-{}/{}", range.start, range.end - 1, size),
+            );
+            response
+        }
+        _ => HttpResponse::Ok(),
+    };
+    let range = match range {
+        Some(Some(Ok(range))) => range,
+        _ => 0..size,
+    };
+
+    let (mut sender, receiver) = mpsc::channel(STREAM_BUFFER_CHUNKS);
+    thread::spawn(move || {
+        // stops when the client went away
+        let streamed = blob_reader.for_each_chunk(range, |chunk| {
+            block_on(sender.send(Ok(web::Bytes::from(chunk)))).is_ok()
+        });
+        if let Err(err) = streamed {
+            let _ = block_on(sender.send(Err(err)));
+        }
+    });
+
+    // the content type is chosen by whoever wrote the file, so browsers must neither guess
+    // it nor render the file in the origin of the API
+    let filename = path.rsplit('/').next().unwrap_or(path).to_string();
+    response
+        .header(ACCEPT_RANGES, "bytes")
+        .header(X_CONTENT_TYPE_OPTIONS, "nosniff")
+        .set(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename(filename)],
+        })
+        .content_type(content_type)
+        .streaming(receiver)
+}
+
+/// Parses a `Range` header into the byte range to send.
+/// Returns `None` if the header should be ignored, which is the case for units other than
+/// bytes and for multiple ranges, and `Some(Err(()))` if the range cannot be satisfied.
+fn parse_range(header: &str, size: usize) -> Option<Result<Range<usize>, ()>> {
+    let spec = header.trim().strip_prefix("bytes=")?;
+    if spec.contains(',') {
+        return None;
+    }
+    let (start, end) = spec.split_at(spec.find('-')?);
+    let end = &end[1..];
+
+    let range = match (start.trim(), end.trim()) {
+        ("", "") => return None,
+        ("", suffix) => {
+            let suffix: usize = suffix.parse().ok()?;
+            size.saturating_sub(suffix)..size
+        }
+        (start, "") => start.parse().ok()?..size,
+        (start, end) => {
+            let end: usize = end.parse().ok()?;
+            start.parse().ok()?..end.saturating_add(1).min(size)
+        }
+    };
+
+    if range.start >= range.end {
+        Some(Err(()))
+    } else {
+        Some(Ok(range))
+    }
+}
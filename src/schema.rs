@@ -0,0 +1,226 @@
+use crate::{
+    blob::BlobCodec,
+    error::GitDataStoreError,
+    metadata::is_reserved_path,
+    path_glob::PathGlobs,
+    validation::{compile_schema, validate_against_schema, Document, Validator},
+};
+use git2::{ObjectType, Repository, Tree, TreeWalkMode, TreeWalkResult};
+use serde::Serialize;
+use serde_json::Value;
+use std::path::Path;
+
+/// Tree holding the JSON Schemas of the registry. Schemas are versioned documents like any
+/// other, so a document is always validated against the schemas of the same commit.
+pub const SCHEMAS_DIR: &str = ".schemas";
+
+/// Keyword of a schema listing the globs of the documents it applies to, e.g.
+/// `{"x-paths": ["users/*.json"], "type": "object"}`.
+pub const SCHEMA_PATHS_KEYWORD: &str = "x-paths";
+
+#[derive(Clone, Debug, Serialize, PartialEq, Eq)]
+pub struct SchemaEntry {
+    /// Path of the schema in the repository.
+    pub path: String,
+    /// Globs of the documents the schema applies to.
+    pub paths: Vec<String>,
+    /// Why the schema cannot be used, `None` if it is valid.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+struct RegisteredSchema {
+    entry: SchemaEntry,
+    globs: PathGlobs,
+    schema: Value,
+}
+
+/// Path of a schema of the registry with the schema, or why it is invalid.
+type ParsedSchema = (String, Result<RegisteredSchema, String>);
+
+pub fn is_schema_path(path: &str) -> bool {
+    Path::new(path.trim_start_matches('/')).starts_with(SCHEMAS_DIR)
+}
+
+fn parse_schema(path: &str, content: &[u8]) -> Result<RegisteredSchema, String> {
+    let schema: Value =
+        serde_json::from_slice(content).map_err(|e| format!("invalid JSON: {}", e))?;
+    let paths: Vec<String> = schema
+        .get(SCHEMA_PATHS_KEYWORD)
+        .and_then(|paths| serde_json::from_value(paths.clone()).ok())
+        .ok_or_else(|| format!("missing '{}' array of globs", SCHEMA_PATHS_KEYWORD))?;
+    let globs = PathGlobs::new(&paths).map_err(|e| e.to_string())?;
+    Ok(RegisteredSchema {
+        entry: SchemaEntry {
+            path: path.to_string(),
+            paths,
+            error: None,
+        },
+        globs,
+        schema,
+    })
+}
+
+/// Paths of the files of `tree` for which `include` returns true, skipping whole directories
+/// for which `skip_dir` returns true.
+fn file_paths<F, S>(tree: &Tree, skip_dir: S, include: F) -> Result<Vec<String>, GitDataStoreError>
+where
+    F: Fn(&str) -> bool,
+    S: Fn(&str) -> bool,
+{
+    let mut paths = Vec::new();
+    tree.walk(TreeWalkMode::PreOrder, |root, entry| {
+        let path = format!("{}{}", root, entry.name().unwrap_or_default());
+        match entry.kind() {
+            Some(ObjectType::Tree) if skip_dir(&path) => TreeWalkResult::Skip,
+            Some(ObjectType::Blob) if include(&path) => {
+                paths.push(path);
+                TreeWalkResult::Ok
+            }
+            _ => TreeWalkResult::Ok,
+        }
+    })?;
+    Ok(paths)
+}
+
+/// Schemas of the registry in `tree`, read with `read`.
+fn parsed_schemas<R>(tree: &Tree, read: R) -> Result<Vec<ParsedSchema>, GitDataStoreError>
+where
+    R: Fn(&str) -> Result<Option<Vec<u8>>, GitDataStoreError>,
+{
+    match tree.get_name(SCHEMAS_DIR) {
+        Some(entry) if entry.kind() == Some(ObjectType::Tree) => {}
+        _ => return Ok(Vec::new()),
+    }
+
+    let mut schemas = Vec::new();
+    for path in file_paths(tree, |dir| !is_schema_path(dir), is_schema_path)? {
+        let content = match read(&path)? {
+            Some(content) => content,
+            None => continue,
+        };
+        let schema = parse_schema(&path, &content);
+        schemas.push((path, schema));
+    }
+    Ok(schemas)
+}
+
+/// Schemas of the registry in `tree`, read with `read`. Fails on the first invalid schema.
+fn registered_schemas<R>(tree: &Tree, read: R) -> Result<Vec<RegisteredSchema>, GitDataStoreError>
+where
+    R: Fn(&str) -> Result<Option<Vec<u8>>, GitDataStoreError>,
+{
+    parsed_schemas(tree, read)?
+        .into_iter()
+        .map(|(path, schema)| {
+            schema.map_err(|error| GitDataStoreError::InvalidConfig { path, error })
+        })
+        .collect()
+}
+
+/// Schemas of the registry in `tree` which apply to the document at `path`, decoded with
+/// `codec`. Invalid schemas are listed with their error since it is unknown which documents
+/// they apply to.
+pub(crate) fn schemas_for(
+    repo: &Repository,
+    tree: &Tree,
+    path: &str,
+    codec: BlobCodec<'_>,
+) -> Result<Vec<SchemaEntry>, GitDataStoreError> {
+    let read = |schema_path: &str| -> Result<Option<Vec<u8>>, GitDataStoreError> {
+        let entry = tree.get_path(Path::new(schema_path))?;
+        let blob = repo.find_blob(entry.id())?;
+        Ok(Some(
+            codec.decode(schema_path, blob.content())?.into_owned(),
+        ))
+    };
+    Ok(parsed_schemas(tree, read)?
+        .into_iter()
+        .filter_map(|(schema_path, schema)| match schema {
+            Ok(schema) if schema.globs.is_match(path) => Some(schema.entry),
+            Ok(_) => None,
+            Err(error) => Some(SchemaEntry {
+                path: schema_path,
+                paths: Vec::new(),
+                error: Some(error),
+            }),
+        })
+        .collect())
+}
+
+/// Validates documents against the schemas of the registry stored under [`SCHEMAS_DIR`] at
+/// the commit being made:
+///
+/// - a written document must be valid against every schema whose globs match its path,
+/// - a written schema must be valid and every existing document it applies to must be valid
+///   against it.
+pub struct SchemaRegistryValidator;
+
+impl SchemaRegistryValidator {
+    fn validate_schema(&self, document: &Document<'_>, content: &[u8]) -> Result<(), String> {
+        let schema = parse_schema(document.path, content)?;
+        let compiled = compile_schema(document.path, &schema.schema).map_err(|e| e.to_string())?;
+
+        let covered = file_paths(
+            document.tree,
+            |dir| is_reserved_path(dir) || is_schema_path(dir),
+            |path| schema.globs.is_match(path),
+        )
+        .map_err(|e| e.to_string())?;
+        let mut errors = Vec::new();
+        for path in covered {
+            let content = match document.read(&path).map_err(|e| e.to_string())? {
+                Some(content) => content,
+                None => continue,
+            };
+            if let Err(error) = validate_against_schema(&compiled, &content) {
+                errors.push(format!("{}: {}", path, error));
+            }
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(format!(
+                "existing documents are not valid against the schema: {}",
+                errors.join(", ")
+            ))
+        }
+    }
+
+    fn validate_document(&self, document: &Document<'_>, content: &[u8]) -> Result<(), String> {
+        let schemas = registered_schemas(document.tree, |path| document.read(path))
+            .map_err(|e| e.to_string())?;
+        let mut errors = Vec::new();
+        for schema in schemas
+            .iter()
+            .filter(|schema| schema.globs.is_match(document.path))
+        {
+            let compiled =
+                compile_schema(&schema.entry.path, &schema.schema).map_err(|e| e.to_string())?;
+            if let Err(error) = validate_against_schema(&compiled, content) {
+                errors.push(format!("{}: {}", schema.entry.path, error));
+            }
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors.join(", "))
+        }
+    }
+}
+
+impl Validator for SchemaRegistryValidator {
+    fn name(&self) -> &str {
+        "schema-registry"
+    }
+
+    fn validate(&self, document: &Document<'_>) -> Result<(), String> {
+        match document.content {
+            Some(content) if is_schema_path(document.path) => {
+                self.validate_schema(document, content)
+            }
+            Some(content) => self.validate_document(document, content),
+            None => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,289 @@
+use crate::error::GitDataStoreError;
+use git2::{ErrorCode, Oid, Repository};
+use serde::Serialize;
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    str::FromStr,
+};
+
+/// Program signing and verifying SSH signatures, as used by git's `gpg.format=ssh`.
+const SSH_KEYGEN_PROGRAM: &str = "ssh-keygen";
+
+/// Program signing and verifying OpenPGP signatures, as used by git by default.
+const GPG_PROGRAM: &str = "gpg";
+
+/// Namespace of SSH signatures made for git commits.
+const SSH_NAMESPACE: &str = "git";
+
+const SSH_SIGNATURE_HEADER: &str = "-----BEGIN SSH SIGNATURE-----";
+const PGP_SIGNATURE_HEADER: &str = "-----BEGIN PGP SIGNATURE-----";
+
+#[derive(Clone, Copy, Debug, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SignatureFormat {
+    Ssh,
+    OpenPgp,
+}
+
+impl FromStr for SignatureFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ssh" => Ok(SignatureFormat::Ssh),
+            "openpgp" => Ok(SignatureFormat::OpenPgp),
+            _ => Err(format!(
+                "unknown signature format {}, expected ssh or openpgp",
+                s
+            )),
+        }
+    }
+}
+
+/// Signs the commits made by the store, the same way `git commit -S` does.
+#[derive(Clone, Debug)]
+pub struct CommitSigner {
+    format: SignatureFormat,
+    /// Private key file, or public key file of a key held by the ssh-agent, for SSH. Key id
+    /// or user id of a key of the local keyring for OpenPGP.
+    key: String,
+    /// Home directory of the OpenPGP keyring, `GNUPGHOME` or `~/.gnupg` when not set.
+    gnupg_home: Option<PathBuf>,
+}
+
+impl CommitSigner {
+    pub fn new(format: SignatureFormat, key: &str) -> Self {
+        CommitSigner {
+            format,
+            key: key.to_string(),
+            gnupg_home: None,
+        }
+    }
+
+    pub fn with_gnupg_home<P: AsRef<Path>>(mut self, gnupg_home: P) -> Self {
+        self.gnupg_home = Some(gnupg_home.as_ref().to_path_buf());
+        self
+    }
+
+    pub fn format(&self) -> SignatureFormat {
+        self.format
+    }
+
+    /// Armored detached signature of the commit `buffer`, as created by `commit_create_buffer`.
+    pub fn sign(&self, buffer: &[u8]) -> Result<String, GitDataStoreError> {
+        let mut command = match self.format {
+            SignatureFormat::Ssh => {
+                let mut command = Command::new(SSH_KEYGEN_PROGRAM);
+                command.args(["-Y", "sign", "-n", SSH_NAMESPACE, "-f", &self.key]);
+                command
+            }
+            SignatureFormat::OpenPgp => {
+                let mut command = gpg(self.gnupg_home.as_deref());
+                command.args(["--batch", "-bsau", &self.key]);
+                command
+            }
+        };
+        let signature = run(&mut command, buffer).map_err(GitDataStoreError::Signing)?;
+        String::from_utf8(signature).map_err(|e| GitDataStoreError::Signing(e.to_string()))
+    }
+}
+
+/// Whether a commit is signed and by whom.
+#[derive(Clone, Debug, Serialize, PartialEq, Eq)]
+#[serde(tag = "status", rename_all = "lowercase")]
+pub enum SignatureStatus {
+    Unsigned,
+    /// The signature is valid and made by a trusted key of `signer`: the principal of the
+    /// allowed signers file for SSH, the user id of the key for OpenPGP.
+    Good {
+        format: SignatureFormat,
+        signer: String,
+    },
+    /// The signature is invalid, or made by an unknown or untrusted key.
+    Bad {
+        format: Option<SignatureFormat>,
+        error: String,
+    },
+}
+
+/// Verifies commit signatures, the same way `git verify-commit` does.
+#[derive(Clone, Debug, Default)]
+pub struct CommitVerifier {
+    /// File of trusted SSH keys in the format of `ssh-keygen -Y verify`. SSH signatures
+    /// are reported as bad without it.
+    pub allowed_signers: Option<PathBuf>,
+    /// Home directory of the OpenPGP keyring, `GNUPGHOME` or `~/.gnupg` when not set.
+    pub gnupg_home: Option<PathBuf>,
+}
+
+impl CommitVerifier {
+    pub fn new(allowed_signers: Option<PathBuf>) -> Self {
+        CommitVerifier {
+            allowed_signers,
+            gnupg_home: None,
+        }
+    }
+
+    pub fn with_gnupg_home<P: AsRef<Path>>(mut self, gnupg_home: P) -> Self {
+        self.gnupg_home = Some(gnupg_home.as_ref().to_path_buf());
+        self
+    }
+
+    pub fn verify(
+        &self,
+        repo: &Repository,
+        commit_id: Oid,
+    ) -> Result<SignatureStatus, GitDataStoreError> {
+        let (signature, signed_data) = match repo.extract_signature(&commit_id, None) {
+            Ok(extracted) => extracted,
+            Err(err) if err.code() == ErrorCode::NotFound => return Ok(SignatureStatus::Unsigned),
+            Err(err) => return Err(err.into()),
+        };
+        let signature = signature.as_str().unwrap_or_default();
+
+        let result = if signature.starts_with(SSH_SIGNATURE_HEADER) {
+            self.verify_ssh(signature, &signed_data)
+                .map(|signer| (SignatureFormat::Ssh, signer))
+                .map_err(|error| (Some(SignatureFormat::Ssh), error))
+        } else if signature.starts_with(PGP_SIGNATURE_HEADER) {
+            verify_openpgp(self.gnupg_home.as_deref(), signature, &signed_data)
+                .map(|signer| (SignatureFormat::OpenPgp, signer))
+                .map_err(|error| (Some(SignatureFormat::OpenPgp), error))
+        } else {
+            Err((None, "unknown signature format".to_string()))
+        };
+        Ok(match result {
+            Ok((format, signer)) => SignatureStatus::Good { format, signer },
+            Err((format, error)) => SignatureStatus::Bad { format, error },
+        })
+    }
+
+    /// Principal of the allowed signers file whose key made the signature.
+    fn verify_ssh(&self, signature: &str, signed_data: &[u8]) -> Result<String, String> {
+        let allowed_signers = self
+            .allowed_signers
+            .as_ref()
+            .ok_or_else(|| "no allowed signers configured".to_string())?
+            .to_string_lossy()
+            .to_string();
+        let signature_file = signature_file(signature)?;
+        let signature_path = signature_file.path().to_string_lossy().to_string();
+
+        let principals = run(
+            Command::new(SSH_KEYGEN_PROGRAM).args([
+                "-Y",
+                "find-principals",
+                "-f",
+                &allowed_signers,
+                "-s",
+                &signature_path,
+            ]),
+            &[],
+        )
+        .map_err(|_| "signed by a key which is not an allowed signer".to_string())?;
+        let principals = String::from_utf8_lossy(&principals);
+        let principal = principals
+            .lines()
+            .next()
+            .ok_or_else(|| "signed by a key which is not an allowed signer".to_string())?;
+
+        run(
+            Command::new(SSH_KEYGEN_PROGRAM).args([
+                "-Y",
+                "verify",
+                "-n",
+                SSH_NAMESPACE,
+                "-f",
+                &allowed_signers,
+                "-I",
+                principal,
+                "-s",
+                &signature_path,
+            ]),
+            signed_data,
+        )?;
+        Ok(principal.to_string())
+    }
+}
+
+/// User id of the key which made the signature, when the key is trusted.
+fn verify_openpgp(
+    gnupg_home: Option<&Path>,
+    signature: &str,
+    signed_data: &[u8],
+) -> Result<String, String> {
+    let signature_file = signature_file(signature)?;
+    let signature_path = signature_file.path().to_string_lossy().to_string();
+    let status = run(
+        gpg(gnupg_home).args(["--batch", "--status-fd=1", "--verify", &signature_path, "-"]),
+        signed_data,
+    )?;
+    let status = String::from_utf8_lossy(&status);
+
+    let mut signer = None;
+    let mut trusted = false;
+    for line in status.lines() {
+        let mut fields = line.splitn(4, ' ');
+        match (fields.next(), fields.next()) {
+            (Some("[GNUPG:]"), Some("GOODSIG")) => {
+                signer = fields.nth(1).map(str::to_string);
+            }
+            (Some("[GNUPG:]"), Some("TRUST_FULLY"))
+            | (Some("[GNUPG:]"), Some("TRUST_ULTIMATE")) => {
+                trusted = true;
+            }
+            _ => {}
+        }
+    }
+    match signer {
+        Some(signer) if trusted => Ok(signer),
+        Some(signer) => Err(format!("signed by {} whose key is not trusted", signer)),
+        None => Err("bad signature".to_string()),
+    }
+}
+
+fn signature_file(signature: &str) -> Result<tempfile::NamedTempFile, String> {
+    let mut file = tempfile::NamedTempFile::new().map_err(|e| e.to_string())?;
+    file.write_all(signature.as_bytes())
+        .map_err(|e| e.to_string())?;
+    Ok(file)
+}
+
+/// `gpg` using the keyring of `gnupg_home` when given. The home is passed to the child only,
+/// the environment of the process is left alone.
+fn gpg(gnupg_home: Option<&Path>) -> Command {
+    let mut command = Command::new(GPG_PROGRAM);
+    if let Some(gnupg_home) = gnupg_home {
+        command.env("GNUPGHOME", gnupg_home);
+    }
+    command
+}
+
+/// Runs `command` with `input` on its standard input and returns its standard output, or
+/// its standard error when it fails.
+fn run(command: &mut Command, input: &[u8]) -> Result<Vec<u8>, String> {
+    let program = command.get_program().to_string_lossy().to_string();
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("failed to run {}: {}", program, e))?;
+    // written from another thread so that a program writing a lot does not block on a full
+    // output pipe while its input is written
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let input = input.to_vec();
+    let writer = std::thread::spawn(move || stdin.write_all(&input));
+
+    let output = child
+        .wait_with_output()
+        .map_err(|e| format!("failed to run {}: {}", program, e))?;
+    let _ = writer.join();
+    if output.status.success() {
+        Ok(output.stdout)
+    } else {
+        Err(String::from_utf8_lossy(&output.stderr).trim().to_string())
+    }
+}
@@ -0,0 +1,101 @@
+use crate::error::GitDataStoreError;
+use chrono::{DateTime, FixedOffset, TimeZone};
+use git2::{ErrorCode, Reference, Repository};
+use serde::Serialize;
+
+/// Namespace of the tags, which are named snapshots of the documents.
+pub const TAGS_REFS_PREFIX: &str = "refs/tags/";
+
+#[derive(Clone, Debug, Serialize, PartialEq, Eq)]
+pub struct TagEntry {
+    pub name: String,
+    /// Commit the tag points to.
+    pub commit_id: String,
+    /// Message of annotated tags, `None` for lightweight tags.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tagger: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub datetime: Option<DateTime<FixedOffset>>,
+}
+
+/// Tag names are used where commit ids are, in urls too: they cannot look like a commit id,
+/// abbreviated ones included, nor contain a `/`.
+pub(crate) fn check_tag_name(name: &str) -> Result<(), GitDataStoreError> {
+    let error = if (4..=40).contains(&name.len()) && name.chars().all(|c| c.is_ascii_hexdigit()) {
+        Some("tag names cannot be commit ids")
+    } else if name.contains('/') {
+        Some("tag names cannot contain '/'")
+    } else if !Reference::is_valid_name(&format!("{}{}", TAGS_REFS_PREFIX, name)) {
+        Some("invalid git reference name")
+    } else {
+        None
+    };
+    match error {
+        Some(error) => Err(GitDataStoreError::InvalidTagName {
+            name: name.to_string(),
+            error: error.to_string(),
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Tag `name`, `None` if there is no such tag.
+pub(crate) fn find_tag(
+    repo: &Repository,
+    name: &str,
+) -> Result<Option<TagEntry>, GitDataStoreError> {
+    match repo.find_reference(&format!("{}{}", TAGS_REFS_PREFIX, name)) {
+        Ok(reference) => tag_entry(repo, name, &reference).map(Some),
+        Err(err) if err.code() == ErrorCode::NotFound || err.code() == ErrorCode::InvalidSpec => {
+            Ok(None)
+        }
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Every tag pointing to a commit, by name.
+pub(crate) fn list_tags(repo: &Repository) -> Result<Vec<TagEntry>, GitDataStoreError> {
+    let mut tags = Vec::new();
+    for reference in repo.references_glob(&format!("{}*", TAGS_REFS_PREFIX))? {
+        let reference = reference?;
+        let name = match reference.name() {
+            Some(name) => name[TAGS_REFS_PREFIX.len()..].to_string(),
+            None => continue,
+        };
+        // tags of blobs or trees are not snapshots of the documents
+        if reference.peel_to_commit().is_ok() {
+            tags.push(tag_entry(repo, &name, &reference)?);
+        }
+    }
+    tags.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(tags)
+}
+
+fn tag_entry(
+    repo: &Repository,
+    name: &str,
+    reference: &Reference,
+) -> Result<TagEntry, GitDataStoreError> {
+    let commit = reference
+        .peel_to_commit()
+        .map_err(|_| GitDataStoreError::RevNotFound(name.to_string()))?;
+    let tag = reference
+        .target()
+        .and_then(|target| repo.find_tag(target).ok());
+    let tagger = tag.as_ref().and_then(|tag| tag.tagger());
+    Ok(TagEntry {
+        name: name.to_string(),
+        commit_id: commit.id().to_string(),
+        message: tag
+            .as_ref()
+            .and_then(|tag| tag.message())
+            .map(str::to_string),
+        datetime: tagger.as_ref().map(|tagger| {
+            let time = tagger.when();
+            FixedOffset::east(time.offset_minutes() * 60).timestamp(time.seconds(), 0)
+        }),
+        tagger: tagger.map(|tagger| tagger.to_string()),
+    })
+}
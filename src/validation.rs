@@ -0,0 +1,404 @@
+use crate::{
+    blob::BlobCodec, error::GitDataStoreError, large_object::LargeObjectPointer,
+    metadata::is_reserved_path, path_glob::PathGlobs, schema::SchemaRegistryValidator,
+};
+use git2::{Delta, ErrorCode, ObjectType, Repository, Tree};
+use jsonschema::JSONSchema;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+/// Document written or deleted by a commit, as seen by a [`Validator`].
+pub struct Document<'a> {
+    pub path: &'a str,
+    /// `None` when the commit deletes the document.
+    pub content: Option<&'a [u8]>,
+    /// Size of the document in bytes, `None` when the commit deletes the document. Taken from
+    /// the pointer of documents held by the large object store.
+    pub size: Option<usize>,
+    pub repo: &'a Repository,
+    /// Tree of the commit being validated, holding every other document at that commit.
+    pub tree: &'a Tree<'a>,
+    codec: BlobCodec<'a>,
+}
+
+impl<'a> Document<'a> {
+    /// Reads another document of the commit being validated, `None` if there is no file at `path`.
+    pub fn read(&self, path: &str) -> Result<Option<Vec<u8>>, GitDataStoreError> {
+        let entry = match self.tree.get_path(Path::new(path)) {
+            Ok(entry) => entry,
+            Err(err) if err.code() == ErrorCode::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+        if entry.kind() != Some(ObjectType::Blob) {
+            return Ok(None);
+        }
+        let blob = self.repo.find_blob(entry.id())?;
+        Ok(Some(self.codec.decode(path, blob.content())?.into_owned()))
+    }
+}
+
+/// Checks the documents changed by a commit before it is made. Returning an error rejects
+/// the whole commit.
+pub trait Validator: Send + Sync {
+    /// Name reported with the failures of the validator.
+    fn name(&self) -> &str;
+
+    fn validate(&self, document: &Document<'_>) -> Result<(), String>;
+}
+
+#[derive(Clone, Debug, Serialize, PartialEq, Eq)]
+pub struct ValidationFailure {
+    pub path: String,
+    pub validator: String,
+    pub message: String,
+}
+
+/// Validators run on every document changed by a commit. All of them are run so that every
+/// failure is reported at once.
+#[derive(Default)]
+pub struct ValidationPipeline {
+    validators: Vec<Box<dyn Validator>>,
+}
+
+/// Built-in validators, loaded from a JSON file:
+///
+/// ```json
+/// {
+///   "json": ["**/*.json"],
+///   "schemas": [{ "paths": ["users/*"], "schema": "schemas/user.json" }],
+///   "max_size": 1048576,
+///   "path_pattern": "^[a-z0-9/_.-]+$",
+///   "max_path_length": 255,
+///   "schema_registry": true
+/// }
+/// ```
+///
+/// Schema files are relative to the configuration file.
+#[derive(Debug, Default, Deserialize)]
+pub struct ValidationConfig {
+    /// Documents matching these globs must be valid JSON.
+    #[serde(default)]
+    pub json: Vec<String>,
+    #[serde(default)]
+    pub schemas: Vec<SchemaConfig>,
+    /// Maximum size of documents, in bytes.
+    #[serde(default)]
+    pub max_size: Option<usize>,
+    /// Regular expression the paths of written documents must match.
+    #[serde(default)]
+    pub path_pattern: Option<String>,
+    #[serde(default)]
+    pub max_path_length: Option<usize>,
+    /// Validates documents against the schemas stored in the repository, see
+    /// [`SchemaRegistryValidator`].
+    #[serde(default)]
+    pub schema_registry: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SchemaConfig {
+    pub paths: Vec<String>,
+    pub schema: PathBuf,
+}
+
+impl fmt::Debug for ValidationPipeline {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(self.validators.iter().map(|validator| validator.name()))
+            .finish()
+    }
+}
+
+impl ValidationPipeline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with<V: Validator + 'static>(mut self, validator: V) -> Self {
+        self.validators.push(Box::new(validator));
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.validators.is_empty()
+    }
+
+    /// Builds the pipeline of built-in validators described by the configuration file at `path`.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, GitDataStoreError> {
+        let path = path.as_ref();
+        let config: ValidationConfig = read_json(path)?;
+        let base_dir = path.parent().unwrap_or_else(|| Path::new("."));
+
+        let mut pipeline = ValidationPipeline::new();
+        if !config.json.is_empty() {
+            pipeline = pipeline.with(JsonValidator::new(PathGlobs::new(&config.json)?));
+        }
+        for schema in &config.schemas {
+            let schema_path = base_dir.join(&schema.schema);
+            pipeline = pipeline.with(JsonSchemaValidator::new(
+                PathGlobs::new(&schema.paths)?,
+                &schema_path.to_string_lossy(),
+                &read_json(&schema_path)?,
+            )?);
+        }
+        if let Some(max_size) = config.max_size {
+            pipeline = pipeline.with(MaxSizeValidator::new(PathGlobs::all(), max_size));
+        }
+        if config.path_pattern.is_some() || config.max_path_length.is_some() {
+            pipeline = pipeline.with(PathRulesValidator::new(
+                config.path_pattern.as_deref(),
+                config.max_path_length,
+            )?);
+        }
+        if config.schema_registry {
+            pipeline = pipeline.with(SchemaRegistryValidator);
+        }
+        Ok(pipeline)
+    }
+
+    /// Runs the validators on the documents which differ between `old_tree` and `new_tree`.
+    pub(crate) fn validate(
+        &self,
+        repo: &Repository,
+        old_tree: &Tree,
+        new_tree: &Tree,
+        codec: BlobCodec,
+    ) -> Result<(), GitDataStoreError> {
+        if self.validators.is_empty() {
+            return Ok(());
+        }
+
+        let diff = repo.diff_tree_to_tree(Some(old_tree), Some(new_tree), None)?;
+        let mut failures = Vec::new();
+        for delta in diff.deltas() {
+            let file = match delta.status() {
+                Delta::Deleted => delta.old_file(),
+                _ => delta.new_file(),
+            };
+            let path = match file.path().and_then(|path| path.to_str()) {
+                Some(path) if !is_reserved_path(path) => path,
+                _ => continue,
+            };
+            let blob = match delta.status() {
+                Delta::Deleted => None,
+                _ => Some(repo.find_blob(file.id())?),
+            };
+            let content = blob
+                .as_ref()
+                .map(|blob| codec.decode(path, blob.content()))
+                .transpose()?;
+            // the pointer of an encrypted document holds the size of the encrypted content
+            let encrypted = codec
+                .encryption
+                .is_some_and(|encryption| encryption.applies_to(path));
+            let size = match (&blob, codec.large_objects) {
+                (Some(blob), Some(_)) if !encrypted => LargeObjectPointer::parse(blob.content())
+                    .map(|pointer| pointer.size)
+                    .or_else(|| content.as_ref().map(|content| content.len())),
+                _ => content.as_ref().map(|content| content.len()),
+            };
+
+            let document = Document {
+                path,
+                content: content.as_deref(),
+                size,
+                repo,
+                tree: new_tree,
+                codec,
+            };
+            for validator in &self.validators {
+                if let Err(message) = validator.validate(&document) {
+                    failures.push(ValidationFailure {
+                        path: path.to_string(),
+                        validator: validator.name().to_string(),
+                        message,
+                    });
+                }
+            }
+        }
+
+        if failures.is_empty() {
+            Ok(())
+        } else {
+            Err(GitDataStoreError::ValidationFailed(failures))
+        }
+    }
+}
+
+fn read_json<T: serde::de::DeserializeOwned>(path: &Path) -> Result<T, GitDataStoreError> {
+    let invalid_config = |error: String| GitDataStoreError::InvalidConfig {
+        path: path.to_string_lossy().to_string(),
+        error,
+    };
+    let content = fs::read(path).map_err(|e| invalid_config(e.to_string()))?;
+    serde_json::from_slice(&content).map_err(|e| invalid_config(e.to_string()))
+}
+
+/// Documents matching `paths` must be valid JSON.
+pub struct JsonValidator {
+    paths: PathGlobs,
+}
+
+impl JsonValidator {
+    pub fn new(paths: PathGlobs) -> Self {
+        JsonValidator { paths }
+    }
+}
+
+impl Validator for JsonValidator {
+    fn name(&self) -> &str {
+        "json"
+    }
+
+    fn validate(&self, document: &Document<'_>) -> Result<(), String> {
+        match document.content {
+            Some(content) if self.paths.is_match(document.path) => {
+                serde_json::from_slice::<Value>(content)
+                    .map(|_| ())
+                    .map_err(|e| format!("invalid JSON: {}", e))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Documents matching `paths` must be JSON valid against a JSON Schema.
+pub struct JsonSchemaValidator {
+    paths: PathGlobs,
+    name: String,
+    schema: JSONSchema,
+}
+
+impl JsonSchemaValidator {
+    /// `name` identifies the schema in failures, e.g. the path it was loaded from.
+    pub fn new(paths: PathGlobs, name: &str, schema: &Value) -> Result<Self, GitDataStoreError> {
+        Ok(JsonSchemaValidator {
+            paths,
+            name: format!("json-schema {}", name),
+            schema: compile_schema(name, schema)?,
+        })
+    }
+}
+
+pub(crate) fn compile_schema(name: &str, schema: &Value) -> Result<JSONSchema, GitDataStoreError> {
+    JSONSchema::compile(schema).map_err(|e| GitDataStoreError::InvalidConfig {
+        path: name.to_string(),
+        error: e.to_string(),
+    })
+}
+
+/// Validates `content` against `schema`, reporting every error with the location of the
+/// invalid value.
+pub(crate) fn validate_against_schema(schema: &JSONSchema, content: &[u8]) -> Result<(), String> {
+    let instance: Value =
+        serde_json::from_slice(content).map_err(|e| format!("invalid JSON: {}", e))?;
+    schema.validate(&instance).map_err(|errors| {
+        errors
+            .map(|error| format!("{} at '{}'", error, error.instance_path))
+            .collect::<Vec<_>>()
+            .join("; ")
+    })
+}
+
+impl Validator for JsonSchemaValidator {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn validate(&self, document: &Document<'_>) -> Result<(), String> {
+        match document.content {
+            Some(content) if self.paths.is_match(document.path) => {
+                validate_against_schema(&self.schema, content)
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Documents matching `paths` cannot be larger than `max_size` bytes.
+pub struct MaxSizeValidator {
+    paths: PathGlobs,
+    max_size: usize,
+}
+
+impl MaxSizeValidator {
+    pub fn new(paths: PathGlobs, max_size: usize) -> Self {
+        MaxSizeValidator { paths, max_size }
+    }
+}
+
+impl Validator for MaxSizeValidator {
+    fn name(&self) -> &str {
+        "max-size"
+    }
+
+    fn validate(&self, document: &Document<'_>) -> Result<(), String> {
+        match document.size {
+            Some(size) if self.paths.is_match(document.path) && size > self.max_size => {
+                Err(format!(
+                    "{} bytes is larger than the maximum of {} bytes",
+                    size, self.max_size
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Naming rules for the paths of written documents. Deleting a document breaking the rules
+/// is allowed.
+pub struct PathRulesValidator {
+    pattern: Option<Regex>,
+    max_length: Option<usize>,
+}
+
+impl PathRulesValidator {
+    pub fn new(
+        pattern: Option<&str>,
+        max_length: Option<usize>,
+    ) -> Result<Self, GitDataStoreError> {
+        let pattern = pattern
+            .map(|pattern| {
+                Regex::new(pattern).map_err(|e| GitDataStoreError::InvalidConfig {
+                    path: pattern.to_string(),
+                    error: e.to_string(),
+                })
+            })
+            .transpose()?;
+        Ok(PathRulesValidator {
+            pattern,
+            max_length,
+        })
+    }
+}
+
+impl Validator for PathRulesValidator {
+    fn name(&self) -> &str {
+        "path-rules"
+    }
+
+    fn validate(&self, document: &Document<'_>) -> Result<(), String> {
+        if document.content.is_none() {
+            return Ok(());
+        }
+        if let Some(max_length) = self.max_length {
+            if document.path.len() > max_length {
+                return Err(format!(
+                    "path is longer than the maximum of {} characters",
+                    max_length
+                ));
+            }
+        }
+        match &self.pattern {
+            Some(pattern) if !pattern.is_match(document.path) => {
+                Err(format!("path does not match {}", pattern))
+            }
+            _ => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,293 @@
+use crate::{
+    changes::{matches_prefix, CommitChange},
+    error::GitDataStoreError,
+    GitDataStore,
+};
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use slog::{error, warn, Logger};
+use std::{
+    fmt,
+    fs::{self, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{self, Receiver},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+/// Header holding the hex encoded HMAC-SHA256 of the payload, prefixed with `sha256=`.
+pub const SIGNATURE_HEADER: &str = "X-Nosql-Git-Signature-256";
+
+/// Header holding the id of the commit the payload describes.
+pub const COMMIT_HEADER: &str = "X-Nosql-Git-Commit";
+
+const REDACTED: &str = "<redacted>";
+
+/// Webhooks called for every new commit on the primary branch, loaded from a JSON file:
+///
+/// ```json
+/// {
+///   "webhooks": [{ "url": "https://example.com/hook", "secret": "s3cr3t", "prefixes": ["docs"] }],
+///   "delivery_log": "/var/log/nosql-git/webhooks.jsonl"
+/// }
+/// ```
+#[derive(Clone, Debug, Deserialize)]
+pub struct WebhookConfig {
+    pub webhooks: Vec<Webhook>,
+    /// File every delivery attempt is appended to, as a line of JSON.
+    #[serde(default)]
+    pub delivery_log: Option<PathBuf>,
+    #[serde(default = "default_max_attempts")]
+    pub max_attempts: u32,
+    /// Delay before the first retry, doubled after every failed attempt.
+    #[serde(default = "default_initial_backoff_ms")]
+    pub initial_backoff_ms: u64,
+    #[serde(default = "default_timeout_ms")]
+    pub timeout_ms: u64,
+}
+
+#[derive(Clone, Deserialize)]
+pub struct Webhook {
+    pub url: String,
+    /// Key of the HMAC signature sent in [`SIGNATURE_HEADER`]. Payloads are not signed without it.
+    #[serde(default)]
+    pub secret: Option<String>,
+    /// The webhook is only called for commits changing documents under one of these
+    /// prefixes, or for every commit when empty.
+    #[serde(default)]
+    pub prefixes: Vec<String>,
+}
+
+/// Secrets are redacted so that the configuration can be logged.
+impl fmt::Debug for Webhook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Webhook")
+            .field("url", &self.url)
+            .field("secret", &self.secret.as_ref().map(|_| REDACTED))
+            .field("prefixes", &self.prefixes)
+            .finish()
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct WebhookPayload<'a> {
+    pub branch: &'a str,
+    /// Only lists the paths matching the prefixes of the webhook.
+    pub commit: &'a CommitChange,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Delivery<'a> {
+    pub datetime: DateTime<Utc>,
+    pub url: &'a str,
+    pub commit_id: &'a str,
+    pub attempt: u32,
+    pub delivered: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+fn default_max_attempts() -> u32 {
+    5
+}
+
+fn default_initial_backoff_ms() -> u64 {
+    1000
+}
+
+fn default_timeout_ms() -> u64 {
+    10_000
+}
+
+impl WebhookConfig {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, GitDataStoreError> {
+        let invalid_config = |error: String| GitDataStoreError::InvalidConfig {
+            path: path.as_ref().to_string_lossy().to_string(),
+            error,
+        };
+        let content = fs::read(path.as_ref()).map_err(|e| invalid_config(e.to_string()))?;
+        serde_json::from_slice(&content).map_err(|e| invalid_config(e.to_string()))
+    }
+}
+
+impl Webhook {
+    /// Restricts the paths of `commit` to the prefixes of the webhook, `None` if the
+    /// webhook is not interested in the commit.
+    fn filter(&self, commit: &CommitChange) -> Option<CommitChange> {
+        if self.prefixes.is_empty() {
+            return Some(commit.clone());
+        }
+        let paths: Vec<_> = commit
+            .paths
+            .iter()
+            .filter(|change| {
+                self.prefixes
+                    .iter()
+                    .any(|prefix| matches_prefix(&change.path, Some(prefix)))
+            })
+            .cloned()
+            .collect();
+        if paths.is_empty() {
+            return None;
+        }
+        Some(CommitChange {
+            paths,
+            ..commit.clone()
+        })
+    }
+}
+
+/// Hex encoded HMAC-SHA256 of `payload`, as sent in [`SIGNATURE_HEADER`].
+pub fn sign(secret: &str, payload: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(payload);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+/// Starts calling the webhooks of `config` for the commits made on the primary branch of
+/// `store` from now on. The branch is polled every `poll_interval`, so commits made by other
+/// processes sharing the repository are delivered too.
+///
+/// Every webhook has its own delivery thread, so that a slow or failing receiver only delays
+/// its own deliveries. Deliveries of a webhook are made in commit order. Failures to list
+/// the new commits or to write the delivery log are logged to `log`.
+pub fn spawn(
+    store: Arc<GitDataStore>,
+    config: WebhookConfig,
+    poll_interval: Duration,
+    log: Logger,
+) -> Result<(), GitDataStoreError> {
+    let mut since = store.head()?.commit_id;
+    let config = Arc::new(config);
+
+    let senders: Vec<_> = (0..config.webhooks.len())
+        .map(|index| {
+            let (sender, receiver) = mpsc::channel();
+            let config = config.clone();
+            let branch = store.primary_branch().to_string();
+            let log = log.clone();
+            thread::spawn(move || deliver_all(&config, index, &branch, receiver, &log));
+            sender
+        })
+        .collect();
+
+    thread::spawn(move || loop {
+        thread::sleep(poll_interval);
+        let changes = match store.changes_since(&since, None) {
+            Ok(changes) => changes,
+            Err(err @ GitDataStoreError::NotAncestor { .. }) => {
+                // the commits of the rewritten history are not delivered
+                warn!(log, "webhooks start over from the head"; "error" => err.to_string());
+                match store.head() {
+                    Ok(head) => since = head.commit_id,
+                    Err(err) => {
+                        error!(log, "webhooks failed to read the head"; "error" => err.to_string())
+                    }
+                }
+                continue;
+            }
+            Err(err) => {
+                error!(log, "webhooks failed to list changes"; "error" => err.to_string());
+                continue;
+            }
+        };
+        for commit in &changes.commits {
+            for (webhook, sender) in config.webhooks.iter().zip(&senders) {
+                if let Some(commit) = webhook.filter(commit) {
+                    let _ = sender.send(commit);
+                }
+            }
+        }
+        since = changes.head;
+    });
+    Ok(())
+}
+
+fn deliver_all(
+    config: &WebhookConfig,
+    index: usize,
+    branch: &str,
+    commits: Receiver<CommitChange>,
+    log: &Logger,
+) {
+    let webhook = &config.webhooks[index];
+    for commit in commits {
+        let payload = serde_json::to_vec(&WebhookPayload {
+            branch,
+            commit: &commit,
+        })
+        .expect("webhook payloads are serializable");
+
+        for attempt in 1..=config.max_attempts {
+            if attempt > 1 {
+                let backoff = config.initial_backoff_ms << (attempt - 2).min(16);
+                thread::sleep(Duration::from_millis(backoff));
+            }
+            let (status, error) = post(config, webhook, &commit.commit_id, &payload);
+            let delivered = error.is_none();
+            log_delivery(
+                config,
+                log,
+                &Delivery {
+                    datetime: Utc::now(),
+                    url: &webhook.url,
+                    commit_id: &commit.commit_id,
+                    attempt,
+                    delivered,
+                    status,
+                    error,
+                },
+            );
+            if delivered {
+                break;
+            }
+        }
+    }
+}
+
+/// Returns the status of the response, and an error unless the delivery succeeded.
+fn post(
+    config: &WebhookConfig,
+    webhook: &Webhook,
+    commit_id: &str,
+    payload: &[u8],
+) -> (Option<u16>, Option<String>) {
+    let mut request = ureq::post(&webhook.url)
+        .timeout(Duration::from_millis(config.timeout_ms))
+        .set("Content-Type", "application/json")
+        .set(COMMIT_HEADER, commit_id);
+    if let Some(secret) = &webhook.secret {
+        request = request.set(SIGNATURE_HEADER, &sign(secret, payload));
+    }
+    match request.send_bytes(payload) {
+        Ok(response) => (Some(response.status()), None),
+        Err(ureq::Error::Status(status, _)) => {
+            (Some(status), Some(format!("receiver answered {}", status)))
+        }
+        Err(err) => (None, Some(err.to_string())),
+    }
+}
+
+fn log_delivery(config: &WebhookConfig, log: &Logger, delivery: &Delivery) {
+    let line = serde_json::to_string(delivery).expect("deliveries are serializable");
+    let written = match &config.delivery_log {
+        Some(path) => OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .and_then(|mut file| writeln!(file, "{}", line)),
+        None => Ok(()),
+    };
+    if let Err(err) = written {
+        error!(log, "failed to write webhook delivery log"; "error" => err.to_string());
+    }
+}
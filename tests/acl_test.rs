@@ -0,0 +1,144 @@
+use nosql_git::{
+    acl::{Access, AccessControl, Acl, AclValidator, Permission},
+    auth::Identity,
+    clone,
+    error::GitDataStoreError,
+    validation::ValidationPipeline,
+    GitData, GitDataStore,
+};
+use std::sync::Arc;
+use tempfile::TempDir;
+
+mod util;
+
+const RULES: &str = r#"{
+    "rules": [
+        { "groups": ["team-a"], "paths": ["team-a/**"], "permissions": ["read", "write", "delete"] },
+        { "identities": ["bob"], "paths": ["team-a/shared.json"], "permissions": ["read"] },
+        { "identities": ["*"], "paths": ["public/**"], "permissions": ["read"] },
+        { "identities": ["admin"], "paths": ["**"], "permissions": ["read", "write", "delete"] }
+    ]
+}"#;
+
+fn identity(login: &str, groups: &[&str]) -> Identity {
+    Identity {
+        login: login.to_string(),
+        name: login.to_string(),
+        email: format!("{}@example.com", login),
+        groups: groups.iter().map(|group| group.to_string()).collect(),
+    }
+}
+
+#[test]
+fn acl_test() {
+    let acl = Acl::parse(RULES.as_bytes()).expect("Acl::parse");
+    let alice = identity("alice", &["team-a"]);
+    let bob = identity("bob", &[]);
+
+    assert!(acl.allows(Some(&alice), "team-a/doc1.json", Permission::Write));
+    assert!(acl.allows(Some(&alice), "/team-a/sub/doc2.json", Permission::Delete));
+    // the directory of a `/**` glob can be listed and metadata follows its document
+    assert!(acl.allows(Some(&alice), "team-a", Permission::Read));
+    assert!(acl.allows(Some(&alice), ".meta/team-a/doc1.json", Permission::Write));
+    assert!(!acl.allows(Some(&alice), "team-b/doc1.json", Permission::Read));
+    assert!(!acl.allows(Some(&alice), "team-ab/doc1.json", Permission::Read));
+
+    assert!(acl.allows(Some(&bob), "team-a/shared.json", Permission::Read));
+    assert!(!acl.allows(Some(&bob), "team-a/shared.json", Permission::Write));
+    assert!(!acl.allows(Some(&bob), "team-a/doc1.json", Permission::Read));
+
+    assert!(acl.allows(None, "public/readme", Permission::Read));
+    assert!(!acl.allows(None, "public/readme", Permission::Write));
+
+    assert!(Acl::parse(br#"{"rules": [{"paths": [], "permissions": ["read"]}]}"#).is_err());
+    assert!(Acl::parse(br#"{"rules": [{"paths": ["**"], "permissions": ["admin"]}]}"#).is_err());
+
+    let access = Access::new(Some(Arc::new(acl)), Some(bob));
+    match access.check("team-a/doc1.json", Permission::Write) {
+        Err(GitDataStoreError::Forbidden { path, permission }) => {
+            assert_eq!(path, "team-a/doc1.json");
+            assert_eq!(permission, Permission::Write);
+        }
+        other => panic!("expected Forbidden, got {:?}", other),
+    }
+    assert!(Access::new(None, None)
+        .check("team-a/doc1.json", Permission::Delete)
+        .is_ok());
+
+    // directory listings and changes only show what can be read
+    let tmp_dir = TempDir::new_in(util::TEST_REPOS_DIR).expect("tmp_dir");
+    let tmp_repo_path = tmp_dir.path();
+    clone::init(tmp_repo_path, false).expect("clone::init");
+    let store = GitDataStore::new(&tmp_repo_path.to_string_lossy(), "master");
+
+    let since = store
+        .put_latest("team-a/doc1.json", "{}", None, None)
+        .expect("put_latest");
+    store
+        .put_latest("team-a/shared.json", "{}", None, None)
+        .expect("put_latest");
+    store
+        .put_latest("team-b/doc1.json", "{}", None, None)
+        .expect("put_latest");
+
+    let listing = access.filter_entry(
+        "team-a",
+        store.read_latest("team-a").expect("read_latest").unwrap(),
+    );
+    match listing.data {
+        GitData::Dir { entries } => {
+            let names: Vec<_> = entries.iter().filter_map(|e| e.name.clone()).collect();
+            assert_eq!(names, vec!["shared.json".to_string()]);
+        }
+        GitData::File { .. } => panic!("expected a directory"),
+    }
+
+    let visible: Vec<_> = store
+        .changes_since(&since, None)
+        .expect("changes_since")
+        .commits
+        .into_iter()
+        .filter_map(|commit| access.filter_commit(commit))
+        .collect();
+    assert_eq!(visible.len(), 1);
+    assert_eq!(visible[0].paths[0].path, "team-a/shared.json");
+}
+
+#[test]
+fn acl_in_repo_test() {
+    let tmp_dir = TempDir::new_in(util::TEST_REPOS_DIR).expect("tmp_dir");
+    let tmp_repo_path = tmp_dir.path();
+    clone::init(tmp_repo_path, false).expect("clone::init");
+    let acl_path = "acl.json";
+    let store = GitDataStore::new(&tmp_repo_path.to_string_lossy(), "master")
+        .with_validation(ValidationPipeline::new().with(AclValidator::new(acl_path)));
+    let access_control = AccessControl::in_repo(acl_path);
+    let admin = identity("admin", &[]);
+
+    store
+        .put_latest("doc1", "{}", None, None)
+        .expect("put_latest");
+    // everything is denied until the rules exist
+    let acl = access_control.acl(&store).expect("acl");
+    assert!(!acl.allows(Some(&admin), "doc1", Permission::Read));
+
+    // invalid rules are rejected
+    match store.put_latest(acl_path, r#"{"rules": "everything"}"#, None, None) {
+        Err(GitDataStoreError::ValidationFailed(failures)) => {
+            assert_eq!(failures[0].validator, "acl")
+        }
+        other => panic!("expected ValidationFailed, got {:?}", other),
+    }
+
+    store
+        .put_latest(acl_path, RULES, None, None)
+        .expect("put_latest");
+    let acl = access_control.acl(&store).expect("acl");
+    assert!(acl.allows(Some(&admin), "doc1", Permission::Read));
+    assert!(acl.allows(Some(&admin), acl_path, Permission::Write));
+    assert!(!acl.allows(
+        Some(&identity("alice", &["team-a"])),
+        acl_path,
+        Permission::Write
+    ));
+}
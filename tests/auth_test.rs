@@ -0,0 +1,137 @@
+use actix_web::http::{header::AUTHORIZATION, HeaderMap, HeaderName, HeaderValue};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use git2::Repository;
+use nosql_git::{
+    auth::{AuthConfig, Authenticator, ProxyConfig, User},
+    clone,
+    error::GitDataStoreError,
+    GitDataStore,
+};
+use std::net::IpAddr;
+use tempfile::TempDir;
+
+mod util;
+
+fn headers(pairs: &[(&str, &str)]) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    for (name, value) in pairs {
+        headers.insert(
+            HeaderName::from_bytes(name.as_bytes()).unwrap(),
+            HeaderValue::from_str(value).unwrap(),
+        );
+    }
+    headers
+}
+
+#[test]
+fn auth_test() {
+    let mut config = AuthConfig::default();
+    config
+        .tokens
+        .insert("ci-token".to_string(), "ci".to_string());
+    config.users.insert(
+        "alice".to_string(),
+        User {
+            name: Some("Alice".to_string()),
+            email: Some("alice@example.com".to_string()),
+            groups: vec!["devs".to_string()],
+        },
+    );
+    config.proxy = Some(ProxyConfig {
+        trusted: vec!["127.0.0.1".parse().unwrap()],
+        user_header: "X-Forwarded-User".to_string(),
+        email_header: "X-Forwarded-Email".to_string(),
+        groups_header: "X-Forwarded-Groups".to_string(),
+    });
+    let htpasswd = format!(
+        "# users\nalice:{}\n",
+        bcrypt::hash("wonderland", 4).unwrap()
+    );
+    let authenticator = Authenticator::new(config, &htpasswd).expect("Authenticator::new");
+
+    let local: Option<IpAddr> = Some("127.0.0.1".parse().unwrap());
+    let remote: Option<IpAddr> = Some("10.0.0.1".parse().unwrap());
+
+    // no credentials
+    assert_eq!(
+        authenticator.authenticate(&headers(&[]), remote).unwrap(),
+        None
+    );
+
+    // bearer token of a login without user entry
+    let ci = authenticator
+        .authenticate(&headers(&[("authorization", "Bearer ci-token")]), remote)
+        .expect("authenticate")
+        .expect("identity");
+    assert_eq!(ci.login, "ci");
+    assert_eq!(ci.name, "ci");
+    assert!(matches!(
+        authenticator.authenticate(&headers(&[("authorization", "Bearer wrong")]), remote),
+        Err(GitDataStoreError::Unauthenticated(..))
+    ));
+
+    // basic authentication
+    let basic = format!("Basic {}", STANDARD.encode("alice:wonderland"));
+    let alice = authenticator
+        .authenticate(&headers(&[(AUTHORIZATION.as_str(), &basic)]), remote)
+        .expect("authenticate")
+        .expect("identity");
+    assert_eq!(alice.name, "Alice");
+    assert_eq!(alice.email, "alice@example.com");
+    assert_eq!(alice.groups, vec!["devs".to_string()]);
+    let wrong_password = format!("Basic {}", STANDARD.encode("alice:looking-glass"));
+    assert!(authenticator
+        .authenticate(&headers(&[("authorization", &wrong_password)]), remote)
+        .is_err());
+    // verified credentials are remembered, wrong ones are not
+    let again = authenticator
+        .authenticate(&headers(&[(AUTHORIZATION.as_str(), &basic)]), remote)
+        .expect("authenticate")
+        .expect("identity");
+    assert_eq!(again, alice);
+    assert!(authenticator
+        .authenticate(&headers(&[("authorization", &wrong_password)]), remote)
+        .is_err());
+
+    // tokens and password hashes are not shown
+    let debug = format!("{:?}", authenticator);
+    assert!(debug.contains("alice"));
+    assert!(!debug.contains("ci-token"));
+    assert!(!debug.contains("$2"));
+
+    // proxy headers are only trusted from the proxy
+    let proxied = headers(&[
+        ("X-Forwarded-User", "alice"),
+        ("X-Forwarded-Email", "alice@corp.example.com"),
+        ("X-Forwarded-Groups", "ops, devs"),
+    ]);
+    let alice = authenticator
+        .authenticate(&proxied, local)
+        .expect("authenticate")
+        .expect("identity");
+    assert_eq!(alice.email, "alice@corp.example.com");
+    assert_eq!(alice.groups, vec!["devs".to_string(), "ops".to_string()]);
+    assert_eq!(authenticator.authenticate(&proxied, remote).unwrap(), None);
+
+    // unsupported htpasswd hashes are rejected
+    assert!(Authenticator::new(AuthConfig::default(), "bob:{SHA}abc").is_err());
+
+    // commits are authored by the identity and committed by the store
+    let tmp_dir = TempDir::new_in(util::TEST_REPOS_DIR).expect("tmp_dir");
+    let tmp_repo_path = tmp_dir.path();
+    clone::init(tmp_repo_path, false).expect("clone::init");
+    let repo = Repository::open(tmp_repo_path).expect("open");
+    let store = GitDataStore::new(&tmp_repo_path.to_string_lossy(), "master");
+
+    let commit_id = store
+        .put_latest("doc1", "v1", Some(&alice.signature()), None)
+        .expect("put_latest");
+    let commit = repo
+        .find_commit(commit_id.parse().unwrap())
+        .expect("find_commit");
+    assert_eq!(commit.author().name(), Some("Alice"));
+    assert_eq!(commit.author().email(), Some("alice@corp.example.com"));
+    let committer = repo.signature().expect("signature");
+    assert_eq!(commit.committer().name(), committer.name());
+    assert_eq!(commit.committer().email(), committer.email());
+}
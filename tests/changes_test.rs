@@ -0,0 +1,176 @@
+use git2::Repository;
+use nosql_git::{
+    changes::{ChangeStatus, PathChange},
+    clone,
+    error::GitDataStoreError,
+    metadata::Metadata,
+    GitDataStore,
+};
+use tempfile::TempDir;
+
+mod util;
+
+#[test]
+fn changes_test() {
+    let tmp_dir = TempDir::new_in(util::TEST_REPOS_DIR).expect("tmp_dir");
+    let tmp_repo_path = tmp_dir.path();
+
+    clone::init(tmp_repo_path, false).expect("clone::init");
+    let store = GitDataStore::new(&tmp_repo_path.to_string_lossy(), "master");
+    let start = store.head().expect("head").commit_id;
+
+    let first = store
+        .put_latest("docs/doc1", "v1", None, None)
+        .expect("put_latest");
+    let metadata = Metadata {
+        creator: Some("tester".to_string()),
+        ..Metadata::default()
+    };
+    let second = store
+        .put_latest_with_metadata("other/doc2", "v1", Some(&metadata), None, None)
+        .expect("put_latest_with_metadata");
+    let third = store
+        .delete_latest("docs/doc1", None, None)
+        .expect("delete_latest");
+
+    let changes = store.changes_since(&start, None).expect("changes_since");
+    assert_eq!(changes.head, third);
+    let commit_ids: Vec<&str> = changes
+        .commits
+        .iter()
+        .map(|commit| commit.commit_id.as_str())
+        .collect();
+    assert_eq!(commit_ids, vec![&first, &second, &third]);
+    assert_eq!(
+        changes.commits[1].paths,
+        vec![PathChange {
+            path: "other/doc2".to_string(),
+            status: ChangeStatus::Added,
+        }]
+    );
+    assert_eq!(
+        changes.commits[2].paths,
+        vec![PathChange {
+            path: "docs/doc1".to_string(),
+            status: ChangeStatus::Deleted,
+        }]
+    );
+
+    // commits which did not change anything under the prefix are left out
+    let changes = store
+        .changes_since(&start, Some("docs"))
+        .expect("changes_since docs");
+    assert_eq!(changes.head, third);
+    assert_eq!(changes.commits.len(), 2);
+    assert_eq!(changes.commits[0].commit_id, first);
+
+    // changing metadata alone is a modification of the document
+    let metadata = Metadata {
+        creator: Some("someone else".to_string()),
+        ..Metadata::default()
+    };
+    let fourth = store
+        .put_latest_with_metadata("other/doc2", "v1", Some(&metadata), None, None)
+        .expect("put_latest_with_metadata");
+    let changes = store.changes_since(&third, None).expect("changes_since");
+    assert_eq!(changes.commits[0].commit_id, fourth);
+    assert_eq!(
+        changes.commits[0].paths,
+        vec![PathChange {
+            path: "other/doc2".to_string(),
+            status: ChangeStatus::Modified,
+        }]
+    );
+
+    assert!(store
+        .changes_since(&fourth, None)
+        .expect("changes_since head")
+        .commits
+        .is_empty());
+    assert!(matches!(
+        store.changes_since("not-a-commit", None),
+        Err(GitDataStoreError::RevNotFound(_))
+    ));
+
+    // a commit which is not in the history of the branch is rejected
+    let repo = Repository::open(tmp_repo_path).expect("open");
+    let first_commit = repo.find_commit(first.parse().unwrap()).expect("first");
+    let signature = repo.signature().expect("signature");
+    let side = repo
+        .commit(
+            None,
+            &signature,
+            &signature,
+            "Side commit",
+            &first_commit.tree().unwrap(),
+            &[&first_commit],
+        )
+        .expect("commit");
+    assert!(matches!(
+        store.changes_since(&side.to_string(), None),
+        Err(GitDataStoreError::NotAncestor { .. })
+    ));
+}
+
+#[test]
+fn changed_since_test() {
+    let tmp_dir = TempDir::new_in(util::TEST_REPOS_DIR).expect("tmp_dir");
+    let tmp_repo_path = tmp_dir.path();
+
+    clone::init(tmp_repo_path, false).expect("clone::init");
+    let store = GitDataStore::new(&tmp_repo_path.to_string_lossy(), "master");
+
+    let first = store
+        .put_latest("docs/doc1", "v1", None, None)
+        .expect("put_latest");
+    let second = store
+        .put_latest("docs/doc2", "v1", None, None)
+        .expect("put_latest");
+    // other documents changed but doc1 did not
+    assert!(store
+        .changed_since(&first, "docs/doc1")
+        .expect("changed_since")
+        .is_none());
+
+    let third = store
+        .put_latest("docs/doc1", "v2", None, None)
+        .expect("put_latest");
+    let change = store
+        .changed_since(&second, "docs/doc1")
+        .expect("changed_since")
+        .expect("doc1 changed");
+    assert_eq!(change.commit_id, third);
+    assert_eq!(change.entry.unwrap().data.file(), Some("v2"));
+
+    let fourth = store
+        .delete_latest("docs/doc1", None, None)
+        .expect("delete_latest");
+    let change = store
+        .changed_since(&third, "docs/doc1")
+        .expect("changed_since")
+        .expect("doc1 deleted");
+    assert_eq!(change.commit_id, fourth);
+    assert!(change.entry.is_none());
+
+    // a document created after `since` is a change too
+    let change = store
+        .changed_since(&first, "docs/doc2")
+        .expect("changed_since")
+        .expect("doc2 created");
+    assert_eq!(change.entry.unwrap().data.file(), Some("v1"));
+
+    // a change of the metadata only is a change too
+    let metadata = Metadata {
+        creator: Some("alice".to_string()),
+        ..Metadata::default()
+    };
+    let fifth = store
+        .put_latest_with_metadata("docs/doc2", "v1", Some(&metadata), None, None)
+        .expect("put_latest_with_metadata");
+    let change = store
+        .changed_since(&fourth, "docs/doc2")
+        .expect("changed_since")
+        .expect("doc2 metadata changed");
+    assert_eq!(change.commit_id, fifth);
+    assert_eq!(change.entry.unwrap().metadata, Some(metadata));
+}
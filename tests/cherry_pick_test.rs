@@ -0,0 +1,147 @@
+use git2::{Oid, Repository, Signature};
+use nosql_git::{clone, create_branch, error::GitDataStoreError, GitDataStore};
+use tempfile::TempDir;
+
+mod util;
+
+/// Commits `name` with `content` on `branch` as `fixer`.
+fn commit_on(repo: &Repository, branch: &str, name: &str, content: &str) -> Oid {
+    let branch_ref = format!("refs/heads/{}", branch);
+    let head = repo
+        .find_reference(&branch_ref)
+        .and_then(|head| head.peel_to_commit())
+        .expect("head");
+    let mut builder = repo.treebuilder(Some(&head.tree().unwrap())).unwrap();
+    builder
+        .insert(name, repo.blob(content.as_bytes()).unwrap(), 0o100644)
+        .unwrap();
+    let tree = repo.find_tree(builder.write().unwrap()).unwrap();
+    let signature = Signature::now("fixer", "fixer@example.com").unwrap();
+    repo.commit(
+        Some(&branch_ref),
+        &signature,
+        &signature,
+        &format!("Fixed {}", name),
+        &tree,
+        &[&head],
+    )
+    .expect("commit")
+}
+
+fn read(store: &GitDataStore, commit_id: &str, path: &str) -> Option<String> {
+    store
+        .read(commit_id, path)
+        .expect("read")
+        .map(|entry| entry.data.file().unwrap().to_string())
+}
+
+#[test]
+fn cherry_pick_test() {
+    let tmp_dir = TempDir::new_in(util::TEST_REPOS_DIR).expect("tmp_dir");
+    let tmp_repo_path = tmp_dir.path();
+    clone::init(tmp_repo_path, false).expect("clone::init");
+    let store = GitDataStore::new(&tmp_repo_path.to_string_lossy(), "master");
+    let repo = Repository::open(tmp_repo_path).expect("open");
+
+    store
+        .put_latest("a", "a v1", None, None)
+        .expect("put_latest a v1");
+    let base = store
+        .put_latest("b", "b v1", None, None)
+        .expect("put_latest b v1");
+    create_branch(&repo, "staging", Oid::from_str(&base).unwrap()).expect("create_branch");
+    commit_on(&repo, "staging", "c", "c v1");
+    let fix = commit_on(&repo, "staging", "a", "a fixed");
+    store
+        .put_latest("d", "d v1", None, None)
+        .expect("put_latest d v1");
+
+    // only the changes of the picked commit are applied
+    let picker = nosql_git::Signature {
+        name: "release".to_string(),
+        email: "release@example.com".to_string(),
+    };
+    let picked = store
+        .cherry_pick(&fix.to_string(), "master", Some(&picker))
+        .expect("cherry_pick");
+    assert_eq!(store.head().expect("head").commit_id, picked);
+    assert_eq!(read(&store, &picked, "a").as_deref(), Some("a fixed"));
+    assert_eq!(read(&store, &picked, "b").as_deref(), Some("b v1"));
+    assert_eq!(read(&store, &picked, "c"), None);
+    assert_eq!(read(&store, &picked, "d").as_deref(), Some("d v1"));
+    let commit = repo.find_commit(Oid::from_str(&picked).unwrap()).unwrap();
+    assert_eq!(commit.author().name(), Some("fixer"));
+    assert_eq!(
+        commit.message(),
+        Some(
+            format!(
+                "Fixed a\n\n(cherry picked from commit {})\n\
+                 Cherry-picked-by: release <release@example.com>",
+                fix
+            )
+            .as_str()
+        )
+    );
+
+    // onto another branch than the primary branch
+    let master_commit = store
+        .put_latest("e", "e v1", None, None)
+        .expect("put_latest e v1");
+    let staging_head = store
+        .cherry_pick(&master_commit, "staging", None)
+        .expect("cherry_pick onto staging");
+    assert_eq!(
+        repo.refname_to_id("refs/heads/staging")
+            .unwrap()
+            .to_string(),
+        staging_head
+    );
+    assert_eq!(read(&store, &staging_head, "e").as_deref(), Some("e v1"));
+    assert_eq!(read(&store, &staging_head, "c").as_deref(), Some("c v1"));
+    assert_eq!(store.head().expect("head").commit_id, master_commit);
+
+    match store.cherry_pick(&fix.to_string(), "unknown", None) {
+        Err(GitDataStoreError::RevNotFound(branch)) => assert_eq!(branch, "unknown"),
+        other => panic!("expected an unknown branch, got {:?}", other),
+    }
+}
+
+#[test]
+fn cherry_pick_conflict_test() {
+    let tmp_dir = TempDir::new_in(util::TEST_REPOS_DIR).expect("tmp_dir");
+    let tmp_repo_path = tmp_dir.path();
+    clone::init(tmp_repo_path, false).expect("clone::init");
+    let store = GitDataStore::new(&tmp_repo_path.to_string_lossy(), "master");
+    let repo = Repository::open(tmp_repo_path).expect("open");
+
+    let base = store
+        .put_latest("a", "a v1", None, None)
+        .expect("put_latest a v1");
+    create_branch(&repo, "staging", Oid::from_str(&base).unwrap()).expect("create_branch");
+    let fix = commit_on(&repo, "staging", "a", "a fixed");
+    let head = store
+        .put_latest("a", "a v2", None, None)
+        .expect("put_latest a v2");
+
+    match store.cherry_pick(&fix.to_string(), "master", None) {
+        Err(GitDataStoreError::MergeConflicts(conflicts)) => {
+            assert_eq!(conflicts.len(), 1);
+            let blob_id = |commit_id: &str| {
+                repo.find_commit(Oid::from_str(commit_id).unwrap())
+                    .unwrap()
+                    .tree()
+                    .unwrap()
+                    .get_name("a")
+                    .unwrap()
+                    .id()
+                    .to_string()
+            };
+            assert_eq!(conflicts[0].path, "a");
+            assert_eq!(conflicts[0].ancestor_id, Some(blob_id(&base)));
+            assert_eq!(conflicts[0].our_id, Some(blob_id(&head)));
+            assert_eq!(conflicts[0].their_id, Some(blob_id(&fix.to_string())));
+        }
+        other => panic!("expected conflicts, got {:?}", other),
+    }
+    assert_eq!(store.head().expect("head").commit_id, head);
+}
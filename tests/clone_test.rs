@@ -0,0 +1,61 @@
+use nosql_git::{clone, credentials::Credentials, GitDataStore};
+use tempfile::TempDir;
+
+mod util;
+
+#[test]
+fn clone_test() {
+    let remote_dir = TempDir::new_in(util::TEST_REPOS_DIR).expect("remote_dir");
+    clone::init(remote_dir.path(), true).expect("clone::init");
+    let remote_url = format!(
+        "file://{}",
+        remote_dir
+            .path()
+            .canonicalize()
+            .expect("canonicalize")
+            .display()
+    );
+
+    let store_dir = TempDir::new_in(util::TEST_REPOS_DIR).expect("store_dir");
+    let clone_path = store_dir.path().join("clone");
+    clone::clone(&remote_url, &clone_path, false, &Credentials::None).expect("clone");
+
+    // local remotes never ask for credentials, whatever is configured
+    let store = GitDataStore::new(&clone_path.to_string_lossy(), "master")
+        .with_credentials(Credentials::SshAgent);
+    store
+        .put_latest("docs/doc1", "cloned", None, None)
+        .expect("put_latest");
+    store.push("origin", "master").expect("push");
+    store.fetch("origin", "master").expect("fetch");
+
+    // failures are reported as errors naming the url
+    let missing_url = format!("{}-missing", remote_url);
+    match clone::clone(
+        &missing_url,
+        store_dir.path().join("missing"),
+        false,
+        &Credentials::None,
+    ) {
+        Ok(_) => panic!("clone of a missing repository should fail"),
+        Err(err) => assert!(err.to_string().contains(&missing_url)),
+    }
+}
+
+#[test]
+fn credentials_debug_test() {
+    let credentials = Credentials::UserPass {
+        username: "ci".to_string(),
+        password: "s3cr3t".to_string(),
+    };
+    let debug = format!("{:?}", credentials);
+    assert!(debug.contains("ci"));
+    assert!(!debug.contains("s3cr3t"));
+
+    let credentials = Credentials::SshKey {
+        private_key: "id_ed25519".into(),
+        public_key: None,
+        passphrase: Some("s3cr3t".to_string()),
+    };
+    assert!(!format!("{:?}", credentials).contains("s3cr3t"));
+}
@@ -0,0 +1,156 @@
+use chrono::{Duration, TimeZone, Utc};
+use git2::{Oid, Repository, Signature, Time};
+use nosql_git::{clone, compaction::CompactionMode, GitDataStore};
+use tempfile::TempDir;
+
+mod util;
+
+/// Commits `name` with `content` on master at `seconds`.
+fn commit_at(repo: &Repository, name: &str, content: &str, seconds: i64) -> Oid {
+    let head = repo
+        .find_reference("refs/heads/master")
+        .and_then(|head| head.peel_to_commit())
+        .expect("head");
+    let mut builder = repo.treebuilder(Some(&head.tree().unwrap())).unwrap();
+    builder
+        .insert(name, repo.blob(content.as_bytes()).unwrap(), 0o100644)
+        .unwrap();
+    let tree = repo.find_tree(builder.write().unwrap()).unwrap();
+    let signature = Signature::new("writer", "writer@example.com", &Time::new(seconds, 0)).unwrap();
+    repo.commit(
+        Some("refs/heads/master"),
+        &signature,
+        &signature,
+        &format!("Updated {}", name),
+        &tree,
+        &[&head],
+    )
+    .expect("commit")
+}
+
+/// Repository with the initial commit of today, then commits on the next three days.
+fn repository(tmp_dir: &TempDir) -> (GitDataStore, Vec<Oid>, i64) {
+    let tmp_repo_path = tmp_dir.path();
+    clone::init(tmp_repo_path, false).expect("clone::init");
+    let repo = Repository::open(tmp_repo_path).expect("open");
+    let tomorrow = (Utc::now().date() + Duration::days(1))
+        .and_hms(0, 0, 0)
+        .timestamp();
+    let hour = 3600;
+    let day = 24 * hour;
+    let commits = vec![
+        commit_at(&repo, "a", "a1", tomorrow + hour),
+        commit_at(&repo, "b", "b1", tomorrow + 2 * hour),
+        commit_at(&repo, "a", "a2", tomorrow + day + hour),
+        commit_at(&repo, "c", "c1", tomorrow + 2 * day + hour),
+    ];
+    let store = GitDataStore::new(&tmp_repo_path.to_string_lossy(), "master");
+    (store, commits, tomorrow)
+}
+
+fn read(store: &GitDataStore, commit_id: &str, path: &str) -> String {
+    store
+        .read(commit_id, path)
+        .expect("read")
+        .unwrap()
+        .data
+        .file()
+        .unwrap()
+        .to_string()
+}
+
+#[test]
+fn compaction_single_test() {
+    let tmp_dir = TempDir::new_in(util::TEST_REPOS_DIR).expect("tmp_dir");
+    let (store, commits, tomorrow) = repository(&tmp_dir);
+    let cutoff = Utc.timestamp(tomorrow + 36 * 3600, 0);
+
+    let report = store
+        .compact(cutoff, CompactionMode::Single, Some("before-compaction"))
+        .expect("compact");
+    assert_eq!(report.commits_before, 5);
+    assert_eq!(report.commits_after, 2);
+    assert_eq!(store.head().expect("head").commit_id, report.head);
+
+    // the newest commit is replayed on a single commit holding the tree before the cutoff
+    let repo = Repository::open(tmp_dir.path()).expect("open");
+    let head = repo
+        .find_commit(Oid::from_str(&report.head).unwrap())
+        .unwrap();
+    assert_eq!(head.message(), Some("Updated c"));
+    assert_eq!(head.author().name(), Some("writer"));
+    assert_eq!(
+        head.tree_id(),
+        repo.find_commit(commits[3]).unwrap().tree_id()
+    );
+    let squashed = head.parent(0).unwrap();
+    assert_eq!(squashed.parent_count(), 0);
+    assert_eq!(
+        squashed.tree_id(),
+        repo.find_commit(commits[2]).unwrap().tree_id()
+    );
+    assert_eq!(read(&store, &report.head, "a"), "a2");
+    assert_eq!(read(&store, &report.head, "b"), "b1");
+
+    // the previous history is archived
+    assert_eq!(
+        report.archive_ref.as_deref(),
+        Some("refs/archive/before-compaction")
+    );
+    assert_eq!(
+        repo.refname_to_id("refs/archive/before-compaction")
+            .unwrap(),
+        commits[3]
+    );
+    assert_eq!(read(&store, &commits[0].to_string(), "a"), "a1");
+}
+
+#[test]
+fn compaction_daily_test() {
+    let tmp_dir = TempDir::new_in(util::TEST_REPOS_DIR).expect("tmp_dir");
+    let (store, commits, tomorrow) = repository(&tmp_dir);
+    let cutoff = Utc.timestamp(tomorrow + 36 * 3600, 0);
+
+    let report = store
+        .compact(cutoff, CompactionMode::Daily, None)
+        .expect("compact");
+    // the initial commit is alone on its day, the two commits of the next day are squashed
+    assert_eq!(report.commits_before, 5);
+    assert_eq!(report.commits_after, 4);
+    assert_eq!(report.archive_ref, None);
+
+    let repo = Repository::open(tmp_dir.path()).expect("open");
+    let head = repo
+        .find_commit(Oid::from_str(&report.head).unwrap())
+        .unwrap();
+    assert_eq!(
+        head.tree_id(),
+        repo.find_commit(commits[3]).unwrap().tree_id()
+    );
+    let second_day = head.parent(0).unwrap();
+    assert_eq!(
+        second_day.tree_id(),
+        repo.find_commit(commits[2]).unwrap().tree_id()
+    );
+    let first_day = second_day.parent(0).unwrap();
+    assert_eq!(
+        first_day.tree_id(),
+        repo.find_commit(commits[1]).unwrap().tree_id()
+    );
+    assert!(first_day
+        .message()
+        .unwrap()
+        .starts_with("Squashed 2 commits until"));
+    assert_eq!(
+        first_day.parent(0).unwrap().message(),
+        Some("Initial commit")
+    );
+
+    // compacting again has nothing left to squash
+    let again = store
+        .compact(cutoff, CompactionMode::Daily, Some("unused"))
+        .expect("compact");
+    assert_eq!(again.head, report.head);
+    assert_eq!(again.commits_after, 4);
+    assert_eq!(again.archive_ref, None);
+}
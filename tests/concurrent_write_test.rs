@@ -0,0 +1,60 @@
+use nosql_git::{clone, GitDataStore};
+use std::thread;
+use tempfile::TempDir;
+
+mod util;
+
+#[test]
+fn concurrent_write_test() {
+    let tmp_dir = TempDir::new_in(util::TEST_REPOS_DIR).expect("tmp_dir");
+    let tmp_repo_path = tmp_dir.path();
+
+    clone::init(tmp_repo_path, false).expect("clone::init");
+
+    let writers = 4;
+    let docs_per_writer = 10;
+
+    // Each writer has its own store, and therefore its own mutex, just like
+    // separate processes sharing the same repository would.
+    let handles: Vec<_> = (0..writers)
+        .map(|writer| {
+            let repo_path = tmp_repo_path.to_string_lossy().to_string();
+            thread::spawn(move || {
+                let store = GitDataStore::new(&repo_path, "master");
+                for doc in 0..docs_per_writer {
+                    store
+                        .put_latest(
+                            &format!("docs/writer{}/doc{}", writer, doc),
+                            &format!("data {} {}", writer, doc),
+                            None,
+                            None,
+                        )
+                        .expect("put_latest");
+                }
+            })
+        })
+        .collect();
+
+    for handle in handles {
+        handle.join().expect("writer thread");
+    }
+
+    let store = GitDataStore::new(&tmp_repo_path.to_string_lossy(), "master");
+    for writer in 0..writers {
+        for doc in 0..docs_per_writer {
+            let entry = store
+                .read_latest(&format!("docs/writer{}/doc{}", writer, doc))
+                .expect("read_latest")
+                .expect("doc should not have been lost");
+            assert_eq!(
+                entry.data.file().unwrap(),
+                format!("data {} {}", writer, doc)
+            );
+        }
+    }
+
+    let history = store.history().expect("history");
+    let commit_count = history.iter().expect("iter").count();
+    // every write plus the initial commit
+    assert_eq!(commit_count, writers * docs_per_writer + 1);
+}
@@ -0,0 +1,75 @@
+use nosql_git::{clone, metadata::Metadata, GitDataStore};
+use std::collections::BTreeMap;
+use tempfile::TempDir;
+
+mod util;
+
+#[test]
+fn content_type_test() {
+    let tmp_dir = TempDir::new_in(util::TEST_REPOS_DIR).expect("tmp_dir");
+    let tmp_repo_path = tmp_dir.path();
+
+    clone::init(tmp_repo_path, false).expect("clone::init");
+    let store = GitDataStore::new(&tmp_repo_path.to_string_lossy(), "master");
+
+    let mut labels = BTreeMap::new();
+    labels.insert("team".to_string(), "web".to_string());
+    let version = store
+        .put_latest_with_metadata(
+            "site/index.html",
+            "draft",
+            Some(&Metadata {
+                labels: labels.clone(),
+                ..Metadata::default()
+            }),
+            None,
+            None,
+        )
+        .expect("put_latest_with_metadata");
+
+    let html = "<html><body>hello</body></html>";
+    let blob_id = store.write_blob(html.as_bytes()).expect("write_blob");
+    store
+        .put_raw(
+            &version,
+            "site/index.html",
+            &blob_id,
+            Some("text/html"),
+            false,
+            None,
+            None,
+        )
+        .expect("put_raw");
+
+    let blob_reader = store
+        .read_blob_latest("site/index.html")
+        .expect("read_blob_latest")
+        .unwrap();
+    assert_eq!(blob_reader.content_type(), Some("text/html"));
+    // the rest of the metadata is kept
+    assert_eq!(blob_reader.metadata().unwrap().labels, labels);
+
+    // writes without a content type keep the recorded one
+    let blob_id = store
+        .write_blob("a,b\n1,2\n".as_bytes())
+        .expect("write_blob");
+    store
+        .put_raw_latest("data/table.csv", &blob_id, Some("text/csv"), None, None)
+        .expect("put_raw_latest");
+    let blob_id = store
+        .write_blob("a,b\n3,4\n".as_bytes())
+        .expect("write_blob");
+    store
+        .put_raw_latest("data/table.csv", &blob_id, None, None, None)
+        .expect("put_raw_latest");
+
+    let entry = store
+        .read_latest("data/table.csv")
+        .expect("read_latest")
+        .unwrap();
+    assert_eq!(entry.data.file().unwrap(), "a,b\n3,4\n");
+    assert_eq!(
+        entry.metadata.unwrap().content_type.as_deref(),
+        Some("text/csv")
+    );
+}
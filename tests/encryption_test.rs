@@ -0,0 +1,243 @@
+use git2::{ObjectType, Oid, Repository};
+use nosql_git::{
+    clone,
+    encryption::{self, Encryption},
+    large_object::LargeObjectStore,
+    path_glob::PathGlobs,
+    GitDataStore,
+};
+use std::path::Path;
+use tempfile::TempDir;
+
+mod util;
+
+const KEY_1: &str = "key-1 AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8=";
+const KEY_2: &str = "key-2 HxAeHRwbGhkYFxYVFBMSERAPDg0MCwoJCAcGBQQDAgE=";
+
+fn encryption(keyfile: &str) -> Encryption {
+    Encryption::new(PathGlobs::new(&["secrets/**"]).unwrap(), keyfile).expect("encryption")
+}
+
+fn stored_content(repo: &Repository, commit_id: &str, path: &str) -> Vec<u8> {
+    repo.revparse_single(commit_id)
+        .and_then(|obj| obj.peel_to_tree())
+        .and_then(|tree| tree.get_path(Path::new(path)))
+        .and_then(|entry| entry.to_object(repo))
+        .and_then(|obj| obj.peel_to_blob())
+        .expect("blob")
+        .content()
+        .to_vec()
+}
+
+#[test]
+fn encryption_test() {
+    let tmp_dir = TempDir::new_in(util::TEST_REPOS_DIR).expect("tmp_dir");
+    let tmp_repo_path = tmp_dir.path();
+    let repo_path = tmp_repo_path.to_string_lossy();
+    clone::init(tmp_repo_path, false).expect("clone::init");
+    let repo = Repository::open(tmp_repo_path).expect("open");
+
+    let store = GitDataStore::new(&repo_path, "master").with_encryption(encryption(KEY_1));
+    let secret = r#"{"password": "hunter2"}"#;
+    let first = store
+        .put_latest("secrets/db.json", secret, None, None)
+        .expect("put_latest secret");
+    store
+        .put_latest("public/readme", "hello", None, None)
+        .expect("put_latest public");
+
+    // only the documents matching the globs are encrypted in the repository
+    let stored = stored_content(&repo, &first, "secrets/db.json");
+    assert!(!String::from_utf8_lossy(&stored).contains("hunter2"));
+    assert_eq!(encryption::encryption_key(&stored), Some("key-1"));
+    assert_eq!(
+        stored_content(&repo, "master", "public/readme"),
+        b"hello".to_vec()
+    );
+
+    // reads decrypt transparently
+    let entry = store.read_latest("secrets/db.json").expect("read").unwrap();
+    assert_eq!(entry.data.file().unwrap(), secret);
+    let pointer = store
+        .read_pointer_latest("secrets/db.json", "password")
+        .expect("read_pointer")
+        .unwrap();
+    assert_eq!(pointer.value, "hunter2");
+
+    // streamed writes are encrypted before they reach the object database
+    let blob_id = store
+        .write_blob_for("secrets/streamed", "streamed secret".as_bytes())
+        .expect("write_blob_for");
+    let plain_id = Oid::hash_object(ObjectType::Blob, b"streamed secret").unwrap();
+    assert!(repo.find_blob(plain_id).is_err());
+    let streamed = store
+        .put_blob_latest("secrets/streamed", &blob_id, None, None, None)
+        .expect("put_blob_latest");
+    assert!(
+        !String::from_utf8_lossy(&stored_content(&repo, &streamed, "secrets/streamed"))
+            .contains("streamed secret")
+    );
+    let blob_reader = store
+        .read_blob_latest("secrets/streamed")
+        .expect("read_blob")
+        .unwrap();
+    assert_eq!(blob_reader.size(), "streamed secret".len());
+    let content: Vec<Vec<u8>> = blob_reader
+        .chunks(0..blob_reader.size())
+        .expect("chunks")
+        .collect::<Result<_, _>>()
+        .expect("chunk");
+    assert_eq!(content.concat(), b"streamed secret".to_vec());
+    // plain blobs written without the path are encrypted when committed
+    let blob_id = store
+        .write_blob("another secret".as_bytes())
+        .expect("write_blob");
+    let committed = store
+        .put_blob_latest("secrets/another", &blob_id, None, None, None)
+        .expect("put_blob_latest");
+    assert_eq!(
+        encryption::encryption_key(&stored_content(&repo, &committed, "secrets/another")),
+        Some("key-1")
+    );
+
+    // plain documents which look encrypted are read as they are
+    let lookalike = format!("{}key key-1\nnot base64\n", "nosql-git-encrypted v1\n");
+    store
+        .put_latest("public/lookalike", &lookalike, None, None)
+        .expect("put_latest lookalike");
+    assert_eq!(
+        store
+            .read_latest("public/lookalike")
+            .expect("read")
+            .unwrap()
+            .data
+            .file()
+            .unwrap(),
+        lookalike
+    );
+
+    // history and changes still list encrypted paths
+    let changes = store.commit_changes(&first).expect("commit_changes");
+    assert_eq!(changes[0].path, "secrets/db.json");
+
+    // without the keys encrypted documents cannot be read
+    let without_keys = GitDataStore::new(&repo_path, "master");
+    assert!(without_keys.read_latest("secrets/db.json").is_err());
+    assert!(without_keys.read_blob_latest("secrets/streamed").is_err());
+    assert_eq!(
+        without_keys
+            .read_latest("public/readme")
+            .expect("read")
+            .unwrap()
+            .data
+            .file()
+            .unwrap(),
+        "hello"
+    );
+}
+
+#[test]
+fn encryption_rotation_test() {
+    let tmp_dir = TempDir::new_in(util::TEST_REPOS_DIR).expect("tmp_dir");
+    let tmp_repo_path = tmp_dir.path();
+    let repo_path = tmp_repo_path.to_string_lossy();
+    let large_objects_dir = TempDir::new_in(util::TEST_REPOS_DIR).expect("large_objects_dir");
+    clone::init(tmp_repo_path, false).expect("clone::init");
+    let repo = Repository::open(tmp_repo_path).expect("open");
+    let large_objects = LargeObjectStore::new(large_objects_dir.path(), 128);
+
+    let store = GitDataStore::new(&repo_path, "master")
+        .with_large_objects(large_objects.clone())
+        .with_encryption(encryption(KEY_1));
+    let large_secret = "a secret which is larger than the threshold once encrypted";
+    let first = store
+        .put_latest("secrets/small", "small secret", None, None)
+        .expect("put_latest small");
+    store
+        .put_latest("secrets/large", large_secret, None, None)
+        .expect("put_latest large");
+
+    // encrypted content is offloaded to the large object store
+    let entry = store.read_latest("secrets/large").expect("read").unwrap();
+    assert_eq!(entry.data.file().unwrap(), large_secret);
+
+    // a new key encrypts the documents again, older commits stay readable
+    let keyfile = format!("{}\n{}\n", KEY_1, KEY_2);
+    let store = GitDataStore::new(&repo_path, "master")
+        .with_large_objects(large_objects.clone())
+        .with_encryption(encryption(&keyfile));
+    let rotated = store
+        .rotate_encryption(None, None)
+        .expect("rotate_encryption")
+        .expect("rotation commit");
+    assert_eq!(
+        encryption::encryption_key(&stored_content(&repo, &rotated, "secrets/small")),
+        Some("key-2")
+    );
+    assert_eq!(
+        store
+            .read_latest("secrets/large")
+            .expect("read")
+            .unwrap()
+            .data
+            .file()
+            .unwrap(),
+        large_secret
+    );
+    assert_eq!(
+        store
+            .read(&first, "secrets/small")
+            .expect("read")
+            .unwrap()
+            .data
+            .file()
+            .unwrap(),
+        "small secret"
+    );
+    assert_eq!(store.rotate_encryption(None, None).expect("rotate"), None);
+
+    // without the old key, old commits cannot be read
+    let store = GitDataStore::new(&repo_path, "master")
+        .with_large_objects(large_objects)
+        .with_encryption(encryption(KEY_2));
+    assert!(store.read(&first, "secrets/small").is_err());
+    assert!(store.read_latest("secrets/small").is_ok());
+}
+
+#[test]
+fn encryption_tampering_test() {
+    let tmp_dir = TempDir::new_in(util::TEST_REPOS_DIR).expect("tmp_dir");
+    let tmp_repo_path = tmp_dir.path();
+    let repo_path = tmp_repo_path.to_string_lossy();
+    clone::init(tmp_repo_path, false).expect("clone::init");
+    let repo = Repository::open(tmp_repo_path).expect("open");
+
+    let store = GitDataStore::new(&repo_path, "master").with_encryption(encryption(KEY_1));
+    let version = store
+        .put_latest("secrets/a", "secret a", None, None)
+        .expect("put_latest");
+
+    // an encrypted blob moved to another path is rejected
+    let encrypted = stored_content(&repo, &version, "secrets/a");
+    let encryption = encryption(KEY_1);
+    assert!(encryption.decrypt("secrets/b", &encrypted).is_err());
+    assert_eq!(
+        encryption
+            .decrypt("secrets/a", &encrypted)
+            .expect("decrypt"),
+        Some(b"secret a".to_vec())
+    );
+
+    // modified content is rejected
+    let mut tampered = encrypted.clone();
+    let last = tampered.len() - 2;
+    tampered[last] = if tampered[last] == b'A' { b'B' } else { b'A' };
+    assert!(encryption.decrypt("secrets/a", &tampered).is_err());
+}
+
+#[test]
+fn encryption_without_paths_test() {
+    // an empty set of globs would encrypt every document
+    let no_paths: &[&str] = &[];
+    assert!(Encryption::new(PathGlobs::new(no_paths).unwrap(), KEY_1).is_err());
+}
@@ -0,0 +1,205 @@
+use git2::{ObjectType, Oid, Repository};
+use nosql_git::{
+    clone,
+    large_object::{LargeObjectPointer, LargeObjectStore},
+    GitDataStore,
+};
+use std::{
+    fs::File,
+    io::Write,
+    path::Path,
+    time::{Duration, SystemTime},
+};
+use tempfile::TempDir;
+
+mod util;
+
+#[test]
+fn large_object_test() {
+    let tmp_dir = TempDir::new_in(util::TEST_REPOS_DIR).expect("tmp_dir");
+    let tmp_repo_path = tmp_dir.path();
+    let large_objects_dir = TempDir::new_in(util::TEST_REPOS_DIR).expect("large_objects_dir");
+
+    clone::init(tmp_repo_path, false).expect("clone::init");
+    let large_objects = LargeObjectStore::new(large_objects_dir.path(), 16);
+    let store = GitDataStore::new(&tmp_repo_path.to_string_lossy(), "master")
+        .with_large_objects(large_objects.clone());
+
+    let small_data = "small";
+    let large_data = "a document which is larger than the threshold";
+    store
+        .put_latest("docs/small", small_data, None, None)
+        .expect("put_latest small");
+    let version = store
+        .put_latest("docs/large", large_data, None, None)
+        .expect("put_latest large");
+
+    // only a pointer is stored in the repository for the large document
+    let repo = Repository::open(tmp_repo_path).expect("open");
+    let tree = repo
+        .revparse_single(&version)
+        .and_then(|obj| obj.peel_to_tree())
+        .expect("tree");
+    let pointer_blob = tree
+        .get_path(Path::new("docs/large"))
+        .and_then(|entry| entry.to_object(&repo))
+        .expect("docs/large")
+        .peel_to_blob()
+        .expect("blob");
+    let pointer = LargeObjectPointer::parse(pointer_blob.content()).expect("pointer");
+    assert_eq!(pointer.size, large_data.len());
+    let small_blob = tree
+        .get_path(Path::new("docs/small"))
+        .and_then(|entry| entry.to_object(&repo))
+        .expect("docs/small")
+        .peel_to_blob()
+        .expect("blob");
+    assert_eq!(small_blob.content(), small_data.as_bytes());
+
+    // reads resolve the pointer transparently
+    let large_entry = store.read_latest("docs/large").expect("read").unwrap();
+    assert_eq!(large_entry.data.file().unwrap(), large_data);
+    let blob_reader = store
+        .read_blob(&version, "docs/large")
+        .expect("read_blob")
+        .unwrap();
+    assert_eq!(blob_reader.size(), large_data.len());
+    let content: Vec<Vec<u8>> = blob_reader
+        .chunks(0..blob_reader.size())
+        .expect("chunks")
+        .collect::<Result<_, _>>()
+        .expect("chunk");
+    assert_eq!(content.concat(), large_data.as_bytes());
+
+    // streamed writes go straight to the large object store
+    let blob_id = store
+        .write_blob("another large document streamed in".as_bytes())
+        .expect("write_blob");
+    let streamed_id =
+        Oid::hash_object(ObjectType::Blob, b"another large document streamed in").unwrap();
+    assert!(repo.find_blob(streamed_id).is_err());
+    store
+        .put_blob_latest("docs/streamed", &blob_id, None, None, None)
+        .expect("put_blob_latest");
+    let streamed_entry = store.read_latest("docs/streamed").expect("read").unwrap();
+    assert_eq!(
+        streamed_entry.data.file().unwrap(),
+        "another large document streamed in"
+    );
+
+    // only objects no longer referenced by any commit are collected
+    let unreferenced = large_objects
+        .store("never committed large document".as_bytes())
+        .expect("store");
+    let report = store
+        .gc_large_objects(Duration::from_secs(0))
+        .expect("gc_large_objects");
+    assert_eq!(report.referenced, 2);
+    assert_eq!(report.removed, vec![unreferenced.oid.to_string()]);
+    assert!(large_objects.read(&unreferenced).is_err());
+    assert_eq!(
+        store
+            .read_latest("docs/large")
+            .expect("read")
+            .unwrap()
+            .data
+            .file()
+            .unwrap(),
+        large_data
+    );
+}
+
+#[test]
+fn large_object_stored_again_test() {
+    let tmp_dir = TempDir::new_in(util::TEST_REPOS_DIR).expect("tmp_dir");
+    let tmp_repo_path = tmp_dir.path();
+    let large_objects_dir = TempDir::new_in(util::TEST_REPOS_DIR).expect("large_objects_dir");
+    clone::init(tmp_repo_path, false).expect("clone::init");
+    let large_objects = LargeObjectStore::new(large_objects_dir.path(), 16);
+    let store = GitDataStore::new(&tmp_repo_path.to_string_lossy(), "master")
+        .with_large_objects(large_objects.clone());
+
+    let data = "a large document which is uploaded twice";
+    let pointer = large_objects.store(data.as_bytes()).expect("store");
+    let hex = pointer.oid.to_string();
+    let object_path = large_objects_dir.path().join(&hex[..2]).join(&hex[2..]);
+    let day_ago = SystemTime::now() - Duration::from_secs(24 * 3600);
+    File::options()
+        .write(true)
+        .open(&object_path)
+        .and_then(|file| file.set_modified(day_ago))
+        .expect("set_modified");
+
+    // storing the object again starts its grace period over
+    store.write_blob(data.as_bytes()).expect("write_blob");
+    let report = store
+        .gc_large_objects(Duration::from_secs(3600))
+        .expect("gc_large_objects");
+    assert!(report.removed.is_empty());
+    assert!(large_objects.read(&pointer).is_ok());
+}
+
+#[test]
+fn large_object_forged_pointer_test() {
+    let tmp_dir = TempDir::new_in(util::TEST_REPOS_DIR).expect("tmp_dir");
+    let tmp_repo_path = tmp_dir.path();
+    let large_objects_dir = TempDir::new_in(util::TEST_REPOS_DIR).expect("large_objects_dir");
+    clone::init(tmp_repo_path, false).expect("clone::init");
+    let large_objects = LargeObjectStore::new(large_objects_dir.path(), 16);
+    let store = GitDataStore::new(&tmp_repo_path.to_string_lossy(), "master")
+        .with_large_objects(large_objects.clone());
+
+    let secret = "a large secret document somebody else wrote";
+    let pointer = large_objects.store(secret.as_bytes()).expect("store");
+    let forged = pointer.to_document();
+    let read = |path: &str| {
+        store
+            .read_latest(path)
+            .expect("read_latest")
+            .unwrap()
+            .data
+            .file()
+            .unwrap()
+            .to_string()
+    };
+
+    // content which looks like a pointer is stored as it is written
+    store
+        .put_latest("docs/put", &forged, None, None)
+        .expect("put_latest");
+    assert_eq!(read("docs/put"), forged);
+
+    let blob_id = store.write_blob(forged.as_bytes()).expect("write_blob");
+    store
+        .put_blob_latest("docs/streamed", &blob_id, None, None, None)
+        .expect("put_blob_latest");
+    assert_eq!(read("docs/streamed"), forged);
+
+    let upload = store.blob_upload().expect("blob_upload");
+    let mut writer = upload.writer().expect("writer");
+    writer.write_all(forged.as_bytes()).expect("write_all");
+    let blob_id = writer.commit().expect("commit").to_string();
+    store
+        .put_blob_latest("docs/uploaded", &blob_id, None, None, None)
+        .expect("put_blob_latest");
+    assert_eq!(read("docs/uploaded"), forged);
+}
+
+#[test]
+fn large_object_concurrent_store_test() {
+    let large_objects_dir = TempDir::new_in(util::TEST_REPOS_DIR).expect("large_objects_dir");
+    let large_objects = LargeObjectStore::new(large_objects_dir.path(), 16);
+    let data = "a large document which is stored by several threads at once".repeat(1000);
+
+    let threads = (0..8)
+        .map(|_| {
+            let large_objects = large_objects.clone();
+            let data = data.clone();
+            std::thread::spawn(move || large_objects.store(data.as_bytes()))
+        })
+        .collect::<Vec<_>>();
+    for thread in threads {
+        let pointer = thread.join().unwrap().expect("store");
+        assert_eq!(large_objects.read(&pointer).expect("read"), data.as_bytes());
+    }
+}
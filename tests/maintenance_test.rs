@@ -0,0 +1,108 @@
+use git2::{Oid, Repository};
+use nosql_git::{
+    clone,
+    maintenance::{Maintenance, MaintenanceOptions},
+    GitDataStore,
+};
+use std::{fs, time::Duration};
+use tempfile::TempDir;
+
+mod util;
+
+#[test]
+fn maintenance_test() {
+    let tmp_dir = TempDir::new_in(util::TEST_REPOS_DIR).expect("tmp_dir");
+    let tmp_repo_path = tmp_dir.path();
+    clone::init(tmp_repo_path, false).expect("clone::init");
+    let store = GitDataStore::new(&tmp_repo_path.to_string_lossy(), "master");
+
+    for i in 0..5 {
+        store
+            .put_latest(
+                &format!("docs/doc{}", i),
+                &format!("data {}", i),
+                None,
+                None,
+            )
+            .expect("put_latest");
+    }
+    let unreachable = store
+        .write_blob("never committed".as_bytes())
+        .expect("write_blob");
+
+    // unreachable objects are kept during the grace period
+    let report = store
+        .maintain(&MaintenanceOptions::default())
+        .expect("maintain");
+    assert!(report.before.loose_objects > 0);
+    assert_eq!(report.after.loose_objects, 1);
+    assert_eq!(report.after.packs, 1);
+    assert!(report.is_healthy(), "{:?}", report.fsck_problems);
+    let repo = Repository::open(tmp_repo_path).expect("open");
+    assert!(repo.find_blob(Oid::from_str(&unreachable).unwrap()).is_ok());
+
+    // then pruned
+    let report = store
+        .maintain(&MaintenanceOptions {
+            grace_period: Duration::from_secs(0),
+            fsck: true,
+        })
+        .expect("maintain");
+    assert_eq!(report.after.loose_objects, 0);
+    let repo = Repository::open(tmp_repo_path).expect("open");
+    assert!(repo
+        .find_blob(Oid::from_str(&unreachable).unwrap())
+        .is_err());
+    assert_eq!(
+        store
+            .read_latest("docs/doc3")
+            .expect("read")
+            .unwrap()
+            .data
+            .file()
+            .unwrap(),
+        "data 3"
+    );
+}
+
+#[test]
+fn maintenance_status_test() {
+    let tmp_dir = TempDir::new_in(util::TEST_REPOS_DIR).expect("tmp_dir");
+    let tmp_repo_path = tmp_dir.path();
+    clone::init(tmp_repo_path, false).expect("clone::init");
+    let store = GitDataStore::new(&tmp_repo_path.to_string_lossy(), "master");
+    let maintenance = Maintenance::new(MaintenanceOptions::default());
+    assert!(maintenance.status().last_run.is_none());
+
+    maintenance.run(&store).expect("run");
+    let status = maintenance.status();
+    assert!(status.last_run.unwrap().error.is_none());
+    let last_report = status.last_report.expect("last_report");
+    assert!(last_report.is_healthy());
+
+    // a missing object fails the next run, the last report is kept
+    let version = store
+        .put_latest("docs/doc", "data", None, None)
+        .expect("put_latest");
+    let repo = Repository::open(tmp_repo_path).expect("open");
+    let blob_id = repo
+        .revparse_single(&format!("{}:docs/doc", version))
+        .expect("blob")
+        .id()
+        .to_string();
+    fs::remove_file(
+        repo.path()
+            .join("objects")
+            .join(&blob_id[..2])
+            .join(&blob_id[2..]),
+    )
+    .expect("remove blob");
+
+    assert!(maintenance.run(&store).is_err());
+    let status = maintenance.status();
+    assert!(status.last_run.unwrap().error.is_some());
+    assert_eq!(
+        status.last_report.expect("last_report").datetime,
+        last_report.datetime
+    );
+}
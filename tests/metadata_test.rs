@@ -0,0 +1,112 @@
+use nosql_git::{clone, error::GitDataStoreError, metadata::Metadata, GitData, GitDataStore};
+use std::collections::BTreeMap;
+use tempfile::TempDir;
+
+mod util;
+
+#[test]
+fn metadata_test() {
+    let tmp_dir = TempDir::new_in(util::TEST_REPOS_DIR).expect("tmp_dir");
+    let tmp_repo_path = tmp_dir.path();
+
+    clone::init(tmp_repo_path, false).expect("clone::init");
+    let store = GitDataStore::new(&tmp_repo_path.to_string_lossy(), "master");
+
+    let mut labels = BTreeMap::new();
+    labels.insert("team".to_string(), "infra".to_string());
+    let metadata_v1 = Metadata {
+        content_type: Some("application/json".to_string()),
+        creator: Some("alice".to_string()),
+        labels: labels.clone(),
+    };
+    let version_1 = store
+        .put_latest_with_metadata("docs/doc1", "{}", Some(&metadata_v1), None, None)
+        .expect("put_latest_with_metadata");
+    store
+        .put_latest("docs/doc2", "no metadata", None, None)
+        .expect("put_latest doc2");
+
+    let doc1 = store.read_latest("docs/doc1").expect("read").unwrap();
+    assert_eq!(doc1.metadata, Some(metadata_v1.clone()));
+    let doc2 = store.read_latest("docs/doc2").expect("read").unwrap();
+    assert_eq!(doc2.metadata, None);
+
+    // writes without metadata keep the existing metadata
+    store
+        .put_latest("docs/doc1", "{\"a\": 1}", None, None)
+        .expect("put_latest doc1");
+    let doc1 = store.read_latest("docs/doc1").expect("read").unwrap();
+    assert_eq!(doc1.metadata, Some(metadata_v1.clone()));
+
+    // metadata is versioned with the document
+    let mut metadata_v2 = metadata_v1.clone();
+    metadata_v2
+        .labels
+        .insert("team".to_string(), "storage".to_string());
+    store
+        .put_with_metadata(
+            &version_1,
+            "docs/doc1",
+            "{\"a\": 2}",
+            Some(&metadata_v2),
+            true,
+            None,
+            None,
+        )
+        .expect("put_with_metadata");
+    assert_eq!(
+        store
+            .read_latest("docs/doc1")
+            .expect("read")
+            .unwrap()
+            .metadata,
+        Some(metadata_v2)
+    );
+    assert_eq!(
+        store
+            .read(&version_1, "docs/doc1")
+            .expect("read")
+            .unwrap()
+            .metadata,
+        Some(metadata_v1)
+    );
+
+    assert_eq!(
+        store.find_by_labels(&version_1, &labels).expect("find"),
+        vec!["docs/doc1".to_string()]
+    );
+    assert!(store
+        .find_by_labels_latest(&labels)
+        .expect("find")
+        .is_empty());
+
+    // the reserved tree is hidden and cannot be written to
+    let root = store.read_latest("").expect("read root").unwrap();
+    match root.data {
+        GitData::Dir { entries } => {
+            let names: Vec<_> = entries.iter().filter_map(|e| e.name.clone()).collect();
+            assert_eq!(names, vec!["docs".to_string()]);
+        }
+        _ => panic!("root should be a dir"),
+    }
+    assert!(matches!(
+        store.put_latest(".meta/docs/doc2", "{}", None, None),
+        Err(GitDataStoreError::ReservedPath(..))
+    ));
+
+    // deleting a document deletes its metadata
+    store
+        .delete_latest("docs/doc1", None, None)
+        .expect("delete_latest");
+    store
+        .put_latest("docs/doc1", "recreated", None, None)
+        .expect("put_latest");
+    assert_eq!(
+        store
+            .read_latest("docs/doc1")
+            .expect("read")
+            .unwrap()
+            .metadata,
+        None
+    );
+}
@@ -0,0 +1,151 @@
+use nosql_git::{
+    clone,
+    error::GitDataStoreError,
+    patch::{Patch, JSON_PATCH_CONTENT_TYPE, MERGE_PATCH_CONTENT_TYPE},
+    GitDataStore,
+};
+use serde_json::{json, Value};
+use tempfile::TempDir;
+
+mod util;
+
+fn read_json(store: &GitDataStore, path: &str) -> Value {
+    let entry = store.read_latest(path).expect("read_latest").unwrap();
+    serde_json::from_str(entry.data.file().unwrap()).expect("json")
+}
+
+#[test]
+fn patch_test() {
+    let tmp_dir = TempDir::new_in(util::TEST_REPOS_DIR).expect("tmp_dir");
+    let tmp_repo_path = tmp_dir.path();
+
+    clone::init(tmp_repo_path, false).expect("clone::init");
+    let store = GitDataStore::new(&tmp_repo_path.to_string_lossy(), "master");
+
+    let doc_path = "apps/app1.json";
+    let version = store
+        .put_latest(
+            doc_path,
+            r#"{"spec": {"replicas": 1, "image": "app:1"}, "owner": "alice"}"#,
+            None,
+            None,
+        )
+        .expect("put_latest");
+
+    let json_patch = Patch::parse(
+        JSON_PATCH_CONTENT_TYPE,
+        br#"[
+            {"op": "test", "path": "/spec/replicas", "value": 1},
+            {"op": "replace", "path": "/spec/replicas", "value": 3}
+        ]"#,
+    )
+    .expect("parse json patch");
+    let patched = store
+        .patch(&version, doc_path, &json_patch, false, None, None)
+        .expect("patch");
+    assert_eq!(
+        read_json(&store, doc_path),
+        json!({"spec": {"replicas": 3, "image": "app:1"}, "owner": "alice"})
+    );
+
+    // the test operation fails now that replicas is 3
+    assert!(matches!(
+        store.patch_latest(doc_path, &json_patch, None, None),
+        Err(GitDataStoreError::PatchFailed { .. })
+    ));
+    // the document changed since `version`
+    let merge_patch = Patch::parse(
+        &format!("{}; charset=utf-8", MERGE_PATCH_CONTENT_TYPE),
+        br#"{"spec": {"image": "app:2"}, "owner": null}"#,
+    )
+    .expect("parse merge patch");
+    assert!(matches!(
+        store.patch(&version, doc_path, &merge_patch, false, None, None),
+        Err(GitDataStoreError::ConflictOnWrite { .. })
+    ));
+
+    store
+        .patch(&patched, doc_path, &merge_patch, false, None, None)
+        .expect("merge patch");
+    assert_eq!(
+        read_json(&store, doc_path),
+        json!({"spec": {"replicas": 3, "image": "app:2"}})
+    );
+
+    // missing documents are patched as null
+    store
+        .patch_latest("apps/app2.json", &merge_patch, None, None)
+        .expect("merge patch missing document");
+    assert_eq!(
+        read_json(&store, "apps/app2.json"),
+        json!({"spec": {"image": "app:2"}})
+    );
+
+    store
+        .put_latest("docs/text", "not json", None, None)
+        .expect("put_latest");
+    assert!(matches!(
+        store.patch_latest("docs/text", &merge_patch, None, None),
+        Err(GitDataStoreError::PatchFailed { .. })
+    ));
+    assert!(matches!(
+        Patch::parse("application/json", b"{}"),
+        Err(GitDataStoreError::UnsupportedPatch(_))
+    ));
+    assert!(matches!(
+        Patch::parse(JSON_PATCH_CONTENT_TYPE, b"{}"),
+        Err(GitDataStoreError::RequestBody(_))
+    ));
+}
+
+#[test]
+fn patch_formatting_test() {
+    let tmp_dir = TempDir::new_in(util::TEST_REPOS_DIR).expect("tmp_dir");
+    let tmp_repo_path = tmp_dir.path();
+
+    clone::init(tmp_repo_path, false).expect("clone::init");
+    let store = GitDataStore::new(&tmp_repo_path.to_string_lossy(), "master");
+    let read = |path: &str| {
+        store
+            .read_latest(path)
+            .expect("read_latest")
+            .unwrap()
+            .data
+            .file()
+            .unwrap()
+            .to_string()
+    };
+    let merge_patch =
+        Patch::parse(MERGE_PATCH_CONTENT_TYPE, br#"{"replicas": 3}"#).expect("parse merge patch");
+
+    // the fields keep their order and indentation
+    store
+        .put_latest(
+            "apps/app1.json",
+            "{\n    \"replicas\": 1,\n    \"image\": \"app:1\"\n}\n",
+            None,
+            None,
+        )
+        .expect("put_latest");
+    store
+        .patch_latest("apps/app1.json", &merge_patch, None, None)
+        .expect("patch_latest");
+    assert_eq!(
+        read("apps/app1.json"),
+        "{\n    \"replicas\": 3,\n    \"image\": \"app:1\"\n}\n"
+    );
+
+    // documents on one line stay on one line
+    store
+        .put_latest(
+            "apps/app2.json",
+            r#"{"replicas":1,"image":"app:1"}"#,
+            None,
+            None,
+        )
+        .expect("put_latest");
+    store
+        .patch_latest("apps/app2.json", &merge_patch, None, None)
+        .expect("patch_latest");
+    assert_eq!(read("apps/app2.json"), r#"{"replicas":3,"image":"app:1"}"#);
+}
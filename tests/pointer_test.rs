@@ -0,0 +1,72 @@
+use nosql_git::{clone, error::GitDataStoreError, pointer::to_json_pointer, GitDataStore};
+use serde_json::json;
+use tempfile::TempDir;
+
+mod util;
+
+#[test]
+fn pointer_test() {
+    let tmp_dir = TempDir::new_in(util::TEST_REPOS_DIR).expect("tmp_dir");
+    let tmp_repo_path = tmp_dir.path();
+
+    clone::init(tmp_repo_path, false).expect("clone::init");
+    let store = GitDataStore::new(&tmp_repo_path.to_string_lossy(), "master");
+
+    assert_eq!(to_json_pointer("/spec/replicas"), "/spec/replicas");
+    assert_eq!(to_json_pointer("spec.replicas"), "/spec/replicas");
+    assert_eq!(to_json_pointer("a/b.c~d"), "/a~1b/c~0d");
+    assert_eq!(to_json_pointer(""), "");
+
+    let doc_path = "apps/app1.json";
+    let v1 = store
+        .put_latest(
+            doc_path,
+            r#"{"spec": {"replicas": 1, "ports": [80, 443]}}"#,
+            None,
+            None,
+        )
+        .expect("put_latest");
+    store
+        .put_latest(doc_path, r#"{"spec": {"replicas": 3}}"#, None, None)
+        .expect("put_latest");
+
+    let latest = store
+        .read_pointer_latest(doc_path, "/spec/replicas")
+        .expect("read_pointer_latest")
+        .expect("value at pointer");
+    assert_eq!(latest.value, json!(3));
+
+    let at_v1 = store
+        .read_pointer(&v1, doc_path, "spec.ports.1")
+        .expect("read_pointer")
+        .expect("value at pointer");
+    assert_eq!(at_v1.value, json!(443));
+    assert_eq!(at_v1.commit_id, v1);
+
+    let whole = store
+        .read_pointer(&v1, doc_path, "")
+        .expect("read_pointer")
+        .expect("whole document");
+    assert_eq!(
+        whole.value,
+        json!({"spec": {"replicas": 1, "ports": [80, 443]}})
+    );
+
+    // missing values and documents
+    assert!(store
+        .read_pointer_latest(doc_path, "/spec/ports")
+        .expect("read_pointer_latest")
+        .is_none());
+    assert!(store
+        .read_pointer_latest("apps/missing.json", "/spec")
+        .expect("read_pointer_latest")
+        .is_none());
+
+    store
+        .put_latest("notes.txt", "not json", None, None)
+        .expect("put_latest");
+    match store.read_pointer_latest("notes.txt", "/a") {
+        Err(GitDataStoreError::NotJson { path, .. }) => assert_eq!(path, "notes.txt"),
+        other => panic!("expected NotJson, got {:?}", other.map(|_| ())),
+    }
+}
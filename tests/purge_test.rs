@@ -0,0 +1,157 @@
+use git2::Repository;
+use nosql_git::{
+    clone, large_object::LargeObjectStore, metadata::Metadata, purge::PurgeTarget, GitDataStore,
+};
+use std::path::Path;
+use tempfile::TempDir;
+
+mod util;
+
+fn blob_id(repo_path: &Path, commit_id: &str, path: &str) -> git2::Oid {
+    let repo = Repository::open(repo_path).expect("open");
+    let tree = repo
+        .revparse_single(commit_id)
+        .and_then(|obj| obj.peel_to_tree())
+        .expect("tree");
+    let id = tree.get_path(Path::new(path)).expect(path).id();
+    id
+}
+
+#[test]
+fn purge_path_test() {
+    let tmp_dir = TempDir::new_in(util::TEST_REPOS_DIR).expect("tmp_dir");
+    let tmp_repo_path = tmp_dir.path();
+    let large_objects_dir = TempDir::new_in(util::TEST_REPOS_DIR).expect("large_objects_dir");
+    clone::init(tmp_repo_path, false).expect("clone::init");
+    let large_objects = LargeObjectStore::new(large_objects_dir.path(), 32);
+    let store = GitDataStore::new(&tmp_repo_path.to_string_lossy(), "master")
+        .with_large_objects(large_objects.clone());
+
+    let keep_v1 = store
+        .put_latest("docs/keep", "keep v1", None, None)
+        .expect("put_latest keep v1");
+    let metadata = Metadata {
+        creator: Some("alice".to_string()),
+        ..Metadata::default()
+    };
+    let pii_v1 = store
+        .put_latest_with_metadata(
+            "users/alice",
+            "alice@example.com",
+            Some(&metadata),
+            None,
+            None,
+        )
+        .expect("put_latest pii v1");
+    let pii_blob = blob_id(tmp_repo_path, &pii_v1, "users/alice");
+    let keep_v2 = store
+        .put_latest("docs/keep", "keep v2", None, None)
+        .expect("put_latest keep v2");
+    let pii_v2 = store
+        .put_latest(
+            "users/alice",
+            "alice@example.com, a document larger than the threshold",
+            None,
+            None,
+        )
+        .expect("put_latest pii v2");
+    let large_pointer = blob_id(tmp_repo_path, &pii_v2, "users/alice");
+    let deleted = store
+        .delete_latest("users/alice", None, None)
+        .expect("delete_latest");
+    // a blob uploaded for a write which is not committed yet
+    let pending = Repository::open(tmp_repo_path)
+        .and_then(|repo| repo.blob(b"pending upload"))
+        .expect("blob");
+    // a branch the purge does not rewrite
+    {
+        let repo = Repository::open(tmp_repo_path).expect("open");
+        let commit = repo
+            .find_commit(git2::Oid::from_str(&keep_v1).unwrap())
+            .expect("commit");
+        repo.branch("other", &commit, false).expect("branch");
+    }
+
+    let report = store
+        .purge(PurgeTarget::Path("users/alice".to_string()))
+        .expect("purge");
+
+    // commits which only touched the document are dropped, the others are rewritten
+    assert_eq!(report.dropped, vec![pii_v1.clone(), pii_v2, deleted]);
+    assert!(!report.commits.contains_key(&keep_v1));
+    let new_keep_v2 = report.commits.get(&keep_v2).expect("keep v2 rewritten");
+    assert_eq!(report.branches.get("refs/heads/master"), Some(new_keep_v2));
+    assert_eq!(store.head().expect("head").commit_id, *new_keep_v2);
+    assert_eq!(
+        store
+            .read(new_keep_v2, "docs/keep")
+            .expect("read")
+            .unwrap()
+            .data
+            .file()
+            .unwrap(),
+        "keep v2"
+    );
+    assert!(store.read(&pii_v1, "users/alice").is_err());
+
+    // no commit holds the document or its metadata anymore
+    let history = store.history().expect("history");
+    for entry in history.iter().expect("iter") {
+        let commit_id = entry.expect("entry").commit_id;
+        assert!(store
+            .read(&commit_id, "users/alice")
+            .expect("read")
+            .is_none());
+        assert!(store
+            .read(&commit_id, ".meta/users/alice")
+            .expect("read")
+            .is_none());
+    }
+
+    // the content is pruned from the object database and the large object store
+    let repo = Repository::open(tmp_repo_path).expect("open");
+    assert!(repo.find_blob(pii_blob).is_err());
+    assert!(repo.find_blob(large_pointer).is_err());
+    assert_eq!(report.removed_large_objects.len(), 1);
+    // other unreachable objects are left to the maintenance
+    assert!(repo.find_blob(pending).is_ok());
+    // only the reflogs of the rewritten references are expired
+    assert_eq!(repo.reflog("refs/heads/master").expect("reflog").len(), 0);
+    assert_eq!(repo.reflog("refs/heads/other").expect("reflog").len(), 1);
+}
+
+#[test]
+fn purge_blob_test() {
+    let tmp_dir = TempDir::new_in(util::TEST_REPOS_DIR).expect("tmp_dir");
+    let tmp_repo_path = tmp_dir.path();
+    clone::init(tmp_repo_path, false).expect("clone::init");
+    let store = GitDataStore::new(&tmp_repo_path.to_string_lossy(), "master");
+
+    store
+        .put_latest("a/secret", "leaked token", None, None)
+        .expect("put_latest a");
+    let both = store
+        .put_latest("b/copy", "leaked token", None, None)
+        .expect("put_latest b");
+    store
+        .put_latest("b/other", "other", None, None)
+        .expect("put_latest other");
+    let leaked = blob_id(tmp_repo_path, &both, "a/secret");
+
+    let report = store.purge(PurgeTarget::Blob(leaked)).expect("purge");
+
+    // both copies are removed, along with the directory left empty
+    assert_eq!(report.dropped.len(), 2);
+    assert!(store.read_latest("a/secret").expect("read").is_none());
+    assert!(store.read_latest("a").expect("read").is_none());
+    assert!(store.read_latest("b/copy").expect("read").is_none());
+    assert!(store.read_latest("b/other").expect("read").is_some());
+    let repo = Repository::open(tmp_repo_path).expect("open");
+    assert!(repo.find_blob(leaked).is_err());
+
+    // nothing left to purge leaves the history untouched
+    let head = store.head().expect("head").commit_id;
+    let report = store.purge(PurgeTarget::Blob(leaked)).expect("purge");
+    assert!(report.commits.is_empty());
+    assert_eq!(store.head().expect("head").commit_id, head);
+}
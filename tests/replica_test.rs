@@ -0,0 +1,57 @@
+use git2::Repository;
+use nosql_git::{clone, replica::Replica, GitDataStore};
+use tempfile::TempDir;
+
+mod util;
+
+#[test]
+fn replica_test() {
+    let upstream_dir = TempDir::new_in(util::TEST_REPOS_DIR).expect("upstream_dir");
+    clone::init(upstream_dir.path(), false).expect("clone::init");
+    let upstream_path = upstream_dir.path().to_string_lossy().to_string();
+    let upstream = GitDataStore::new(&upstream_path, "master");
+
+    let replica_dir = TempDir::new_in(util::TEST_REPOS_DIR).expect("replica_dir");
+    Repository::clone(&upstream_path, replica_dir.path()).expect("clone");
+    let store = GitDataStore::new(&replica_dir.path().to_string_lossy(), "master");
+    let replica = Replica::new("origin", "master");
+
+    let version = upstream
+        .put_latest("docs/doc1", "v1", None, None)
+        .expect("put_latest");
+    // local commits are discarded by the next fetch
+    store
+        .put_latest("docs/local", "local", None, None)
+        .expect("put_latest local");
+
+    assert_eq!(replica.fetch(&store).expect("fetch"), version);
+    assert_eq!(
+        store
+            .read_latest("docs/doc1")
+            .expect("read_latest")
+            .unwrap()
+            .data
+            .file(),
+        Some("v1")
+    );
+    assert!(store
+        .read_latest("docs/local")
+        .expect("read_latest")
+        .is_none());
+
+    let status = replica.status(&store).expect("status");
+    assert_eq!(status.commit_id, version);
+    assert!(status.commit_age_secs >= 0);
+    assert!(status.last_success.is_some());
+    assert!(status.last_fetch.expect("last_fetch").error.is_none());
+
+    // failed fetches are reported without losing the last successful one
+    store
+        .set_remote("origin", &format!("{}-missing", upstream_path))
+        .expect("set_remote");
+    assert!(replica.fetch(&store).is_err());
+    let status = replica.status(&store).expect("status");
+    assert_eq!(status.commit_id, version);
+    assert!(status.last_success.is_some());
+    assert!(status.last_fetch.expect("last_fetch").error.is_some());
+}
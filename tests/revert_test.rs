@@ -0,0 +1,155 @@
+use nosql_git::{
+    changes::ChangeStatus, clone, error::GitDataStoreError, metadata::Metadata, GitDataStore,
+};
+use tempfile::TempDir;
+
+mod util;
+
+fn read(store: &GitDataStore, path: &str) -> Option<String> {
+    store
+        .read_latest(path)
+        .expect("read_latest")
+        .map(|entry| entry.data.file().unwrap().to_string())
+}
+
+#[test]
+fn revert_test() {
+    let tmp_dir = TempDir::new_in(util::TEST_REPOS_DIR).expect("tmp_dir");
+    let tmp_repo_path = tmp_dir.path();
+    clone::init(tmp_repo_path, false).expect("clone::init");
+    let store = GitDataStore::new(&tmp_repo_path.to_string_lossy(), "master");
+
+    store
+        .put_latest("a", "a v1", None, None)
+        .expect("put_latest a v1");
+    let bad = store
+        .put_latest("a", "a v2", None, Some("Bad write"))
+        .expect("put_latest a v2");
+    store
+        .put_latest("b", "b v1", None, None)
+        .expect("put_latest b v1");
+
+    // the later change of another document is kept
+    let revert_id = store.revert(&bad, None, None).expect("revert");
+    assert_eq!(store.head().expect("head").commit_id, revert_id);
+    assert_eq!(read(&store, "a").as_deref(), Some("a v1"));
+    assert_eq!(read(&store, "b").as_deref(), Some("b v1"));
+    let head = store.head().expect("head");
+    assert_eq!(
+        head.message,
+        Some(format!(
+            "Revert \"Bad write\"\n\nThis reverts commit {}.",
+            bad
+        ))
+    );
+
+    // reverting a commit which added a document deletes it
+    let added = store
+        .put_latest("c", "c v1", None, None)
+        .expect("put_latest c v1");
+    store
+        .revert(&added, None, Some("Removed c"))
+        .expect("revert added");
+    assert_eq!(read(&store, "c"), None);
+    assert_eq!(read(&store, "b").as_deref(), Some("b v1"));
+
+    // a document changed again since conflicts
+    let changed = store
+        .put_latest("b", "b v2", None, None)
+        .expect("put_latest b v2");
+    store
+        .put_latest("b", "b v3", None, None)
+        .expect("put_latest b v3");
+    match store.revert(&changed, None, None) {
+        Err(GitDataStoreError::MergeConflicts(conflicts)) => {
+            assert_eq!(conflicts.len(), 1);
+            assert_eq!(conflicts[0].path, "b");
+            assert!(conflicts[0].ancestor_id.is_some());
+            assert!(conflicts[0].our_id.is_some());
+            assert!(conflicts[0].their_id.is_some());
+        }
+        other => panic!("expected conflicts, got {:?}", other),
+    }
+    assert_eq!(read(&store, "b").as_deref(), Some("b v3"));
+
+    match store.revert("unknown", None, None) {
+        Err(GitDataStoreError::RevNotFound(_)) => {}
+        other => panic!("expected an unknown commit, got {:?}", other),
+    }
+}
+
+#[test]
+fn restore_test() {
+    let tmp_dir = TempDir::new_in(util::TEST_REPOS_DIR).expect("tmp_dir");
+    let tmp_repo_path = tmp_dir.path();
+    clone::init(tmp_repo_path, false).expect("clone::init");
+    let store = GitDataStore::new(&tmp_repo_path.to_string_lossy(), "master");
+
+    let metadata = Metadata {
+        creator: Some("alice".to_string()),
+        ..Metadata::default()
+    };
+    store
+        .put_latest_with_metadata("docs/a", "a v1", Some(&metadata), None, None)
+        .expect("put_latest docs/a v1");
+    let v1 = store
+        .put_latest("docs/b", "b v1", None, None)
+        .expect("put_latest docs/b v1");
+    store
+        .put_latest_with_metadata("docs/a", "a v2", Some(&Metadata::default()), None, None)
+        .expect("put_latest docs/a v2");
+    store
+        .put_latest("docs/c", "c v1", None, None)
+        .expect("put_latest docs/c v1");
+    store
+        .put_latest("other", "other v1", None, None)
+        .expect("put_latest other");
+
+    // a document is restored with its metadata
+    store
+        .restore("docs/a", &v1, None, None)
+        .expect("restore docs/a");
+    let entry = store.read_latest("docs/a").expect("read_latest").unwrap();
+    assert_eq!(entry.data.file(), Some("a v1"));
+    assert_eq!(entry.metadata, Some(metadata));
+    assert_eq!(read(&store, "docs/c").as_deref(), Some("c v1"));
+    let head = store.head().expect("head");
+    assert_eq!(head.message, Some(format!("Restored docs/a from {}", v1)));
+
+    // a directory is restored as a whole
+    store
+        .put_latest("docs/b", "b v2", None, None)
+        .expect("put_latest docs/b v2");
+    let mut changes = store
+        .restore_changes("docs", &v1)
+        .expect("restore_changes")
+        .into_iter()
+        .map(|change| (change.path, change.status))
+        .collect::<Vec<_>>();
+    changes.sort_by(|a, b| a.0.cmp(&b.0));
+    assert_eq!(
+        changes,
+        vec![
+            ("docs/b".to_string(), ChangeStatus::Modified),
+            ("docs/c".to_string(), ChangeStatus::Deleted),
+        ]
+    );
+    store
+        .restore("docs", &v1, None, Some("Restored docs"))
+        .expect("restore docs");
+    assert_eq!(read(&store, "docs/a").as_deref(), Some("a v1"));
+    assert_eq!(read(&store, "docs/b").as_deref(), Some("b v1"));
+    assert_eq!(read(&store, "docs/c"), None);
+    assert_eq!(read(&store, "other").as_deref(), Some("other v1"));
+
+    // a path which did not exist is deleted
+    store
+        .restore("other", &v1, None, None)
+        .expect("restore other");
+    assert_eq!(read(&store, "other"), None);
+
+    match store.restore(".meta/docs/a", &v1, None, None) {
+        Err(GitDataStoreError::ReservedPath(_)) => {}
+        other => panic!("expected a reserved path, got {:?}", other),
+    }
+}
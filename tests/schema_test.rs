@@ -0,0 +1,162 @@
+use nosql_git::{
+    clone,
+    encryption::Encryption,
+    error::GitDataStoreError,
+    large_object::LargeObjectStore,
+    path_glob::PathGlobs,
+    schema::{SchemaEntry, SchemaRegistryValidator},
+    validation::ValidationPipeline,
+    GitDataStore,
+};
+use tempfile::TempDir;
+
+mod util;
+
+fn failure_messages(result: Result<String, GitDataStoreError>) -> Vec<String> {
+    match result {
+        Err(GitDataStoreError::ValidationFailed(failures)) => failures
+            .into_iter()
+            .map(|failure| format!("{} {}", failure.path, failure.message))
+            .collect(),
+        result => panic!("expected ValidationFailed, got {:?}", result),
+    }
+}
+
+#[test]
+fn schema_test() {
+    let tmp_dir = TempDir::new_in(util::TEST_REPOS_DIR).expect("tmp_dir");
+    let tmp_repo_path = tmp_dir.path();
+
+    clone::init(tmp_repo_path, false).expect("clone::init");
+    let store = GitDataStore::new(&tmp_repo_path.to_string_lossy(), "master")
+        .with_validation(ValidationPipeline::new().with(SchemaRegistryValidator));
+
+    store
+        .put_latest("users/alice.json", r#"{"name": "alice"}"#, None, None)
+        .expect("documents are not checked without schemas");
+    let user_schema = r#"{
+        "x-paths": ["users/*.json"],
+        "type": "object",
+        "required": ["name"],
+        "properties": { "name": { "type": "string" } }
+    }"#;
+    let version = store
+        .put_latest(".schemas/user.json", user_schema, None, None)
+        .expect("put schema");
+
+    assert_eq!(
+        store
+            .schemas_for_latest("users/bob.json")
+            .expect("schemas_for_latest"),
+        vec![SchemaEntry {
+            path: ".schemas/user.json".to_string(),
+            paths: vec!["users/*.json".to_string()],
+            error: None,
+        }]
+    );
+    assert!(store
+        .schemas_for_latest("other/bob.json")
+        .expect("schemas_for_latest")
+        .is_empty());
+
+    let messages =
+        failure_messages(store.put_latest("users/bob.json", r#"{"age": 1}"#, None, None));
+    assert_eq!(messages.len(), 1);
+    assert!(messages[0].starts_with("users/bob.json .schemas/user.json:"));
+    store
+        .put_latest("users/bob.json", r#"{"name": "bob"}"#, None, None)
+        .expect("valid document");
+
+    // a schema change is validated against the documents it already covers
+    let stricter_schema = r#"{
+        "x-paths": ["users/*.json"],
+        "type": "object",
+        "required": ["name", "email"]
+    }"#;
+    let messages =
+        failure_messages(store.put_latest(".schemas/user.json", stricter_schema, None, None));
+    assert_eq!(messages.len(), 1);
+    assert!(messages[0].contains("users/alice.json"));
+    assert!(messages[0].contains("users/bob.json"));
+
+    let messages = failure_messages(store.put_latest(
+        ".schemas/broken.json",
+        r#"{"type": "object"}"#,
+        None,
+        None,
+    ));
+    assert!(messages[0].contains("x-paths"));
+
+    // schemas are versioned with the documents
+    store
+        .delete_latest(".schemas/user.json", None, None)
+        .expect("delete schema");
+    assert!(store
+        .schemas_for_latest("users/bob.json")
+        .expect("schemas_for_latest")
+        .is_empty());
+    assert_eq!(
+        store
+            .schemas_for(&version, "users/bob.json")
+            .expect("schemas_for")
+            .len(),
+        1
+    );
+    store
+        .put_latest("users/carol.json", r#"{"age": 1}"#, None, None)
+        .expect("no schema applies anymore");
+}
+
+#[test]
+fn schema_listing_test() {
+    let tmp_dir = TempDir::new_in(util::TEST_REPOS_DIR).expect("tmp_dir");
+    let tmp_repo_path = tmp_dir.path();
+    let large_objects_dir = TempDir::new_in(util::TEST_REPOS_DIR).expect("large_objects_dir");
+
+    clone::init(tmp_repo_path, false).expect("clone::init");
+    let encryption = Encryption::new(
+        PathGlobs::new(&[".schemas/secret.json"]).unwrap(),
+        "key-1 AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8=",
+    )
+    .expect("encryption");
+    let store = GitDataStore::new(&tmp_repo_path.to_string_lossy(), "master")
+        .with_large_objects(LargeObjectStore::new(large_objects_dir.path(), 64))
+        .with_encryption(encryption);
+
+    // schemas are read like any other document
+    let large_schema = format!(
+        r#"{{"x-paths": ["users/*.json"], "description": "{}"}}"#,
+        "a large schema ".repeat(10)
+    );
+    store
+        .put_latest(".schemas/large.json", &large_schema, None, None)
+        .expect("put large schema");
+    store
+        .put_latest(
+            ".schemas/secret.json",
+            r#"{"x-paths": ["users/*.json"]}"#,
+            None,
+            None,
+        )
+        .expect("put encrypted schema");
+    // without validation an invalid schema can be committed
+    store
+        .put_latest(".schemas/broken.json", "not json", None, None)
+        .expect("put broken schema");
+
+    let schemas = store
+        .schemas_for_latest("users/bob.json")
+        .expect("schemas_for_latest");
+    assert_eq!(
+        schemas
+            .iter()
+            .map(|schema| (schema.path.as_str(), schema.error.is_some()))
+            .collect::<Vec<_>>(),
+        vec![
+            (".schemas/broken.json", true),
+            (".schemas/large.json", false),
+            (".schemas/secret.json", false),
+        ]
+    );
+    assert!(schemas[0].paths.is_empty());
+}
@@ -0,0 +1,132 @@
+use nosql_git::{
+    clone,
+    signing::{CommitSigner, CommitVerifier, SignatureFormat, SignatureStatus},
+    GitDataStore,
+};
+use std::{fs, process::Command};
+use tempfile::TempDir;
+
+mod util;
+
+/// Signing relies on the programs git uses, which must be installed to run the tests.
+fn require_program(program: &str) {
+    assert!(
+        Command::new(program).arg("--version").output().is_ok(),
+        "{} is required to run the signing tests",
+        program
+    );
+}
+
+fn run(command: &mut Command) {
+    let output = command.output().expect("run");
+    assert!(output.status.success(), "{:?}", output);
+}
+
+#[test]
+fn ssh_signing_test() {
+    require_program("ssh-keygen");
+    let tmp_dir = TempDir::new_in(util::TEST_REPOS_DIR).expect("tmp_dir");
+    let tmp_repo_path = tmp_dir.path().join("repo");
+    let key_dir = TempDir::new().expect("key_dir");
+    let key = key_dir.path().join("id_ed25519");
+    run(Command::new("ssh-keygen")
+        .args(["-q", "-t", "ed25519", "-N", ""])
+        .arg("-f")
+        .arg(&key));
+    let allowed_signers = key_dir.path().join("allowed_signers");
+    let public_key = fs::read_to_string(key.with_extension("pub")).expect("public key");
+    fs::write(
+        &allowed_signers,
+        format!("server@example.com {}", public_key),
+    )
+    .expect("allowed_signers");
+
+    clone::init(&tmp_repo_path, false).expect("clone::init");
+    let unsigned_id = GitDataStore::new(&tmp_repo_path.to_string_lossy(), "master")
+        .put_latest("doc1", "v1", None, None)
+        .expect("put_latest");
+
+    let store = GitDataStore::new(&tmp_repo_path.to_string_lossy(), "master")
+        .with_signer(CommitSigner::new(
+            SignatureFormat::Ssh,
+            &key.to_string_lossy(),
+        ))
+        .with_verifier(CommitVerifier::new(Some(allowed_signers)));
+    let signed_id = store
+        .put_latest("doc1", "v2", None, None)
+        .expect("put_latest");
+
+    assert_eq!(
+        store.verify(&unsigned_id).unwrap(),
+        SignatureStatus::Unsigned
+    );
+    assert_eq!(
+        store.verify(&signed_id).unwrap(),
+        SignatureStatus::Good {
+            format: SignatureFormat::Ssh,
+            signer: "server@example.com".to_string()
+        }
+    );
+    // the signed commit is a regular commit of the branch
+    assert_eq!(store.head().unwrap().commit_id, signed_id);
+    assert_eq!(
+        store.read_latest("doc1").unwrap().unwrap().data.file(),
+        Some("v2")
+    );
+
+    // keys which are not allowed are reported
+    let untrusting = GitDataStore::new(&tmp_repo_path.to_string_lossy(), "master")
+        .with_verifier(CommitVerifier::new(Some(key_dir.path().join("missing"))));
+    assert!(matches!(
+        untrusting.verify(&signed_id).unwrap(),
+        SignatureStatus::Bad {
+            format: Some(SignatureFormat::Ssh),
+            ..
+        }
+    ));
+}
+
+#[test]
+fn openpgp_signing_test() {
+    require_program("gpg");
+    let tmp_dir = TempDir::new_in(util::TEST_REPOS_DIR).expect("tmp_dir");
+    let tmp_repo_path = tmp_dir.path().join("repo");
+    // short path, gpg-agent sockets are created in the home directory
+    let gnupg_home = TempDir::new_in("/tmp").expect("gnupg_home");
+    run(Command::new("gpg")
+        .env("GNUPGHOME", gnupg_home.path())
+        .args([
+            "--batch",
+            "--passphrase",
+            "",
+            "--quick-gen-key",
+            "Server <server@example.com>",
+            "ed25519",
+            "sign",
+            "never",
+        ]));
+
+    clone::init(&tmp_repo_path, false).expect("clone::init");
+    let store = GitDataStore::new(&tmp_repo_path.to_string_lossy(), "master")
+        .with_signer(
+            CommitSigner::new(SignatureFormat::OpenPgp, "server@example.com")
+                .with_gnupg_home(gnupg_home.path()),
+        )
+        .with_verifier(CommitVerifier::default().with_gnupg_home(gnupg_home.path()));
+    let signed_id = store
+        .put_latest("doc1", "v1", None, None)
+        .expect("put_latest");
+
+    assert_eq!(
+        store.verify(&signed_id).unwrap(),
+        SignatureStatus::Good {
+            format: SignatureFormat::OpenPgp,
+            signer: "Server <server@example.com>".to_string()
+        }
+    );
+
+    let _ = Command::new("gpgconf")
+        .env("GNUPGHOME", gnupg_home.path())
+        .args(["--kill", "gpg-agent"])
+        .output();
+}
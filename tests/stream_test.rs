@@ -0,0 +1,123 @@
+use nosql_git::{blob::CHUNK_SIZE, clone, maintenance::MaintenanceOptions, GitDataStore};
+use std::io::{Read, Write};
+use tempfile::TempDir;
+
+mod util;
+
+#[test]
+fn stream_test() {
+    let tmp_dir = TempDir::new_in(util::TEST_REPOS_DIR).expect("tmp_dir");
+    let tmp_repo_path = tmp_dir.path();
+
+    clone::init(tmp_repo_path, false).expect("clone::init");
+    let store = GitDataStore::new(&tmp_repo_path.to_string_lossy(), "master");
+
+    let data: Vec<u8> = (0..(3 * CHUNK_SIZE + 17))
+        .map(|i| (i % 251) as u8)
+        .collect();
+
+    let blob_id = store.write_blob(&data[..]).expect("write_blob");
+    let doc_path = "artifacts/big.bin";
+    let version = store
+        .put_blob_latest(doc_path, &blob_id, None, None, None)
+        .expect("put_blob_latest");
+
+    let blob_reader = store
+        .read_blob_latest(doc_path)
+        .expect("read_blob_latest")
+        .expect("blob should exist");
+    assert_eq!(blob_reader.size(), data.len());
+    assert_eq!(blob_reader.commit_id(), version);
+
+    let chunks: Vec<Vec<u8>> = blob_reader
+        .chunks(0..blob_reader.size())
+        .expect("chunks")
+        .collect::<Result<_, _>>()
+        .expect("chunk");
+    assert_eq!(chunks.len(), 4);
+    assert!(chunks.iter().all(|chunk| chunk.len() <= CHUNK_SIZE));
+    assert_eq!(chunks.concat(), data);
+
+    let partial: Vec<Vec<u8>> = blob_reader
+        .chunks(100..CHUNK_SIZE + 200)
+        .expect("chunks")
+        .collect::<Result<_, _>>()
+        .expect("chunk");
+    assert_eq!(partial.concat(), &data[100..CHUNK_SIZE + 200]);
+
+    // loose objects are streamed from the object database
+    let mut streamed = Vec::new();
+    blob_reader
+        .for_each_chunk(100..CHUNK_SIZE + 200, |chunk| {
+            assert!(chunk.len() <= CHUNK_SIZE);
+            streamed.extend(chunk);
+            true
+        })
+        .expect("for_each_chunk");
+    assert_eq!(streamed, &data[100..CHUNK_SIZE + 200]);
+    let mut first = Vec::new();
+    blob_reader
+        .for_each_chunk(0..usize::MAX, |chunk| {
+            first = chunk;
+            false
+        })
+        .expect("for_each_chunk");
+    assert_eq!(first, &data[..CHUNK_SIZE]);
+
+    // packed objects are read at once
+    store
+        .maintain(&MaintenanceOptions::default())
+        .expect("maintain");
+    let mut packed = Vec::new();
+    store
+        .read_blob_latest(doc_path)
+        .expect("read_blob_latest")
+        .expect("blob should exist")
+        .for_each_chunk(0..usize::MAX, |chunk| {
+            packed.extend(chunk);
+            true
+        })
+        .expect("for_each_chunk");
+    assert_eq!(packed, data);
+
+    // streamed writes through the BlobWriter are committed like any other put
+    let upload = store.blob_upload().expect("blob_upload");
+    let mut writer = upload.writer().expect("writer");
+    writer.write_all(b"hello ").expect("write");
+    writer.write_all(b"world").expect("write");
+    let small_blob_id = writer.commit().expect("commit").to_string();
+    store
+        .put_blob(
+            &version,
+            "docs/hello",
+            &small_blob_id,
+            None,
+            false,
+            None,
+            None,
+        )
+        .expect("put_blob");
+
+    let entry = store
+        .read_latest("docs/hello")
+        .expect("read_latest")
+        .unwrap();
+    assert_eq!(entry.data.file().unwrap(), "hello world");
+
+    let mut content = String::new();
+    let blob_reader = store
+        .read_blob(&entry.commit_id, "docs/hello")
+        .expect("read_blob")
+        .unwrap();
+    for chunk in blob_reader.chunks(0..usize::MAX).expect("chunks") {
+        (&chunk.expect("chunk")[..])
+            .read_to_string(&mut content)
+            .expect("utf8");
+    }
+    assert_eq!(content, "hello world");
+
+    assert!(store
+        .read_blob(&entry.commit_id, "docs")
+        .expect("read_blob dir")
+        .is_none());
+}
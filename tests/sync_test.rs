@@ -0,0 +1,110 @@
+use git2::Repository;
+use nosql_git::{clone, error::GitDataStoreError, merge::MergeStrategy, GitDataStore};
+use tempfile::TempDir;
+
+mod util;
+
+fn clone_store(remote_path: &str, tmp_dir: &TempDir) -> GitDataStore {
+    Repository::clone(remote_path, tmp_dir.path()).expect("clone");
+    GitDataStore::new(&tmp_dir.path().to_string_lossy(), "master")
+}
+
+#[test]
+fn sync_test() {
+    let remote_dir = TempDir::new_in(util::TEST_REPOS_DIR).expect("remote_dir");
+    clone::init(remote_dir.path(), true).expect("clone::init");
+    let remote_path = remote_dir.path().to_string_lossy().to_string();
+
+    let store_a_dir = TempDir::new_in(util::TEST_REPOS_DIR).expect("store_a_dir");
+    let store_a = clone_store(&remote_path, &store_a_dir);
+    let store_b_dir = TempDir::new_in(util::TEST_REPOS_DIR).expect("store_b_dir");
+    let store_b = clone_store(&remote_path, &store_b_dir);
+
+    store_a
+        .put_latest("docs/doc1", "from a", None, None)
+        .expect("put_latest a");
+    store_a.push("origin", "master").expect("push a");
+
+    // b has diverged from the remote and must pull before pushing
+    store_b
+        .put_latest("docs/doc2", "from b", None, None)
+        .expect("put_latest b");
+    assert!(matches!(
+        store_b.push("origin", "master"),
+        Err(GitDataStoreError::NonFastForward { .. })
+    ));
+    assert!(matches!(
+        store_b.pull("origin", "master", MergeStrategy::FastForwardOnly),
+        Err(GitDataStoreError::NonFastForward { .. })
+    ));
+    store_b
+        .pull("origin", "master", MergeStrategy::Merge)
+        .expect("pull b");
+    store_b.push("origin", "master").expect("push b");
+    assert_eq!(
+        store_b
+            .read_latest("docs/doc1")
+            .expect("read")
+            .unwrap()
+            .data
+            .file(),
+        Some("from a")
+    );
+
+    // a is only behind and fast-forwards to the merge commit of b
+    let head_a = store_a
+        .pull("origin", "master", MergeStrategy::FastForwardOnly)
+        .expect("pull a");
+    assert_eq!(
+        head_a,
+        store_b.read_latest("").expect("read").unwrap().commit_id
+    );
+    assert_eq!(
+        store_a
+            .read_latest("docs/doc2")
+            .expect("read")
+            .unwrap()
+            .data
+            .file(),
+        Some("from b")
+    );
+
+    // conflicting changes are reported, or resolved with ours/theirs
+    store_a
+        .put_latest("docs/doc1", "changed by a", None, None)
+        .expect("put_latest a");
+    store_a.push("origin", "master").expect("push a");
+    store_b
+        .put_latest("docs/doc1", "changed by b", None, None)
+        .expect("put_latest b");
+
+    match store_b.pull("origin", "master", MergeStrategy::Merge) {
+        Err(GitDataStoreError::MergeConflicts(conflicts)) => {
+            assert_eq!(conflicts.len(), 1);
+            assert_eq!(conflicts[0].path, "docs/doc1");
+            assert!(conflicts[0].our_id.is_some());
+            assert!(conflicts[0].their_id.is_some());
+        }
+        result => panic!("expected conflicts, got {:?}", result),
+    }
+    store_b
+        .sync("origin", "master", MergeStrategy::Theirs)
+        .expect("sync b");
+    assert_eq!(
+        store_b
+            .read_latest("docs/doc1")
+            .expect("read")
+            .unwrap()
+            .data
+            .file(),
+        Some("changed by a")
+    );
+
+    store_a
+        .pull("origin", "master", MergeStrategy::FastForwardOnly)
+        .expect("pull a");
+    assert_eq!(
+        store_a.read_latest("").expect("read").unwrap().commit_id,
+        store_b.read_latest("").expect("read").unwrap().commit_id
+    );
+}
@@ -0,0 +1,126 @@
+use nosql_git::{clone, error::GitDataStoreError, purge::PurgeTarget, GitDataStore, Signature};
+use tempfile::TempDir;
+
+mod util;
+
+fn read(store: &GitDataStore, commit_id: &str, path: &str) -> Option<String> {
+    store
+        .read(commit_id, path)
+        .expect("read")
+        .map(|entry| entry.data.file().unwrap().to_string())
+}
+
+#[test]
+fn tag_test() {
+    let tmp_dir = TempDir::new_in(util::TEST_REPOS_DIR).expect("tmp_dir");
+    let tmp_repo_path = tmp_dir.path();
+    clone::init(tmp_repo_path, false).expect("clone::init");
+    let store = GitDataStore::new(&tmp_repo_path.to_string_lossy(), "master");
+
+    let v1 = store
+        .put_latest("a", "a v1", None, None)
+        .expect("put_latest v1");
+    let tagger = Signature {
+        name: "release".to_string(),
+        email: "release@example.com".to_string(),
+    };
+    let tag = store
+        .create_tag_latest("v1", "First release", Some(&tagger))
+        .expect("create_tag_latest");
+    assert_eq!(tag.name, "v1");
+    assert_eq!(tag.commit_id, v1);
+    assert_eq!(tag.message.as_deref(), Some("First release"));
+    assert_eq!(tag.tagger.as_deref(), Some("release <release@example.com>"));
+    assert!(tag.datetime.is_some());
+
+    store
+        .put_latest("a", "a v2", None, None)
+        .expect("put_latest v2");
+    let v0 = store
+        .create_tag("v0", &v1, "Tagged v1 again", None)
+        .expect("create_tag");
+    assert_eq!(v0.commit_id, v1);
+
+    // tags are read like commit ids
+    assert_eq!(read(&store, "v1", "a").as_deref(), Some("a v1"));
+    assert_eq!(store.tag("v1").expect("tag"), Some(tag.clone()));
+    assert_eq!(store.tag("v2").expect("tag"), None);
+    assert_eq!(
+        store
+            .tags()
+            .expect("tags")
+            .iter()
+            .map(|tag| tag.name.as_str())
+            .collect::<Vec<_>>(),
+        vec!["v0", "v1"]
+    );
+
+    // other revisions are still accepted
+    assert_eq!(read(&store, &v1[..7], "a").as_deref(), Some("a v1"));
+    assert_eq!(read(&store, "master~1", "a").as_deref(), Some("a v1"));
+    assert_eq!(read(&store, "master", "a").as_deref(), Some("a v2"));
+
+    // a tag is a parent like a commit id
+    match store.put("v1", "a", "a v3", false, None, None) {
+        Err(GitDataStoreError::ConflictOnWrite { .. }) => {}
+        other => panic!("expected a conflict, got {:?}", other),
+    }
+    store
+        .put("v1", "b", "b v1", false, None, None)
+        .expect("put on tag");
+
+    match store.create_tag_latest("v1", "Again", None) {
+        Err(GitDataStoreError::TagExists(name)) => assert_eq!(name, "v1"),
+        other => panic!("expected an existing tag, got {:?}", other),
+    }
+    for name in &["", "release/1", "bad..name", &v1, &v1[..7], "cafe"] {
+        match store.create_tag_latest(name, "Invalid", None) {
+            Err(GitDataStoreError::InvalidTagName { .. }) => {}
+            other => panic!("expected an invalid tag name {}, got {:?}", name, other),
+        }
+    }
+    match store.create_tag("v3", "unknown", "Unknown", None) {
+        Err(GitDataStoreError::RevNotFound(_)) => {}
+        other => panic!("expected an unknown commit, got {:?}", other),
+    }
+
+    assert_eq!(store.delete_tag("v1").expect("delete_tag"), tag);
+    assert_eq!(store.tag("v1").expect("tag"), None);
+    assert!(store.read("v1", "a").is_err());
+    match store.delete_tag("v1") {
+        Err(GitDataStoreError::RevNotFound(_)) => {}
+        other => panic!("expected an unknown tag, got {:?}", other),
+    }
+    // the tagged commit is kept
+    assert_eq!(read(&store, &v1, "a").as_deref(), Some("a v1"));
+}
+
+#[test]
+fn tag_purge_test() {
+    let tmp_dir = TempDir::new_in(util::TEST_REPOS_DIR).expect("tmp_dir");
+    let tmp_repo_path = tmp_dir.path();
+    clone::init(tmp_repo_path, false).expect("clone::init");
+    let store = GitDataStore::new(&tmp_repo_path.to_string_lossy(), "master");
+
+    store
+        .put_latest("secret", "password", None, None)
+        .expect("put_latest secret");
+    let tagged = store
+        .put_latest("a", "a v1", None, None)
+        .expect("put_latest a");
+    let tag = store
+        .create_tag_latest("v1", "First release", None)
+        .expect("create_tag_latest");
+
+    let report = store
+        .purge(PurgeTarget::Path("secret".to_string()))
+        .expect("purge");
+    let moved = store.tag("v1").expect("tag").expect("tag v1");
+    assert_ne!(moved.commit_id, tagged);
+    assert_eq!(report.tags.get("v1"), Some(&moved.commit_id));
+    assert_eq!(report.commits.get(&tagged), Some(&moved.commit_id));
+    assert_eq!(moved.message, tag.message);
+    assert_eq!(moved.tagger, tag.tagger);
+    assert_eq!(read(&store, "v1", "a").as_deref(), Some("a v1"));
+    assert_eq!(read(&store, "v1", "secret"), None);
+}
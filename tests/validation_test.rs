@@ -0,0 +1,218 @@
+use actix_web::ResponseError;
+use nosql_git::{
+    clone,
+    error::GitDataStoreError,
+    large_object::LargeObjectStore,
+    path_glob::PathGlobs,
+    validation::{
+        Document, JsonSchemaValidator, JsonValidator, MaxSizeValidator, PathRulesValidator,
+        ValidationPipeline, Validator,
+    },
+    GitDataStore,
+};
+use serde_json::json;
+use std::fs;
+use tempfile::TempDir;
+
+mod util;
+
+/// Documents under `locked/` cannot be deleted.
+struct NoDeleteValidator;
+
+impl Validator for NoDeleteValidator {
+    fn name(&self) -> &str {
+        "no-delete"
+    }
+
+    fn validate(&self, document: &Document<'_>) -> Result<(), String> {
+        if document.content.is_none() && document.path.starts_with("locked/") {
+            Err("locked documents cannot be deleted".to_string())
+        } else {
+            Ok(())
+        }
+    }
+}
+
+fn failed_validators(err: GitDataStoreError) -> Vec<(String, String)> {
+    assert_eq!(err.status_code().as_u16(), 422);
+    match err {
+        GitDataStoreError::ValidationFailed(failures) => failures
+            .into_iter()
+            .map(|failure| (failure.path, failure.validator))
+            .collect(),
+        err => panic!("expected ValidationFailed, got {:?}", err),
+    }
+}
+
+#[test]
+fn validation_test() {
+    let tmp_dir = TempDir::new_in(util::TEST_REPOS_DIR).expect("tmp_dir");
+    let tmp_repo_path = tmp_dir.path();
+
+    clone::init(tmp_repo_path, false).expect("clone::init");
+    let schema = json!({
+        "type": "object",
+        "required": ["name"],
+        "properties": { "name": { "type": "string" } }
+    });
+    let validation = ValidationPipeline::new()
+        .with(JsonValidator::new(
+            PathGlobs::new(&["**/*.json"]).expect("globs"),
+        ))
+        .with(
+            JsonSchemaValidator::new(
+                PathGlobs::new(&["users/*.json"]).expect("globs"),
+                "user",
+                &schema,
+            )
+            .expect("schema"),
+        )
+        .with(MaxSizeValidator::new(PathGlobs::all(), 64))
+        .with(PathRulesValidator::new(Some("^[a-z0-9/_.-]+$"), Some(40)).expect("path rules"))
+        .with(NoDeleteValidator);
+    let store =
+        GitDataStore::new(&tmp_repo_path.to_string_lossy(), "master").with_validation(validation);
+
+    let version = store
+        .put_latest("users/alice.json", r#"{"name": "alice"}"#, None, None)
+        .expect("valid document");
+    store
+        .put_latest("locked/doc", "not json, not under a json glob", None, None)
+        .expect("documents outside of the globs are not checked");
+
+    assert_eq!(
+        failed_validators(
+            store
+                .put_latest("users/bob.json", r#"{"name": 42"#, None, None)
+                .unwrap_err()
+        ),
+        vec![
+            ("users/bob.json".to_string(), "json".to_string()),
+            ("users/bob.json".to_string(), "json-schema user".to_string())
+        ]
+    );
+    assert_eq!(
+        failed_validators(
+            store
+                .put(
+                    &version,
+                    "users/bob.json",
+                    r#"{"age": 42}"#,
+                    false,
+                    None,
+                    None
+                )
+                .unwrap_err()
+        ),
+        vec![("users/bob.json".to_string(), "json-schema user".to_string())]
+    );
+    assert_eq!(
+        failed_validators(
+            store
+                .put_latest("docs/big", &"x".repeat(65), None, None)
+                .unwrap_err()
+        ),
+        vec![("docs/big".to_string(), "max-size".to_string())]
+    );
+    assert_eq!(
+        failed_validators(
+            store
+                .put_latest("Docs/Upper", "data", None, None)
+                .unwrap_err()
+        ),
+        vec![("Docs/Upper".to_string(), "path-rules".to_string())]
+    );
+    assert_eq!(
+        failed_validators(store.delete_latest("locked/doc", None, None).unwrap_err()),
+        vec![("locked/doc".to_string(), "no-delete".to_string())]
+    );
+
+    // nothing was committed by the rejected writes
+    assert_eq!(
+        store.head().expect("head").message.as_deref(),
+        Some("Updated locked/doc")
+    );
+    assert!(store.read_latest("users/bob.json").expect("read").is_none());
+
+    // streamed writes are validated too
+    let blob_id = store.write_blob(&b"{}"[..]).expect("write_blob");
+    assert_eq!(
+        failed_validators(
+            store
+                .put_blob_latest("users/carol.json", &blob_id, None, None, None)
+                .unwrap_err()
+        ),
+        vec![(
+            "users/carol.json".to_string(),
+            "json-schema user".to_string()
+        )]
+    );
+
+    // built-in validators from a configuration file
+    let config_dir = tmp_dir.path().join("config");
+    fs::create_dir_all(config_dir.join("schemas")).expect("create_dir_all");
+    fs::write(
+        config_dir.join("schemas/user.json"),
+        serde_json::to_string(&schema).unwrap(),
+    )
+    .expect("write schema");
+    fs::write(
+        config_dir.join("validation.json"),
+        r#"{"json": ["**/*.json"], "schemas": [{"paths": ["users/*.json"], "schema": "schemas/user.json"}], "max_size": 64}"#,
+    )
+    .expect("write config");
+    let validation =
+        ValidationPipeline::load(config_dir.join("validation.json")).expect("load validation");
+    let store =
+        GitDataStore::new(&tmp_repo_path.to_string_lossy(), "master").with_validation(validation);
+    assert_eq!(
+        failed_validators(
+            store
+                .put_latest("users/dave.json", "{}", None, None)
+                .unwrap_err()
+        ),
+        vec![(
+            "users/dave.json".to_string(),
+            format!(
+                "json-schema {}",
+                config_dir.join("schemas/user.json").display()
+            )
+        )]
+    );
+}
+
+#[test]
+fn validation_large_object_test() {
+    let tmp_dir = TempDir::new_in(util::TEST_REPOS_DIR).expect("tmp_dir");
+    let tmp_repo_path = tmp_dir.path();
+    let large_objects_dir = TempDir::new_in(util::TEST_REPOS_DIR).expect("large_objects_dir");
+    clone::init(tmp_repo_path, false).expect("clone::init");
+    let validation = ValidationPipeline::new().with(MaxSizeValidator::new(PathGlobs::all(), 64));
+    let store = GitDataStore::new(&tmp_repo_path.to_string_lossy(), "master")
+        .with_large_objects(LargeObjectStore::new(large_objects_dir.path(), 16))
+        .with_validation(validation);
+
+    // the size of a large document is the size of its content, not of its pointer
+    store
+        .put_latest("docs/large", &"x".repeat(64), None, None)
+        .expect("large document within the maximum");
+    assert_eq!(
+        failed_validators(
+            store
+                .put_latest("docs/big", &"x".repeat(65), None, None)
+                .unwrap_err()
+        ),
+        vec![("docs/big".to_string(), "max-size".to_string())]
+    );
+    let blob_id = store
+        .write_blob("x".repeat(100).as_bytes())
+        .expect("write_blob");
+    assert_eq!(
+        failed_validators(
+            store
+                .put_blob_latest("docs/streamed", &blob_id, None, None, None)
+                .unwrap_err()
+        ),
+        vec![("docs/streamed".to_string(), "max-size".to_string())]
+    );
+}
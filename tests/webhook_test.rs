@@ -0,0 +1,156 @@
+use nosql_git::{
+    clone,
+    webhook::{self, Webhook, WebhookConfig, COMMIT_HEADER, SIGNATURE_HEADER},
+    GitDataStore,
+};
+use serde_json::Value;
+use slog::{o, Discard, Logger};
+use std::{
+    collections::HashMap,
+    fs,
+    io::{BufRead, BufReader, Read, Write},
+    net::TcpListener,
+    sync::{mpsc, Arc},
+    thread,
+    time::Duration,
+};
+use tempfile::TempDir;
+
+mod util;
+
+struct Request {
+    headers: HashMap<String, String>,
+    body: Vec<u8>,
+}
+
+/// Answers the first request with 500 and the next ones with 200.
+fn spawn_receiver() -> (String, mpsc::Receiver<Request>) {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("bind");
+    let url = format!("http://{}/hook", listener.local_addr().expect("local_addr"));
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for (index, stream) in listener.incoming().enumerate() {
+            let mut stream = stream.expect("stream");
+            let mut reader = BufReader::new(stream.try_clone().expect("try_clone"));
+            let mut headers = HashMap::new();
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).expect("read_line");
+                let line = line.trim_end();
+                if line.is_empty() {
+                    break;
+                }
+                if let Some((name, value)) = line.split_once(": ") {
+                    headers.insert(name.to_lowercase(), value.to_string());
+                }
+            }
+            let length = headers["content-length"].parse().expect("content-length");
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).expect("body");
+
+            let status = if index == 0 {
+                "500 Internal Server Error"
+            } else {
+                "200 OK"
+            };
+            write!(stream, "HTTP/1.1 {}\r\ncontent-length: 0\r\n\r\n", status).expect("write");
+            sender.send(Request { headers, body }).expect("send");
+        }
+    });
+    (url, receiver)
+}
+
+#[test]
+fn webhook_test() {
+    let tmp_dir = TempDir::new_in(util::TEST_REPOS_DIR).expect("tmp_dir");
+    let tmp_repo_path = tmp_dir.path().join("repo");
+
+    clone::init(&tmp_repo_path, false).expect("clone::init");
+    let store = Arc::new(GitDataStore::new(
+        &tmp_repo_path.to_string_lossy(),
+        "master",
+    ));
+
+    let (url, requests) = spawn_receiver();
+    let delivery_log = tmp_dir.path().join("deliveries.jsonl");
+    let config = WebhookConfig {
+        webhooks: vec![Webhook {
+            url: url.clone(),
+            secret: Some("secret".to_string()),
+            prefixes: vec!["docs".to_string()],
+        }],
+        delivery_log: Some(delivery_log.clone()),
+        max_attempts: 3,
+        initial_backoff_ms: 10,
+        timeout_ms: 5000,
+    };
+    let log = Logger::root(Discard, o!());
+    webhook::spawn(store.clone(), config, Duration::from_millis(50), log).expect("spawn");
+
+    store
+        .put_latest("other/doc1", "ignored", None, None)
+        .expect("put_latest");
+    let commit_id = store
+        .put_latest("docs/doc1", "v1", None, None)
+        .expect("put_latest");
+
+    // the first attempt fails and is retried
+    let timeout = Duration::from_secs(10);
+    let failed = requests.recv_timeout(timeout).expect("first attempt");
+    let delivered = requests.recv_timeout(timeout).expect("second attempt");
+    assert_eq!(failed.body, delivered.body);
+    assert_eq!(delivered.headers[&COMMIT_HEADER.to_lowercase()], commit_id);
+    assert_eq!(
+        delivered.headers[&SIGNATURE_HEADER.to_lowercase()],
+        webhook::sign("secret", &delivered.body)
+    );
+
+    let payload: Value = serde_json::from_slice(&delivered.body).expect("payload");
+    assert_eq!(payload["branch"], "master");
+    assert_eq!(payload["commit"]["commit_id"], commit_id.as_str());
+    assert_eq!(
+        payload["commit"]["paths"],
+        serde_json::json!([{ "path": "docs/doc1", "status": "added" }])
+    );
+    // commits outside of the prefixes are not delivered
+    assert!(requests.recv_timeout(Duration::from_millis(300)).is_err());
+
+    // attempts are logged once the receiver answered
+    let mut deliveries: Vec<Value> = Vec::new();
+    for _ in 0..100 {
+        deliveries = fs::read_to_string(&delivery_log)
+            .unwrap_or_default()
+            .lines()
+            .map(|line| serde_json::from_str(line).expect("delivery"))
+            .collect();
+        if deliveries.len() >= 2 {
+            break;
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+    assert_eq!(deliveries.len(), 2);
+    assert_eq!(deliveries[0]["attempt"], 1);
+    assert_eq!(deliveries[0]["delivered"], false);
+    assert_eq!(deliveries[0]["status"], 500);
+    assert_eq!(deliveries[1]["attempt"], 2);
+    assert_eq!(deliveries[1]["delivered"], true);
+    assert_eq!(deliveries[1]["url"], url.as_str());
+}
+
+#[test]
+fn webhook_config_debug_test() {
+    let config = WebhookConfig {
+        webhooks: vec![Webhook {
+            url: "https://example.com/hook".to_string(),
+            secret: Some("s3cr3t".to_string()),
+            prefixes: Vec::new(),
+        }],
+        delivery_log: None,
+        max_attempts: 3,
+        initial_backoff_ms: 10,
+        timeout_ms: 5000,
+    };
+    let debug = format!("{:?}", config);
+    assert!(debug.contains("https://example.com/hook"));
+    assert!(!debug.contains("s3cr3t"));
+}